@@ -0,0 +1,34 @@
+use std::time::Instant;
+
+use tiny_solver::sparse::LinearSolverType;
+use tiny_solver::{
+    LevenbergMarquardtOptimizer, OptimizerOptions, helper::read_bal, optimizer::Optimizer,
+};
+
+/// cargo run --release --example bal -- problem-49-7776-pre.txt
+/// Datasets: https://grail.cs.washington.edu/projects/bal/
+fn main() {
+    // init logger
+    env_logger::init();
+
+    let filename = std::env::args()
+        .nth(1)
+        .unwrap_or("tests/data/bal_synthetic.txt".to_string());
+    let (problem, init_values) = read_bal(&filename).unwrap();
+    println!(
+        "{} variables, {} residuals",
+        init_values.len(),
+        problem.total_residual_dimension
+    );
+
+    let options = OptimizerOptions {
+        linear_solver_type: LinearSolverType::SparseSchur,
+        ..Default::default()
+    };
+    let optimizer = LevenbergMarquardtOptimizer::default();
+    let start = Instant::now();
+    let (_, summary) = optimizer.optimize_with_summary(&problem, &init_values, Some(options));
+    let duration = start.elapsed();
+    println!("{}", summary);
+    println!("Time elapsed in total is: {:?}", duration);
+}
@@ -9,7 +9,7 @@ fn main() {
     // init logger
     env_logger::init();
 
-    let (problem, init_values) = read_g2o("tests/data/parking-garage.g2o");
+    let (problem, init_values) = read_g2o("tests/data/parking-garage.g2o").unwrap();
     let init_points: Vec<(f64, f64)> = init_values.values().map(|v| (v[4], v[5])).collect();
     let root_drawing_area =
         BitMapBackend::new("parking_garage.png", (1024, 1024)).into_drawing_area();
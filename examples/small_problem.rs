@@ -1,11 +1,28 @@
 use std::collections::HashMap;
 
-use tiny_solver::{self, Optimizer, na};
+use tiny_solver::{self, Optimizer, factor, na};
 
-struct CustomFactor;
-// define your own residual function and the jacobian will be auto generated
-impl<T: na::RealField> tiny_solver::factors::Factor<T> for CustomFactor {
-    fn residual_func(&self, params: &[nalgebra::DVector<T>]) -> nalgebra::DVector<T> {
+fn main() {
+    // init logger, `export RUST_LOG=trace` to see more log
+    env_logger::init();
+
+    // init problem (factor graph)
+    let mut problem = tiny_solver::Problem::new();
+
+    // add residual blocks (factors)
+    // add residual x needs to be close to 3.0
+    problem
+        .add_residual_block(
+            1,
+            &["x"],
+            Box::new(tiny_solver::factors::PriorFactor {
+                v: na::dvector![3.0],
+            }),
+            None,
+        )
+        .unwrap();
+    // add custom residual for x and yz, the jacobian will be auto generated
+    let custom_factor = factor!(|params| {
         let x = &params[0][0];
         let y = &params[1][0];
         let z = &params[1][1];
@@ -16,28 +33,10 @@ impl<T: na::RealField> tiny_solver::factors::Factor<T> for CustomFactor {
                 + z.clone() * T::from_f64(4.0).unwrap(),
             y.clone() * z.clone()
         ]
-    }
-}
-
-fn main() {
-    // init logger, `export RUST_LOG=trace` to see more log
-    env_logger::init();
-
-    // init problem (factor graph)
-    let mut problem = tiny_solver::Problem::new();
-
-    // add residual blocks (factors)
-    // add residual x needs to be close to 3.0
-    problem.add_residual_block(
-        1,
-        &["x"],
-        Box::new(tiny_solver::factors::PriorFactor {
-            v: na::dvector![3.0],
-        }),
-        None,
-    );
-    // add custom residual for x and yz
-    problem.add_residual_block(2, &["x", "yz"], Box::new(CustomFactor), None);
+    });
+    problem
+        .add_residual_block(2, &["x", "yz"], Box::new(custom_factor), None)
+        .unwrap();
 
     // the initial values for x is 0.7 and yz is [-30.2, 123.4]
     let initial_values = HashMap::<String, na::DVector<f64>>::from([
@@ -49,7 +48,8 @@ fn main() {
     let optimizer = tiny_solver::GaussNewtonOptimizer {};
 
     // optimize
-    let result = optimizer.optimize(&problem, &initial_values, None);
+    let (result, summary) = optimizer.optimize_with_summary(&problem, &initial_values, None);
+    println!("{}", summary);
 
     // result
     for (k, v) in result.unwrap() {
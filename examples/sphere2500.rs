@@ -9,7 +9,7 @@ fn main() {
     // init logger
     env_logger::init();
 
-    let (problem, init_values) = read_g2o("tests/data/sphere2500.g2o");
+    let (problem, init_values) = read_g2o("tests/data/sphere2500.g2o").unwrap();
     let init_points: Vec<(f64, f64)> = init_values.values().map(|v| (v[4], v[6])).collect();
     let root_drawing_area =
         BitMapBackend::new("sphere2500_rs.png", (1024, 1024)).into_drawing_area();
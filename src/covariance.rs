@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+
+use faer::dyn_stack::{MemBuffer, MemStack, StackReq};
+use faer::linalg::solvers::Solve;
+use faer::sparse::Triplet;
+use faer::sparse::linalg::{colamd, qr, solvers, triangular_solve};
+use faer_ext::{IntoFaer, IntoNalgebra};
+use nalgebra as na;
+
+use crate::error::TinySolverError;
+use crate::parameter_block::ParameterBlock;
+use crate::problem::Problem;
+use crate::solver_context::SolverContext;
+use crate::sparse::normal_matrix;
+use crate::variable_key::{VariableKey, key_to_string};
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub enum CovarianceAlgorithmType {
+    /// Factor J^T * J with a sparse Cholesky and solve only for the columns
+    /// needed by the requested blocks.
+    #[default]
+    SparseCholesky,
+    /// Sparse QR factorization of J, more accurate than `SparseCholesky`
+    /// on ill-conditioned problems as the condition number is not squared.
+    SparseQR,
+    /// Dense SVD of J. Slow, but it can handle rank deficient problems by
+    /// truncating small singular values (pseudo-inverse).
+    DenseSvd,
+}
+
+#[derive(Clone, Debug)]
+pub struct CovarianceOptions {
+    pub algorithm_type: CovarianceAlgorithmType,
+    /// Only used by `DenseSvd`. Singular values with `(s / s_max)^2` below
+    /// this threshold are treated as zero.
+    pub min_reciprocal_condition_number: f64,
+}
+
+impl Default for CovarianceOptions {
+    fn default() -> Self {
+        CovarianceOptions {
+            algorithm_type: CovarianceAlgorithmType::SparseCholesky,
+            min_reciprocal_condition_number: 1e-14,
+        }
+    }
+}
+
+/// Covariance of the estimated variables in the tangent space, computed as
+/// (J^T * J)^-1 at the given values.
+///
+/// Variables with a manifold have blocks of size `tangent_size`. Fixed
+/// components of Euclidean variables have zero covariance.
+pub struct Covariance<K = String> {
+    options: CovarianceOptions,
+    covariance_blocks: HashMap<(K, K), na::DMatrix<f64>>,
+}
+
+impl<K: VariableKey> Default for Covariance<K> {
+    fn default() -> Self {
+        Covariance::new(CovarianceOptions::default())
+    }
+}
+
+impl<K: VariableKey> Covariance<K> {
+    pub fn new(options: CovarianceOptions) -> Self {
+        Covariance {
+            options,
+            covariance_blocks: HashMap::new(),
+        }
+    }
+
+    /// Computes the covariance blocks for every `(var_a, var_b)` pair.
+    /// Fails if a variable is not in the problem or the values, or if the
+    /// Jacobian is rank deficient (except for `DenseSvd`).
+    pub fn compute<Q: Into<K> + Clone>(
+        &mut self,
+        covariance_blocks: &[(Q, Q)],
+        problem: &Problem<K>,
+        values: &HashMap<K, na::DVector<f64>>,
+    ) -> Result<(), TinySolverError> {
+        self.compute_with_context(
+            covariance_blocks,
+            problem,
+            values,
+            &mut SolverContext::new(),
+        )
+    }
+
+    /// Same as `compute` but the columns and the Jacobian pattern are taken
+    /// from `context`, e.g. the one of the solve that returned `values`.
+    pub fn compute_with_context<Q: Into<K> + Clone>(
+        &mut self,
+        covariance_blocks: &[(Q, Q)],
+        problem: &Problem<K>,
+        values: &HashMap<K, na::DVector<f64>>,
+        context: &mut SolverContext<K>,
+    ) -> Result<(), TinySolverError> {
+        self.covariance_blocks.clear();
+
+        let parameter_blocks = problem.initialize_parameter_blocks(values);
+        let mut requested_blocks = Vec::with_capacity(covariance_blocks.len());
+        for (var_a, var_b) in covariance_blocks {
+            let (var_a, var_b): (K, K) = (var_a.clone().into(), var_b.clone().into());
+            let mut idx_pair = [0; 2];
+            for (i, var) in [&var_a, &var_b].into_iter().enumerate() {
+                match problem.get_variable_idx(var) {
+                    Some(idx) if parameter_blocks[idx].is_some() => idx_pair[i] = idx,
+                    _ => {
+                        return Err(TinySolverError::UnknownVariable {
+                            key: key_to_string(&var),
+                        });
+                    }
+                }
+            }
+            requested_blocks.push(((var_a, var_b), idx_pair));
+        }
+
+        let structure = context.structure(problem, &parameter_blocks);
+        let variable_idx_to_col_idx = &structure.variable_idx_to_col_idx;
+        let (_, jac) = problem.compute_residual_and_jacobian(
+            &parameter_blocks,
+            variable_idx_to_col_idx,
+            &structure.symbolic_structure,
+        )?;
+        let param = |idx: usize| parameter_blocks[idx].as_ref().unwrap();
+
+        // only solve for the columns of the right hand side variables
+        let mut rhs_variables: Vec<usize> = requested_blocks.iter().map(|(_, [_, b])| *b).collect();
+        rhs_variables.sort();
+        rhs_variables.dedup();
+        let mut rhs_col_start = HashMap::new();
+        let mut num_rhs_cols = 0;
+        for &var in &rhs_variables {
+            rhs_col_start.insert(var, num_rhs_cols);
+            num_rhs_cols += param(var).free_tangent_size();
+        }
+        let mut rhs = faer::Mat::<f64>::zeros(structure.total_variable_dimension, num_rhs_cols);
+        for &var in &rhs_variables {
+            let col_idx = variable_idx_to_col_idx[var];
+            for i in 0..param(var).free_tangent_size() {
+                rhs[(col_idx + i, rhs_col_start[&var] + i)] = 1.0;
+            }
+        }
+
+        let inv_columns = self.solve_columns(&jac, rhs)?;
+        if inv_columns
+            .col_iter()
+            .any(|c| c.iter().any(|v| !v.is_finite()))
+        {
+            log::debug!("non-finite covariance, the problem might be rank deficient");
+            return Err(TinySolverError::NotPositiveDefinite);
+        }
+        let inv_columns = inv_columns.as_ref().into_nalgebra();
+
+        for (keys, [var_a, var_b]) in requested_blocks {
+            let param_a = param(var_a);
+            let param_b = param(var_b);
+            let reduced = inv_columns
+                .view(
+                    (variable_idx_to_col_idx[var_a], rhs_col_start[&var_b]),
+                    (param_a.free_tangent_size(), param_b.free_tangent_size()),
+                )
+                .clone_owned();
+            let free_a = free_tangent_indexes(param_a);
+            let free_b = free_tangent_indexes(param_b);
+            let mut block = na::DMatrix::zeros(param_a.tangent_size(), param_b.tangent_size());
+            for (r, &ra) in free_a.iter().enumerate() {
+                for (c, &cb) in free_b.iter().enumerate() {
+                    block[(ra, cb)] = reduced[(r, c)];
+                }
+            }
+            self.covariance_blocks.insert(keys, block);
+        }
+        Ok(())
+    }
+
+    /// Returns the cross covariance of `var_a` and `var_b` if it (or its
+    /// transpose) was requested in `compute`.
+    pub fn get_covariance_block<Q: Into<K>>(&self, var_a: Q, var_b: Q) -> Option<na::DMatrix<f64>> {
+        let key = (var_a.into(), var_b.into());
+        if let Some(block) = self.covariance_blocks.get(&key) {
+            Some(block.clone())
+        } else {
+            self.covariance_blocks
+                .get(&(key.1, key.0))
+                .map(|block| block.transpose())
+        }
+    }
+
+    /// Stacks the blocks of `variables` into one matrix. Every pair of
+    /// variables needs to be computed beforehand.
+    pub fn get_covariance_matrix<Q: Into<K> + Clone>(
+        &self,
+        variables: &[Q],
+    ) -> Option<na::DMatrix<f64>> {
+        let mut blocks = Vec::new();
+        for var_a in variables {
+            let mut row = Vec::new();
+            for var_b in variables {
+                row.push(self.get_covariance_block(var_a.clone(), var_b.clone())?);
+            }
+            blocks.push(row);
+        }
+        let sizes: Vec<usize> = blocks.iter().map(|row| row[0].nrows()).collect();
+        let total = sizes.iter().sum();
+        let mut mat = na::DMatrix::zeros(total, total);
+        let mut row_start = 0;
+        for (i, row) in blocks.iter().enumerate() {
+            let mut col_start = 0;
+            for (j, block) in row.iter().enumerate() {
+                mat.view_mut((row_start, col_start), (sizes[i], sizes[j]))
+                    .copy_from(block);
+                col_start += sizes[j];
+            }
+            row_start += sizes[i];
+        }
+        Some(mat)
+    }
+
+    /// Solves J^T * J * x = rhs.
+    fn solve_columns(
+        &self,
+        jac: &faer::sparse::SparseColMat<usize, f64>,
+        rhs: faer::Mat<f64>,
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        match self.options.algorithm_type {
+            CovarianceAlgorithmType::SparseCholesky => {
+                let jtj = normal_matrix(jac);
+                let sym = solvers::SymbolicLlt::try_new(jtj.symbolic(), faer::Side::Lower)
+                    .map_err(|e| TinySolverError::LinearSolverFailed(format!("{:?}", e)))?;
+                let llt = solvers::Llt::try_new_with_symbolic(sym, jtj.as_ref(), faer::Side::Lower)
+                    .map_err(|_| TinySolverError::NotPositiveDefinite)?;
+                Ok(llt.solve(&rhs))
+            }
+            CovarianceAlgorithmType::SparseQR => solve_with_qr(jac, rhs),
+            CovarianceAlgorithmType::DenseSvd => {
+                // J = U * S * V^T, so (J^T * J)^+ = V * S^-2 * V^T
+                let dense = jac.to_dense();
+                let svd = dense.as_ref().into_nalgebra().svd(false, true);
+                let v_t = svd.v_t.unwrap();
+                let max_singular_value = svd.singular_values.max();
+                if max_singular_value <= 0.0 {
+                    return Err(TinySolverError::NotPositiveDefinite);
+                }
+                let min_ratio = self.options.min_reciprocal_condition_number.sqrt();
+                let inv_squared = svd.singular_values.map(|v| {
+                    if v / max_singular_value < min_ratio {
+                        0.0
+                    } else {
+                        1.0 / (v * v)
+                    }
+                });
+                let rhs_na = rhs.as_ref().into_nalgebra();
+                let result =
+                    v_t.transpose() * na::DMatrix::from_diagonal(&inv_squared) * (&v_t * rhs_na);
+                Ok(result.view_range(.., ..).into_faer().to_owned())
+            }
+        }
+    }
+}
+
+/// Solves J^T * J * x = rhs with the R factor of J * P = Q * R, so that the
+/// condition number of J is not squared as in a factorization of J^T * J.
+/// https://github.com/ceres-solver/ceres-solver/blob/master/internal/ceres/covariance_impl.cc
+fn solve_with_qr(
+    jac: &faer::sparse::SparseColMat<usize, f64>,
+    rhs: faer::Mat<f64>,
+) -> Result<faer::Mat<f64>, TinySolverError> {
+    let (m, n) = jac.shape();
+    if m < n {
+        return Err(TinySolverError::NotPositiveDefinite);
+    }
+    let faer_error =
+        |e: faer::sparse::FaerError| TinySolverError::LinearSolverFailed(format!("{:?}", e));
+    let jac_t = jac
+        .as_ref()
+        .transpose()
+        .to_col_major()
+        .map_err(faer_error)?;
+
+    // the steps of faer's symbolic QR, which does not expose R
+    let mut perm = vec![0usize; n];
+    let mut perm_inv = vec![0usize; n];
+    let mut etree = vec![0usize; n];
+    let mut post = vec![0usize; n];
+    let mut col_counts = vec![0usize; n];
+    let mut min_col = vec![0usize; m];
+    let mut mem = MemBuffer::new(StackReq::any_of(&[
+        colamd::order_scratch::<usize>(m, n, jac.compute_nnz()),
+        qr::col_etree_scratch::<usize>(m, n),
+        qr::postorder_scratch::<usize>(n),
+        qr::column_counts_aat_scratch::<usize>(m, n),
+        qr::simplicial::factorize_simplicial_symbolic_qr_scratch::<usize>(m, n),
+    ]));
+    let stack = MemStack::new(&mut mem);
+    colamd::order(
+        &mut perm,
+        &mut perm_inv,
+        jac.symbolic(),
+        Default::default(),
+        stack,
+    )
+    .map_err(faer_error)?;
+    let col_perm = faer::perm::PermRef::new_checked(&perm, &perm_inv, n);
+    let etree = qr::col_etree(jac.symbolic(), Some(col_perm), &mut etree, stack);
+    qr::postorder(&mut post, etree, stack);
+    qr::column_counts_ata(
+        &mut col_counts,
+        &mut min_col,
+        jac_t.symbolic(),
+        Some(col_perm),
+        etree,
+        &post,
+        stack,
+    );
+    let symbolic =
+        qr::simplicial::factorize_simplicial_symbolic_qr(&min_col, etree, &col_counts, stack)
+            .map_err(faer_error)?;
+
+    let mut r_col_ptr = vec![0usize; n + 1];
+    let mut r_row_idx = vec![0usize; symbolic.len_r()];
+    let mut r_val = vec![0.0; symbolic.len_r()];
+    let mut householder_col_ptr = vec![0usize; n + 1];
+    let mut householder_row_idx = vec![0usize; symbolic.len_householder()];
+    let mut householder_val = vec![0.0; symbolic.len_householder()];
+    let mut tau_val = vec![0.0; n];
+    let mut mem = MemBuffer::new(qr::simplicial::factorize_simplicial_numeric_qr_scratch::<
+        usize,
+        f64,
+    >(&symbolic));
+    let factors = qr::simplicial::factorize_simplicial_numeric_qr_unsorted(
+        &mut r_col_ptr,
+        &mut r_row_idx,
+        &mut r_val,
+        &mut householder_col_ptr,
+        &mut householder_row_idx,
+        &mut householder_val,
+        &mut tau_val,
+        jac.as_ref(),
+        Some(col_perm),
+        &symbolic,
+        MemStack::new(&mut mem),
+    );
+    // sorted rows put the diagonal last in every column of R and first in
+    // every column of R^T, as the triangular solves expect
+    let r = factors.R();
+    let triplets: Vec<Triplet<usize, usize, f64>> = (0..n)
+        .flat_map(|c| {
+            r.row_idx_of_col(c)
+                .zip(r.val_of_col(c))
+                .map(move |(row, &val)| Triplet::new(row, c, val))
+        })
+        .collect();
+    let r = faer::sparse::SparseColMat::try_new_from_triplets(n, n, &triplets)
+        .map_err(|e| TinySolverError::LinearSolverFailed(format!("{:?}", e)))?;
+    let diagonal: Vec<f64> = (0..n)
+        .map(|c| {
+            r.val_of_col(c)
+                .last()
+                .filter(|_| r.row_idx_of_col(c).last() == Some(c))
+                .map_or(0.0, |v| v.abs())
+        })
+        .collect();
+    let max_diagonal = diagonal.iter().copied().fold(0.0, f64::max);
+    if diagonal.iter().any(|&d| d <= f64::EPSILON * max_diagonal) {
+        log::debug!("R is singular, the problem might be rank deficient");
+        return Err(TinySolverError::NotPositiveDefinite);
+    }
+    let r_t = r.as_ref().transpose().to_col_major().map_err(faer_error)?;
+
+    // J^T * J = P * R^T * R * P^T
+    let mut x = faer::Mat::<f64>::zeros(n, rhs.ncols());
+    for j in 0..rhs.ncols() {
+        for i in 0..n {
+            x[(i, j)] = rhs[(perm[i], j)];
+        }
+    }
+    let par = faer::get_global_parallelism();
+    triangular_solve::solve_lower_triangular_in_place(
+        r_t.as_ref(),
+        faer::Conj::No,
+        x.as_mut(),
+        par,
+    );
+    triangular_solve::solve_upper_triangular_in_place(r.as_ref(), faer::Conj::No, x.as_mut(), par);
+    let mut result = faer::Mat::<f64>::zeros(n, rhs.ncols());
+    for j in 0..rhs.ncols() {
+        for i in 0..n {
+            result[(perm[i], j)] = x[(i, j)];
+        }
+    }
+    Ok(result)
+}
+
+fn free_tangent_indexes(param: &ParameterBlock) -> Vec<usize> {
+    (0..param.tangent_size())
+        .filter(|i| !param.fixed_variables.contains(i))
+        .collect()
+}
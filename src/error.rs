@@ -0,0 +1,133 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum TinySolverError {
+    /// A residual block uses a variable without an initial value.
+    MissingVariable {
+        key: String,
+        residual_block_id: usize,
+    },
+    /// The factor returned a residual of a different size than `dim_residual`.
+    DimensionMismatch {
+        residual_block_id: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// The value of a variable on a manifold has a different size than the
+    /// ambient size of the manifold.
+    VariableSizeMismatch {
+        key: String,
+        expected: usize,
+        actual: usize,
+    },
+    /// A fixed index is not a tangent index of the variable, see
+    /// `Problem::fix_variable`.
+    FixedIndexOutOfRange {
+        key: String,
+        index: usize,
+        tangent_size: usize,
+    },
+    /// The variable is not in the problem or has no value, see
+    /// `Covariance::compute`.
+    UnknownVariable {
+        key: String,
+    },
+    /// All the problems found by `Problem::validate`.
+    InvalidProblem(Vec<TinySolverError>),
+    /// The residual block can not be added to the problem, e.g. it has no variables.
+    InvalidResidualBlock(String),
+    /// Cholesky factorization failed, the problem might be rank deficient.
+    NotPositiveDefinite,
+    LinearSolverFailed(String),
+    /// The cost became NaN or infinite.
+    NonFiniteCost,
+    InvalidLossParameter(String),
+    /// `line` starts from 1.
+    ParseError {
+        line: usize,
+        message: String,
+    },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for TinySolverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TinySolverError::MissingVariable {
+                key,
+                residual_block_id,
+            } => write!(
+                f,
+                "variable {} of residual block {} has no initial value",
+                key, residual_block_id
+            ),
+            TinySolverError::DimensionMismatch {
+                residual_block_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "residual block {} expects a residual of size {} but the factor returned {}",
+                residual_block_id, expected, actual
+            ),
+            TinySolverError::VariableSizeMismatch {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "variable {} has size {} but its manifold expects {}",
+                key, actual, expected
+            ),
+            TinySolverError::FixedIndexOutOfRange {
+                key,
+                index,
+                tangent_size,
+            } => write!(
+                f,
+                "variable {} has fixed index {} but its tangent size is {}",
+                key, index, tangent_size
+            ),
+            TinySolverError::UnknownVariable { key } => {
+                write!(f, "variable {} is not in the problem or has no value", key)
+            }
+            TinySolverError::InvalidProblem(errors) => {
+                write!(f, "found {} problems:", errors.len())?;
+                for err in errors {
+                    write!(f, "\n  {}", err)?;
+                }
+                Ok(())
+            }
+            TinySolverError::InvalidResidualBlock(msg) => {
+                write!(f, "invalid residual block: {}", msg)
+            }
+            TinySolverError::NotPositiveDefinite => {
+                write!(f, "the linear system is not positive definite")
+            }
+            TinySolverError::LinearSolverFailed(msg) => write!(f, "linear solver failed: {}", msg),
+            TinySolverError::NonFiniteCost => write!(f, "the cost is not finite"),
+            TinySolverError::InvalidLossParameter(msg) => {
+                write!(f, "invalid loss parameter: {}", msg)
+            }
+            TinySolverError::ParseError { line, message } => {
+                write!(f, "parse error at line {}: {}", line, message)
+            }
+            TinySolverError::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TinySolverError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TinySolverError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TinySolverError {
+    fn from(err: std::io::Error) -> Self {
+        TinySolverError::Io(err)
+    }
+}
@@ -1,38 +1,301 @@
 pub use nalgebra as na;
+use num_dual::{DualDVec64, DualSVec64};
 
+use crate::manifold::se2::SE2;
 use crate::manifold::se3::SE3;
+use crate::manifold::{AutoDiffManifold, StaticDualManifold};
+use crate::parameter_block::ParameterBlock;
+
+/// Sizes of the statically sized dual numbers, the tangent size of a residual
+/// block is rounded up to the next one. Larger residual blocks use `DualDVec64`.
+pub const STATIC_DUAL_SIZES: [usize; 4] = [4, 8, 12, 16];
 
 pub trait Factor<T: na::RealField>: Send + Sync {
     fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T>;
 }
-pub trait FactorImpl: Factor<num_dual::DualDVec64> + Factor<f64> {
+pub trait FactorImpl: Send + Sync {
+    fn residual_func_dual(
+        &self,
+        params: &[na::DVector<num_dual::DualDVec64>],
+    ) -> na::DVector<num_dual::DualDVec64>;
+    fn residual_func_f64(&self, params: &[na::DVector<f64>]) -> na::DVector<f64>;
+    /// Residual and Jacobian with respect to the stacked ambient parameters
+    /// if the factor has an analytic Jacobian, `None` uses automatic
+    /// differentiation instead.
+    fn analytic_residual_and_jacobian(
+        &self,
+        _params: &[na::DVector<f64>],
+    ) -> Option<(na::DVector<f64>, na::DMatrix<f64>)> {
+        None
+    }
+    /// Residual and Jacobian with respect to the tangent space of `params`
+    /// without `DualDVec64`, i.e. statically sized dual numbers for a `Factor`
+    /// or finite differences for a `NumericDiffFactor`. `None` if the factor
+    /// needs the dynamically sized dual numbers, e.g. the tangent size is
+    /// larger than `STATIC_DUAL_SIZES`.
+    fn tangent_residual_and_jacobian(
+        &self,
+        _params: &[&ParameterBlock],
+    ) -> Option<(na::DVector<f64>, na::DMatrix<f64>)> {
+        None
+    }
+}
+
+/// Every `Factor` implemented for `f64` and `DualDVec64` is a `FactorImpl`.
+impl<T> FactorImpl for T
+where
+    T: Factor<f64> + Factor<DualDVec64>,
+{
     fn residual_func_dual(
         &self,
         params: &[na::DVector<num_dual::DualDVec64>],
     ) -> na::DVector<num_dual::DualDVec64> {
         self.residual_func(params)
     }
+
     fn residual_func_f64(&self, params: &[na::DVector<f64>]) -> na::DVector<f64> {
         self.residual_func(params)
     }
 }
 
-impl<T> FactorImpl for T
+/// Differentiates `F` with statically sized dual numbers, which avoids the
+/// heap allocations of `DualDVec64`, when the tangent size of the residual
+/// block fits in `STATIC_DUAL_SIZES` and every manifold supports them (see
+/// `Manifold::as_static_dual`). `F` implements `Factor` for the sizes of
+/// `STATIC_DUAL_SIZES`, e.g. a factor implemented for any `na::RealField`.
+///
+/// ```
+/// use tiny_solver::Problem;
+/// use tiny_solver::factors::{PriorFactor, StaticDual};
+/// use tiny_solver::na;
+///
+/// let mut problem = Problem::new();
+/// let prior = PriorFactor { v: na::dvector![1.0, 2.0] };
+/// problem
+///     .add_residual_block(2, &["x"], Box::new(StaticDual(prior)), None)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticDual<F>(pub F);
+
+impl<F> FactorImpl for StaticDual<F>
 where
-    T: Factor<num_dual::DualDVec64> + Factor<f64>,
+    F: Factor<f64>
+        + Factor<DualDVec64>
+        + Factor<DualSVec64<4>>
+        + Factor<DualSVec64<8>>
+        + Factor<DualSVec64<12>>
+        + Factor<DualSVec64<16>>,
 {
     fn residual_func_dual(
         &self,
         params: &[na::DVector<num_dual::DualDVec64>],
     ) -> na::DVector<num_dual::DualDVec64> {
-        self.residual_func(params)
+        self.0.residual_func(params)
     }
 
     fn residual_func_f64(&self, params: &[na::DVector<f64>]) -> na::DVector<f64> {
-        self.residual_func(params)
+        self.0.residual_func(params)
+    }
+
+    fn tangent_residual_and_jacobian(
+        &self,
+        params: &[&ParameterBlock],
+    ) -> Option<(na::DVector<f64>, na::DMatrix<f64>)> {
+        match params.iter().map(|p| p.tangent_size()).sum::<usize>() {
+            0..=4 => static_residual_and_jacobian::<_, 4>(&self.0, params),
+            5..=8 => static_residual_and_jacobian::<_, 8>(&self.0, params),
+            9..=12 => static_residual_and_jacobian::<_, 12>(&self.0, params),
+            13..=16 => static_residual_and_jacobian::<_, 16>(&self.0, params),
+            _ => None,
+        }
     }
 }
 
+fn static_residual_and_jacobian<F, const N: usize>(
+    factor: &F,
+    params: &[&ParameterBlock],
+) -> Option<(na::DVector<f64>, na::DMatrix<f64>)>
+where
+    F: Factor<DualSVec64<N>> + ?Sized,
+    dyn StaticDualManifold: AutoDiffManifold<DualSVec64<N>>,
+{
+    let mut dim_variable = 0;
+    let params_plus_tangent_dual = params
+        .iter()
+        .map(|param| {
+            let zeros_with_dual = na::DVector::from_fn(param.tangent_size(), |j, _| {
+                DualSVec64::<N>::from_re(0.0).derivative(dim_variable + j)
+            });
+            dim_variable += param.tangent_size();
+            param.plus_static(zeros_with_dual.as_view())
+        })
+        .collect::<Option<Vec<na::DVector<DualSVec64<N>>>>>()?;
+    let residual_with_jacobian = factor.residual_func(&params_plus_tangent_dual);
+    let residual = residual_with_jacobian.map(|x| x.re);
+    let mut jacobian = na::DMatrix::zeros(residual.nrows(), dim_variable);
+    for (r, x) in residual_with_jacobian.iter().enumerate() {
+        let eps = x.eps.unwrap_generic(na::Const::<N>, na::Const::<1>);
+        jacobian
+            .row_mut(r)
+            .copy_from(&eps.rows(0, dim_variable).transpose());
+    }
+    Some((residual, jacobian))
+}
+
+/// Defines a factor inline, without writing the struct and the generic
+/// `Factor<T>` implementation. The body is generic over the scalar type `T`
+/// and `params` is a `&[na::DVector<T>]`. Values used by the residual are
+/// declared with their types in brackets and are references in the body.
+///
+/// ```
+/// use tiny_solver::{factor, na};
+///
+/// let custom = factor!(|params| {
+///     na::dvector![params[0][0].clone() * params[1][0].clone()]
+/// });
+/// let observed = na::dvector![1.0, 2.0];
+/// let prior = factor!([v: na::DVector<f64> = observed] |params| {
+///     params[0].clone() - v.clone().cast::<T>()
+/// });
+/// # let _ = (custom, prior);
+/// ```
+#[macro_export]
+macro_rules! factor {
+    (|$params:ident| $body:expr) => {
+        $crate::factor!([] |$params| $body)
+    };
+    ([$($name:ident : $ty:ty = $value:expr),* $(,)?] |$params:ident| $body:expr) => {{
+        struct ClosureFactor {
+            $($name: $ty,)*
+        }
+        impl<T: $crate::na::RealField> $crate::factors::Factor<T> for ClosureFactor {
+            fn residual_func(&self, $params: &[$crate::na::DVector<T>]) -> $crate::na::DVector<T> {
+                $(let $name = &self.$name;)*
+                $body
+            }
+        }
+        ClosureFactor {
+            $($name: $value,)*
+        }
+    }};
+}
+
+/// Factor with a hand-written Jacobian, evaluated in `f64` without dual
+/// numbers. Wrap it in `Analytic` to add it to a problem, and use
+/// `compare_jacobians` to check it against automatic differentiation.
+pub trait AnalyticFactor: Send + Sync {
+    fn residual(&self, params: &[na::DVector<f64>]) -> na::DVector<f64> {
+        self.residual_and_jacobian(params).0
+    }
+    /// The Jacobian has a column per ambient parameter, stacked in the order
+    /// of `params`. The chain rule through the manifolds is applied by the
+    /// residual block.
+    fn residual_and_jacobian(
+        &self,
+        params: &[na::DVector<f64>],
+    ) -> (na::DVector<f64>, na::DMatrix<f64>);
+}
+
+/// Uses the analytic Jacobian of `F` when linearizing the problem.
+#[derive(Debug, Clone)]
+pub struct Analytic<F>(pub F);
+
+impl<F: AnalyticFactor> FactorImpl for Analytic<F> {
+    /// First order expansion with the analytic Jacobian, so the factor still
+    /// works where dual numbers are required.
+    fn residual_func_dual(
+        &self,
+        params: &[na::DVector<num_dual::DualDVec64>],
+    ) -> na::DVector<num_dual::DualDVec64> {
+        let params_re: Vec<na::DVector<f64>> = params.iter().map(|p| p.map(|x| x.re)).collect();
+        let (residual, jacobian) = self.0.residual_and_jacobian(&params_re);
+        first_order_dual(params, &residual, &jacobian)
+    }
+    fn residual_func_f64(&self, params: &[na::DVector<f64>]) -> na::DVector<f64> {
+        self.0.residual(params)
+    }
+    fn analytic_residual_and_jacobian(
+        &self,
+        params: &[na::DVector<f64>],
+    ) -> Option<(na::DVector<f64>, na::DMatrix<f64>)> {
+        Some(self.0.residual_and_jacobian(params))
+    }
+}
+
+/// `residual + jacobian * (params - params.re)` in dual numbers, where the
+/// Jacobian is with respect to the stacked ambient parameters.
+pub(crate) fn first_order_dual(
+    params: &[na::DVector<DualDVec64>],
+    residual: &na::DVector<f64>,
+    jacobian: &na::DMatrix<f64>,
+) -> na::DVector<DualDVec64> {
+    let params_eps: Vec<DualDVec64> = params
+        .iter()
+        .flat_map(|p| p.iter().map(|x| x.clone() - x.re))
+        .collect();
+    na::DVector::from_fn(residual.nrows(), |r, _| {
+        params_eps
+            .iter()
+            .enumerate()
+            .fold(DualDVec64::from_re(residual[r]), |acc, (c, eps)| {
+                acc + eps.clone() * jacobian[(r, c)]
+            })
+    })
+}
+
+/// Analytic and automatic differentiation Jacobians of a factor with respect
+/// to its ambient parameters, see `compare_jacobians`.
+#[derive(Debug, Clone)]
+pub struct JacobianComparison {
+    pub analytic: na::DMatrix<f64>,
+    pub autodiff: na::DMatrix<f64>,
+    pub max_abs_error: f64,
+}
+
+/// Evaluates the analytic Jacobian of `factor` at `params` and the one from
+/// automatic differentiation of its `Factor` implementation, for debugging
+/// hand-written Jacobians.
+pub fn compare_jacobians<F>(factor: &F, params: &[na::DVector<f64>]) -> JacobianComparison
+where
+    F: AnalyticFactor + Factor<num_dual::DualDVec64>,
+{
+    let (_, analytic) = factor.residual_and_jacobian(params);
+    let dim_variable: usize = params.iter().map(|p| p.nrows()).sum();
+    let mut col = 0;
+    let dual_params: Vec<na::DVector<num_dual::DualDVec64>> = params
+        .iter()
+        .map(|p| {
+            p.map(|x| {
+                let mut eps = na::DVector::zeros(dim_variable);
+                eps[col] = 1.0;
+                col += 1;
+                num_dual::DualDVec64::new(x, num_dual::Derivative::some(eps))
+            })
+        })
+        .collect();
+    let residual = factor.residual_func(&dual_params);
+    let autodiff = na::DMatrix::from_fn(residual.nrows(), dim_variable, |r, c| {
+        residual[r]
+            .eps
+            .clone()
+            .unwrap_generic(na::Dyn(dim_variable), na::Const::<1>)[c]
+    });
+    let max_abs_error = if analytic.shape() == autodiff.shape() {
+        (&analytic - &autodiff).amax()
+    } else {
+        f64::INFINITY
+    };
+    JacobianComparison {
+        analytic,
+        autodiff,
+        max_abs_error,
+    }
+}
+
+/// Relative pose between two `SE2Manifold` variables `[theta, x, y]`, the
+/// residual is the pose error `[x, y, theta]` as in g2o and GTSAM, with the
+/// angle wrapped to `[-pi, pi)`.
 #[derive(Debug, Clone)]
 pub struct BetweenFactorSE2 {
     pub dx: f64,
@@ -41,27 +304,12 @@ pub struct BetweenFactorSE2 {
 }
 impl<T: na::RealField> Factor<T> for BetweenFactorSE2 {
     fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
-        let t_origin_k0 = &params[0];
-        let t_origin_k1 = &params[1];
-        let se2_origin_k0 = na::Isometry2::new(
-            na::Vector2::new(t_origin_k0[1].clone(), t_origin_k0[2].clone()),
-            t_origin_k0[0].clone(),
-        );
-        let se2_origin_k1 = na::Isometry2::new(
-            na::Vector2::new(t_origin_k1[1].clone(), t_origin_k1[2].clone()),
-            t_origin_k1[0].clone(),
-        );
-        let se2_k0_k1 = na::Isometry2::new(
-            na::Vector2::<T>::new(T::from_f64(self.dx).unwrap(), T::from_f64(self.dy).unwrap()),
-            T::from_f64(self.dtheta).unwrap(),
-        );
+        let se2_origin_k0 = SE2::from_vec(params[0].as_view());
+        let se2_origin_k1 = SE2::from_vec(params[1].as_view());
+        let se2_k0_k1 = SE2::new(self.dtheta, self.dx, self.dy).cast::<T>();
 
         let se2_diff = se2_origin_k1.inverse() * se2_origin_k0 * se2_k0_k1;
-        na::dvector![
-            se2_diff.translation.x.clone(),
-            se2_diff.translation.y.clone(),
-            se2_diff.rotation.angle()
-        ]
+        na::dvector![se2_diff.xy.x.clone(), se2_diff.xy.y.clone(), se2_diff.theta]
     }
 }
 
@@ -96,6 +344,54 @@ impl<T: na::RealField> Factor<T> for BetweenFactorSE3 {
     }
 }
 
+/// Whitens the residual of `factor` with a square-root information matrix,
+/// i.e. `r' = sqrt_info * r` so that `|r'|^2 = r^T * info * r`.
+#[derive(Debug, Clone)]
+pub struct WeightedFactor<F> {
+    pub factor: F,
+    pub sqrt_info: na::DMatrix<f64>,
+}
+
+impl<F> WeightedFactor<F> {
+    pub fn new(factor: F, sqrt_info: na::DMatrix<f64>) -> Self {
+        WeightedFactor { factor, sqrt_info }
+    }
+    /// Cholesky-factors `information = L * L^T` and uses `L^T` as the weight.
+    /// Returns `None` if the information matrix is not positive definite.
+    pub fn from_information(factor: F, information: na::DMatrix<f64>) -> Option<Self> {
+        let llt = information.cholesky()?;
+        Some(WeightedFactor {
+            factor,
+            sqrt_info: llt.l().transpose(),
+        })
+    }
+}
+
+impl<T: na::RealField, F: Factor<T>> Factor<T> for WeightedFactor<F> {
+    fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+        let residual = self.factor.residual_func(params);
+        mul_f64_matrix(&self.sqrt_info, &residual)
+    }
+}
+
+/// `matrix * v` without casting `matrix` to `T`, skipping its zeros, e.g.
+/// those of a triangular square-root information matrix.
+pub(crate) fn mul_f64_matrix<T: na::RealField>(
+    matrix: &na::DMatrix<f64>,
+    v: &na::DVector<T>,
+) -> na::DVector<T> {
+    na::DVector::from_fn(matrix.nrows(), |r, _| {
+        matrix
+            .row(r)
+            .iter()
+            .zip(v.iter())
+            .filter(|&(&m, _)| m != 0.0)
+            .fold(T::zero(), |acc, (&m, x)| {
+                acc + x.clone() * T::from_f64(m).unwrap()
+            })
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct PriorFactor {
     pub v: na::DVector<f64>,
@@ -105,3 +401,254 @@ impl<T: na::RealField> Factor<T> for PriorFactor {
         params[0].clone() - self.v.clone().cast()
     }
 }
+impl AnalyticFactor for PriorFactor {
+    fn residual_and_jacobian(
+        &self,
+        params: &[na::DVector<f64>],
+    ) -> (na::DVector<f64>, na::DMatrix<f64>) {
+        let dim = params[0].nrows();
+        (&params[0] - &self.v, na::DMatrix::identity(dim, dim))
+    }
+}
+
+/// Rotates `point` by the angle-axis vector `angle_axis` with the Rodrigues formula.
+pub fn angle_axis_rotate_point<T: na::RealField>(
+    angle_axis: &na::Vector3<T>,
+    point: &na::Vector3<T>,
+) -> na::Vector3<T> {
+    let theta2 = angle_axis.norm_squared();
+    if theta2 > T::from_f64(f64::EPSILON).unwrap() {
+        let theta = theta2.sqrt();
+        let axis = angle_axis / theta.clone();
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let axis_dot_point = axis.dot(point);
+        point * cos_theta.clone()
+            + axis.cross(point) * sin_theta
+            + axis * (axis_dot_point * (T::one() - cos_theta))
+    } else {
+        // first order approximation near zero, R = I + hat(angle_axis)
+        point + angle_axis.cross(point)
+    }
+}
+
+/// Reprojection error of the Bundle Adjustment in the Large (BAL) datasets.
+///
+/// params: `[camera, point]` with camera `[angle-axis (3), t (3), f, k1, k2]`
+/// and the world point `[x, y, z]`. BAL cameras look down the -z axis,
+/// `p = -P / P.z` with `P = R * X + t`, and the projection is
+/// `f * (1 + k1 * |p|^2 + k2 * |p|^4) * p`.
+/// https://grail.cs.washington.edu/projects/bal/
+#[derive(Debug, Clone)]
+pub struct ReprojectionFactorBAL {
+    pub observed: na::Vector2<f64>,
+}
+impl<T: na::RealField> Factor<T> for ReprojectionFactorBAL {
+    fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+        let camera = &params[0];
+        let point = na::Vector3::new(
+            params[1][0].clone(),
+            params[1][1].clone(),
+            params[1][2].clone(),
+        );
+        let angle_axis = na::Vector3::new(camera[0].clone(), camera[1].clone(), camera[2].clone());
+        let t = na::Vector3::new(camera[3].clone(), camera[4].clone(), camera[5].clone());
+        let p_camera = angle_axis_rotate_point(&angle_axis, &point) + t;
+
+        let xp = -p_camera[0].clone() / p_camera[2].clone();
+        let yp = -p_camera[1].clone() / p_camera[2].clone();
+        let r2 = xp.clone() * xp.clone() + yp.clone() * yp.clone();
+        let distortion =
+            T::one() + r2.clone() * (camera[7].clone() + camera[8].clone() * r2.clone());
+        let focal = camera[6].clone() * distortion;
+        na::dvector![
+            focal.clone() * xp - T::from_f64(self.observed[0]).unwrap(),
+            focal * yp - T::from_f64(self.observed[1]).unwrap()
+        ]
+    }
+}
+impl AnalyticFactor for ReprojectionFactorBAL {
+    fn residual_and_jacobian(
+        &self,
+        params: &[na::DVector<f64>],
+    ) -> (na::DVector<f64>, na::DMatrix<f64>) {
+        let camera = &params[0];
+        let point = na::Vector3::new(params[1][0], params[1][1], params[1][2]);
+        let angle_axis = na::Vector3::new(camera[0], camera[1], camera[2]);
+        let t = na::Vector3::new(camera[3], camera[4], camera[5]);
+        let (f, k1, k2) = (camera[6], camera[7], camera[8]);
+        let rotation = na::Rotation3::new(angle_axis);
+        let p_camera = rotation * point + t;
+
+        let p = -p_camera.xy() / p_camera.z;
+        let r2 = p.norm_squared();
+        let distortion = 1.0 + r2 * (k1 + k2 * r2);
+        let residual = p * (f * distortion) - self.observed;
+
+        // d(R * X) / d(angle_axis) = -R * hat(X) * Jr(angle_axis)
+        let theta2 = angle_axis.norm_squared();
+        let hat_w = angle_axis.cross_matrix();
+        let right_jacobian = if theta2 > f64::EPSILON {
+            let theta = theta2.sqrt();
+            na::Matrix3::identity() - hat_w * ((1.0 - theta.cos()) / theta2)
+                + hat_w * hat_w * ((theta - theta.sin()) / (theta2 * theta))
+        } else {
+            na::Matrix3::identity()
+        };
+        let d_pc_d_w = -(rotation.matrix() * point.cross_matrix() * right_jacobian);
+        let d_pc_d_x = rotation.matrix();
+
+        // p = -P.xy / P.z
+        let inv_z = 1.0 / p_camera.z;
+        let d_p_d_pc = na::Matrix2x3::new(-inv_z, 0.0, -p.x * inv_z, 0.0, -inv_z, -p.y * inv_z);
+        // f * distortion(|p|^2) * p
+        let d_distortion_d_p = p.transpose() * (2.0 * (k1 + 2.0 * k2 * r2));
+        let d_r_d_p = (na::Matrix2::identity() * distortion + p * d_distortion_d_p) * f;
+        let d_r_d_pc = d_r_d_p * d_p_d_pc;
+
+        let mut jacobian = na::DMatrix::zeros(2, 12);
+        jacobian
+            .fixed_view_mut::<2, 3>(0, 0)
+            .copy_from(&(d_r_d_pc * d_pc_d_w));
+        jacobian.fixed_view_mut::<2, 3>(0, 3).copy_from(&d_r_d_pc);
+        jacobian
+            .fixed_view_mut::<2, 1>(0, 6)
+            .copy_from(&(p * distortion));
+        jacobian
+            .fixed_view_mut::<2, 1>(0, 7)
+            .copy_from(&(p * (f * r2)));
+        jacobian
+            .fixed_view_mut::<2, 1>(0, 8)
+            .copy_from(&(p * (f * r2 * r2)));
+        jacobian
+            .fixed_view_mut::<2, 3>(0, 9)
+            .copy_from(&(d_r_d_pc * d_pc_d_x));
+        (na::dvector![residual.x, residual.y], jacobian)
+    }
+}
+
+/// Projection models of `ReprojectionFactor`. The intrinsics always start
+/// with `[fx, fy, cx, cy]` followed by the distortion parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraModel {
+    /// `[fx, fy, cx, cy]`
+    Pinhole,
+    /// Radial and tangential distortion in OpenCV order,
+    /// `[fx, fy, cx, cy, k1, k2, p1, p2, k3]`.
+    BrownConrady,
+    /// Equidistant fisheye model, `[fx, fy, cx, cy, k1, k2, k3, k4]`.
+    KannalaBrandt,
+    /// Unified camera model, `[fx, fy, cx, cy, alpha]`.
+    UnifiedCamera,
+}
+
+impl CameraModel {
+    pub fn num_intrinsics(&self) -> usize {
+        match self {
+            CameraModel::Pinhole => 4,
+            CameraModel::BrownConrady => 9,
+            CameraModel::KannalaBrandt => 8,
+            CameraModel::UnifiedCamera => 5,
+        }
+    }
+
+    /// Projects a point in the camera frame to the image.
+    pub fn project<T: na::RealField>(
+        &self,
+        intrinsics: &na::DVector<T>,
+        p_camera: &na::Vector3<T>,
+    ) -> na::Vector2<T> {
+        let fx = intrinsics[0].clone();
+        let fy = intrinsics[1].clone();
+        let cx = intrinsics[2].clone();
+        let cy = intrinsics[3].clone();
+        let (x, y, z) = (
+            p_camera[0].clone(),
+            p_camera[1].clone(),
+            p_camera[2].clone(),
+        );
+        let (mx, my) = match self {
+            CameraModel::Pinhole => (x / z.clone(), y / z),
+            CameraModel::BrownConrady => {
+                let (k1, k2, p1, p2, k3) = (
+                    intrinsics[4].clone(),
+                    intrinsics[5].clone(),
+                    intrinsics[6].clone(),
+                    intrinsics[7].clone(),
+                    intrinsics[8].clone(),
+                );
+                let xp = x / z.clone();
+                let yp = y / z;
+                let two = T::from_f64(2.0).unwrap();
+                let xy = xp.clone() * yp.clone();
+                let r2 = xp.clone() * xp.clone() + yp.clone() * yp.clone();
+                let radial = T::one() + r2.clone() * (k1 + r2.clone() * (k2 + r2.clone() * k3));
+                let xpp = xp.clone() * radial.clone()
+                    + two.clone() * p1.clone() * xy.clone()
+                    + p2.clone() * (r2.clone() + two.clone() * xp.clone() * xp);
+                let ypp =
+                    yp.clone() * radial + p1 * (r2 + two.clone() * yp.clone() * yp) + two * p2 * xy;
+                (xpp, ypp)
+            }
+            CameraModel::KannalaBrandt => {
+                let (k1, k2, k3, k4) = (
+                    intrinsics[4].clone(),
+                    intrinsics[5].clone(),
+                    intrinsics[6].clone(),
+                    intrinsics[7].clone(),
+                );
+                let r2 = x.clone() * x.clone() + y.clone() * y.clone();
+                if r2 > T::from_f64(f64::EPSILON).unwrap() {
+                    let r = r2.sqrt();
+                    let theta = r.clone().atan2(z);
+                    let theta2 = theta.clone() * theta.clone();
+                    let theta_d = theta
+                        * (T::one()
+                            + theta2.clone()
+                                * (k1
+                                    + theta2.clone() * (k2 + theta2.clone() * (k3 + theta2 * k4))));
+                    let scale = theta_d / r;
+                    (x * scale.clone(), y * scale)
+                } else {
+                    // theta_d / r -> 1 / z on the optical axis
+                    (x / z.clone(), y / z)
+                }
+            }
+            CameraModel::UnifiedCamera => {
+                let alpha = intrinsics[4].clone();
+                let d =
+                    (x.clone() * x.clone() + y.clone() * y.clone() + z.clone() * z.clone()).sqrt();
+                let denominator = alpha.clone() * d + (T::one() - alpha) * z;
+                (x / denominator.clone(), y / denominator)
+            }
+        };
+        na::Vector2::new(fx * mx + cx, fy * my + cy)
+    }
+}
+
+/// Reprojection error `project(T_camera_world * X) - observed`.
+///
+/// params: `[intrinsics, T_camera_world, point]` where the intrinsics follow
+/// `camera_model`, the pose is `[qx, qy, qz, qw, tx, ty, tz]` (use `SE3Manifold`)
+/// and the world point is `[x, y, z]`. For calibration with a known target,
+/// fix the point with `Problem::fix_variable`.
+#[derive(Debug, Clone)]
+pub struct ReprojectionFactor {
+    pub camera_model: CameraModel,
+    pub observed: na::Vector2<f64>,
+}
+impl<T: na::RealField> Factor<T> for ReprojectionFactor {
+    fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+        let t_camera_world = SE3::from_vec(params[1].as_view());
+        let point = na::Vector3::new(
+            params[2][0].clone(),
+            params[2][1].clone(),
+            params[2][2].clone(),
+        );
+        let p_camera = &t_camera_world * point.as_view();
+        let projected = self.camera_model.project(&params[0], &p_camera);
+        na::dvector![
+            projected[0].clone() - T::from_f64(self.observed[0]).unwrap(),
+            projected[1].clone() - T::from_f64(self.observed[1]).unwrap()
+        ]
+    }
+}
@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::read_to_string;
 use std::sync::Arc;
 
 use nalgebra as na;
 
-use crate::loss_functions::HuberLoss;
+use crate::error::TinySolverError;
+use crate::manifold::se2::SE2Manifold;
 use crate::manifold::se3::SE3Manifold;
 use crate::{factors, problem};
 
@@ -29,84 +30,266 @@ pub fn translation_quaternion_to_na<T: na::RealField>(
     )
 }
 
-pub fn read_g2o(filename: &str) -> (problem::Problem, HashMap<String, na::DVector<f64>>) {
+/// Rebuilds a symmetric matrix from its row-major upper triangle,
+/// which is how g2o stores the information matrix of an edge.
+pub fn upper_triangular_to_symmetric(values: &[f64], dim: usize) -> na::DMatrix<f64> {
+    let mut mat = na::DMatrix::zeros(dim, dim);
+    let mut count = 0;
+    for r in 0..dim {
+        for c in r..dim {
+            mat[(r, c)] = values[count];
+            mat[(c, r)] = values[count];
+            count += 1;
+        }
+    }
+    mat
+}
+
+fn parse_token<T: std::str::FromStr>(
+    tokens: &[&str],
+    idx: usize,
+    line: usize,
+) -> Result<T, TinySolverError> {
+    let token = tokens.get(idx).ok_or_else(|| TinySolverError::ParseError {
+        line,
+        message: format!("expected at least {} values", idx + 1),
+    })?;
+    token.parse::<T>().map_err(|_| TinySolverError::ParseError {
+        line,
+        message: format!("failed to parse '{}'", token),
+    })
+}
+
+fn parse_information(
+    tokens: &[&str],
+    start: usize,
+    dim: usize,
+    line: usize,
+) -> Result<na::DMatrix<f64>, TinySolverError> {
+    let values = (start..tokens.len())
+        .map(|idx| parse_token::<f64>(tokens, idx, line))
+        .collect::<Result<Vec<f64>, _>>()?;
+    let expected = dim * (dim + 1) / 2;
+    if values.len() < expected {
+        return Err(TinySolverError::ParseError {
+            line,
+            message: format!(
+                "expected {} values for the information matrix, found {}",
+                expected,
+                values.len()
+            ),
+        });
+    }
+    Ok(upper_triangular_to_symmetric(&values, dim))
+}
+
+fn not_positive_definite(line: usize) -> TinySolverError {
+    TinySolverError::ParseError {
+        line,
+        message: "information matrix is not positive definite".to_string(),
+    }
+}
+
+/// Reads a 2D (`VERTEX_SE2`, `EDGE_SE2`) or 3D (`VERTEX_SE3:QUAT`, `EDGE_SE3:QUAT`)
+/// pose graph in g2o format. The vertices listed by `FIX` are fixed, otherwise
+/// `x0` is fixed if it exists. Other tags, e.g. `PARAMS_*` or
+/// landmark vertices, are skipped with a warning.
+pub fn read_g2o(
+    filename: &str,
+) -> Result<(problem::Problem, HashMap<String, na::DVector<f64>>), TinySolverError> {
     let mut problem = problem::Problem::new();
     let mut init_values = HashMap::<String, na::DVector<f64>>::new();
-    for line in read_to_string(filename).unwrap().lines() {
-        let line: Vec<&str> = line.split(' ').collect();
-        match line[0] {
+    let mut tangent_sizes = HashMap::<String, usize>::new();
+    let mut fixed_vertices = Vec::new();
+    let mut skipped_tags = HashSet::new();
+    for (idx, line) in read_to_string(filename)?.lines().enumerate() {
+        let line_number = idx + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(&tag) = tokens.first() else {
+            continue;
+        };
+        let f = |i: usize| parse_token::<f64>(&tokens, i, line_number);
+        let id =
+            |i: usize| parse_token::<usize>(&tokens, i, line_number).map(|v| format!("x{}", v));
+        match tag {
             "VERTEX_SE2" => {
-                let x = line[2].parse::<f64>().unwrap();
-                let y = line[3].parse::<f64>().unwrap();
-                let theta = line[4].parse::<f64>().unwrap();
-                init_values.insert(format!("x{}", line[1]), na::dvector![theta, x, y]);
+                let x = f(2)?;
+                let y = f(3)?;
+                let theta = f(4)?;
+                let var_name = id(1)?;
+                problem.set_variable_manifold(&var_name, Arc::new(SE2Manifold));
+                tangent_sizes.insert(var_name.clone(), 3);
+                init_values.insert(var_name, na::dvector![theta, x, y]);
             }
             "EDGE_SE2" => {
-                let id0 = format!("x{}", line[1]);
-                let id1 = format!("x{}", line[2]);
-                let dx = line[3].parse::<f64>().unwrap();
-                let dy = line[4].parse::<f64>().unwrap();
-                let dtheta = line[5].parse::<f64>().unwrap();
-                // todo add info matrix
-                let edge = factors::BetweenFactorSE2 { dx, dy, dtheta };
+                let id0 = id(1)?;
+                let id1 = id(2)?;
+                let dx = f(3)?;
+                let dy = f(4)?;
+                let dtheta = f(5)?;
+                // information matrix is ordered as [x, y, theta], same as the residual
+                let information = parse_information(&tokens, 6, 3, line_number)?;
+                let edge = factors::WeightedFactor::from_information(
+                    factors::BetweenFactorSE2 { dx, dy, dtheta },
+                    information,
+                )
+                .ok_or_else(|| not_positive_definite(line_number))?;
                 problem.add_residual_block(
                     3,
                     &[&id0, &id1],
-                    Box::new(edge),
-                    Some(Box::new(HuberLoss::new(1.0))),
-                );
+                    Box::new(factors::StaticDual(edge)),
+                    None,
+                )?;
             }
             "VERTEX_SE3:QUAT" => {
-                let x = line[2].parse::<f64>().expect("Failed to parse g2o");
-                let y = line[3].parse::<f64>().expect("Failed to parse g2o");
-                let z = line[4].parse::<f64>().expect("Failed to parse g2o");
-                let qx = line[5].parse::<f64>().expect("Failed to parse g2o");
-                let qy = line[6].parse::<f64>().expect("Failed to parse g2o");
-                let qz = line[7].parse::<f64>().expect("Failed to parse g2o");
-                let qw = line[8].parse::<f64>().expect("Failed to parse g2o");
-                let var_name = format!("x{}", line[1]);
+                let x = f(2)?;
+                let y = f(3)?;
+                let z = f(4)?;
+                let qx = f(5)?;
+                let qy = f(6)?;
+                let qz = f(7)?;
+                let qw = f(8)?;
+                let var_name = id(1)?;
                 problem.set_variable_manifold(&var_name, Arc::new(SE3Manifold));
+                tangent_sizes.insert(var_name.clone(), 6);
                 init_values.insert(var_name, na::dvector![qx, qy, qz, qw, x, y, z]);
             }
             "EDGE_SE3:QUAT" => {
-                let id0 = format!("x{}", line[1]);
-                let id1 = format!("x{}", line[2]);
-                let dtx = line[3].parse::<f64>().expect("Failed to parse g2o");
-                let dty = line[4].parse::<f64>().expect("Failed to parse g2o");
-                let dtz = line[5].parse::<f64>().expect("Failed to parse g2o");
-                let dqx = line[6].parse::<f64>().expect("Failed to parse g2o");
-                let dqy = line[7].parse::<f64>().expect("Failed to parse g2o");
-                let dqz = line[8].parse::<f64>().expect("Failed to parse g2o");
-                let dqw = line[9].parse::<f64>().expect("Failed to parse g2o");
-                let edge = factors::BetweenFactorSE3 {
-                    dtx,
-                    dty,
-                    dtz,
-                    dqx,
-                    dqy,
-                    dqz,
-                    dqw,
-                };
+                let id0 = id(1)?;
+                let id1 = id(2)?;
+                let dtx = f(3)?;
+                let dty = f(4)?;
+                let dtz = f(5)?;
+                let dqx = f(6)?;
+                let dqy = f(7)?;
+                let dqz = f(8)?;
+                let dqw = f(9)?;
+                // g2o orders the information matrix as [translation, rotation]
+                // while the residual of BetweenFactorSE3 is [rotation, translation]
+                let information = parse_information(&tokens, 10, 6, line_number)?;
+                let perm = [3, 4, 5, 0, 1, 2];
+                let information =
+                    na::DMatrix::from_fn(6, 6, |r, c| information[(perm[r], perm[c])]);
+                let edge = factors::WeightedFactor::from_information(
+                    factors::BetweenFactorSE3 {
+                        dtx,
+                        dty,
+                        dtz,
+                        dqx,
+                        dqy,
+                        dqz,
+                        dqw,
+                    },
+                    information,
+                )
+                .ok_or_else(|| not_positive_definite(line_number))?;
                 problem.add_residual_block(
                     6,
                     &[&id0, &id1],
-                    Box::new(edge),
-                    Some(Box::new(HuberLoss::new(1.0))),
-                );
+                    Box::new(factors::StaticDual(edge)),
+                    None,
+                )?;
+            }
+            "FIX" => {
+                for i in 1..tokens.len() {
+                    fixed_vertices.push(id(i)?);
+                }
             }
             _ => {
-                println!("err");
-                break;
+                if skipped_tags.insert(tag.to_string()) {
+                    log::warn!("skipping the unsupported g2o tag '{}'", tag);
+                }
             }
         }
     }
-    let x0 = init_values.get("x0").unwrap();
-    let origin_factor = factors::PriorFactor { v: x0.clone() };
-    problem.add_residual_block(
-        x0.shape().0,
-        &["x0"],
-        Box::new(origin_factor),
-        Some(Box::new(HuberLoss::new(1.0))),
-    );
-    (problem, init_values)
+    // without FIX vertices x0 is fixed to remove the gauge freedom
+    if fixed_vertices.is_empty() {
+        if tangent_sizes.contains_key("x0") {
+            fixed_vertices.push("x0".to_string());
+        } else {
+            log::warn!("x0 is not in the g2o file, the gauge freedom is not fixed");
+        }
+    }
+    for var_name in &fixed_vertices {
+        match tangent_sizes.get(var_name) {
+            Some(&tangent_size) => {
+                for i in 0..tangent_size {
+                    problem.fix_variable(var_name.as_str(), i);
+                }
+            }
+            None => log::warn!("fixed vertex {} is not in the g2o file", var_name),
+        }
+    }
+    Ok((problem, init_values))
+}
+
+/// Reads a Bundle Adjustment in the Large (BAL) problem.
+/// https://grail.cs.washington.edu/projects/bal/
+///
+/// Cameras are named `c{i}` with `[angle-axis (3), t (3), f, k1, k2]` and
+/// points `p{j}` with `[x, y, z]`. Every observation adds a 2 dimensional
+/// `ReprojectionFactorBAL`.
+pub fn read_bal(
+    filename: &str,
+) -> Result<(problem::Problem, HashMap<String, na::DVector<f64>>), TinySolverError> {
+    let content = read_to_string(filename)?;
+    let mut tokens = content
+        .lines()
+        .enumerate()
+        .flat_map(|(idx, line)| line.split_whitespace().map(move |token| (idx + 1, token)));
+    let mut last_line = 0;
+    let mut next = || -> Result<(usize, &str), TinySolverError> {
+        let (line, token) = tokens.next().ok_or(TinySolverError::ParseError {
+            line: last_line + 1,
+            message: "unexpected end of BAL file".to_string(),
+        })?;
+        last_line = line;
+        Ok((line, token))
+    };
+    fn parse<T: std::str::FromStr>((line, token): (usize, &str)) -> Result<T, TinySolverError> {
+        token.parse::<T>().map_err(|_| TinySolverError::ParseError {
+            line,
+            message: format!("failed to parse '{}'", token),
+        })
+    }
+
+    let num_cameras: usize = parse(next()?)?;
+    let num_points: usize = parse(next()?)?;
+    let num_observations: usize = parse(next()?)?;
+
+    let mut observations = Vec::with_capacity(num_observations);
+    for _ in 0..num_observations {
+        let camera_idx: usize = parse(next()?)?;
+        let point_idx: usize = parse(next()?)?;
+        let x = parse(next()?)?;
+        let y = parse(next()?)?;
+        observations.push((camera_idx, point_idx, na::Vector2::new(x, y)));
+    }
+
+    let mut init_values = HashMap::<String, na::DVector<f64>>::new();
+    for i in 0..num_cameras {
+        let camera = (0..9)
+            .map(|_| parse(next()?))
+            .collect::<Result<Vec<f64>, _>>()?;
+        init_values.insert(format!("c{}", i), na::DVector::from_vec(camera));
+    }
+    for j in 0..num_points {
+        let point = (0..3)
+            .map(|_| parse(next()?))
+            .collect::<Result<Vec<f64>, _>>()?;
+        init_values.insert(format!("p{}", j), na::DVector::from_vec(point));
+    }
+
+    let mut problem = problem::Problem::new();
+    for (camera_idx, point_idx, observed) in observations {
+        let camera = format!("c{}", camera_idx);
+        let point = format!("p{}", point_idx);
+        problem.add_residual_block(
+            2,
+            &[&camera, &point],
+            Box::new(factors::ReprojectionFactorBAL { observed }),
+            None,
+        )?;
+    }
+    Ok((problem, init_values))
 }
@@ -1,16 +1,25 @@
 pub mod corrector;
+pub mod covariance;
+pub mod error;
 pub mod factors;
 pub mod helper;
 pub mod linear;
 pub mod loss_functions;
 pub mod manifold;
+pub mod marginalization;
+pub mod numeric_diff;
 pub mod optimizer;
 pub mod parameter_block;
 pub mod problem;
 pub mod residual_block;
+pub mod variable_key;
 
+pub use covariance::*;
+pub use error::*;
 pub use factors::na;
 pub use linear::*;
+pub use marginalization::*;
 pub use optimizer::*;
 pub use problem::*;
 pub use residual_block::*;
+pub use variable_key::*;
@@ -0,0 +1,292 @@
+use std::ops::Mul;
+
+use faer::sparse::SparseColMatRef;
+use nalgebra as na;
+use rayon::prelude::*;
+
+use super::sparse::{SparseLinearSolver, normal_matrix};
+use crate::error::TinySolverError;
+
+const DEFAULT_MAX_ITERATIONS: usize = 500;
+const DEFAULT_RELATIVE_TOLERANCE: f64 = 1e-6;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum PreconditionerType {
+    Identity,
+    /// Inverse of the diagonal of J^T * J.
+    #[default]
+    Jacobi,
+    /// Inverse of the diagonal blocks of J^T * J, one block per parameter block.
+    BlockJacobi,
+    /// Exact inverse of the eliminated (e.g. landmark) blocks, and the inverse of
+    /// the diagonal blocks of the Schur complement for the remaining (e.g. camera) blocks.
+    SchurJacobi,
+}
+
+/// Inexact solver for the normal equations with preconditioned conjugate gradients.
+/// It only needs matrix-vector products with J^T * J, so no factorization is stored.
+///
+/// Iterations stop when |J^T * J * x - b| <= eta * |b|, where eta is set with
+/// `set_relative_tolerance` (e.g. a forcing sequence in the outer loop).
+#[derive(Debug, Clone)]
+pub struct ConjugateGradientSolver {
+    preconditioner_type: PreconditionerType,
+    /// (start column, size) of every parameter block.
+    parameter_blocks: Vec<(usize, usize)>,
+    /// (start column, size) of the blocks eliminated by `SchurJacobi`.
+    eliminate_blocks: Vec<(usize, usize)>,
+    max_iterations: usize,
+    relative_tolerance: f64,
+}
+
+enum Preconditioner {
+    Identity,
+    Diagonal(na::DVector<f64>),
+    /// (start column, inverse block)
+    Blocks(Vec<(usize, na::DMatrix<f64>)>),
+}
+
+impl Preconditioner {
+    fn apply(&self, r: &na::DVector<f64>) -> na::DVector<f64> {
+        match self {
+            Preconditioner::Identity => r.clone(),
+            Preconditioner::Diagonal(inv_diag) => r.component_mul(inv_diag),
+            Preconditioner::Blocks(blocks) => {
+                let mut z = r.clone();
+                for (start, inv) in blocks {
+                    let size = inv.nrows();
+                    let zi = inv * r.rows(*start, size);
+                    z.rows_mut(*start, size).copy_from(&zi);
+                }
+                z
+            }
+        }
+    }
+}
+
+impl ConjugateGradientSolver {
+    pub fn new(
+        preconditioner_type: PreconditionerType,
+        parameter_blocks: Vec<(usize, usize)>,
+        eliminate_blocks: Vec<(usize, usize)>,
+    ) -> Self {
+        ConjugateGradientSolver {
+            preconditioner_type,
+            parameter_blocks,
+            eliminate_blocks,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            relative_tolerance: DEFAULT_RELATIVE_TOLERANCE,
+        }
+    }
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    fn build_preconditioner(&self, jtj: SparseColMatRef<usize, f64>) -> Preconditioner {
+        let n = jtj.ncols();
+        let diagonal = na::DVector::from_fn(n, |c, _| {
+            jtj.row_idx_of_col(c)
+                .zip(jtj.val_of_col(c))
+                .find(|(r, _)| *r == c)
+                .map(|(_, v)| *v)
+                .unwrap_or(0.0)
+        });
+        let inv_diagonal = diagonal.map(|v| if v > 0.0 { 1.0 / v } else { 1.0 });
+        match self.preconditioner_type {
+            PreconditionerType::Identity => Preconditioner::Identity,
+            PreconditionerType::Jacobi => Preconditioner::Diagonal(inv_diagonal),
+            PreconditionerType::BlockJacobi => {
+                let blocks = self
+                    .parameter_blocks
+                    .par_iter()
+                    .map(|&(start, size)| {
+                        let block = dense_block(jtj, start, size, start, size);
+                        (start, invert_or_diagonal(block, &inv_diagonal, start))
+                    })
+                    .collect();
+                Preconditioner::Blocks(blocks)
+            }
+            PreconditionerType::SchurJacobi => self.build_schur_jacobi(jtj, &inv_diagonal),
+        }
+    }
+
+    fn build_schur_jacobi(
+        &self,
+        jtj: SparseColMatRef<usize, f64>,
+        inv_diagonal: &na::DVector<f64>,
+    ) -> Preconditioner {
+        let n = jtj.ncols();
+        let mut eliminated = vec![false; n];
+        for &(start, size) in &self.eliminate_blocks {
+            eliminated[start..start + size].fill(true);
+        }
+        let reduced_blocks: Vec<(usize, usize)> = self
+            .parameter_blocks
+            .iter()
+            .filter(|(start, size)| *size > 0 && !eliminated[*start])
+            .copied()
+            .collect();
+        let mut reduced_block_of_col = vec![usize::MAX; n];
+        for (block_idx, &(start, size)) in reduced_blocks.iter().enumerate() {
+            reduced_block_of_col[start..start + size].fill(block_idx);
+        }
+
+        let eliminated_inverses: Vec<(usize, na::DMatrix<f64>)> = self
+            .eliminate_blocks
+            .par_iter()
+            .map(|&(start, size)| {
+                let block = dense_block(jtj, start, size, start, size);
+                (start, invert_or_diagonal(block, inv_diagonal, start))
+            })
+            .collect();
+
+        // S_jj = H_jj - sum_i H_ji * H_ii^-1 * H_ij
+        let mut schur_diagonal: Vec<na::DMatrix<f64>> = reduced_blocks
+            .iter()
+            .map(|&(start, size)| dense_block(jtj, start, size, start, size))
+            .collect();
+        for ((start, size), (_, hee_inv)) in self.eliminate_blocks.iter().zip(&eliminated_inverses)
+        {
+            let mut coupled_blocks: Vec<usize> = (*start..start + size)
+                .flat_map(|c| jtj.row_idx_of_col(c))
+                .map(|r| reduced_block_of_col[r])
+                .filter(|&b| b != usize::MAX)
+                .collect();
+            coupled_blocks.sort();
+            coupled_blocks.dedup();
+            for block_idx in coupled_blocks {
+                let (reduced_start, reduced_size) = reduced_blocks[block_idx];
+                let hec = dense_block(jtj, *start, *size, reduced_start, reduced_size);
+                schur_diagonal[block_idx] -= hec.transpose() * hee_inv * &hec;
+            }
+        }
+        let mut blocks = eliminated_inverses;
+        blocks.extend(
+            reduced_blocks
+                .iter()
+                .zip(schur_diagonal)
+                .map(|(&(start, _), block)| {
+                    (start, invert_or_diagonal(block, inv_diagonal, start))
+                }),
+        );
+        Preconditioner::Blocks(blocks)
+    }
+}
+
+fn dense_block(
+    mat: SparseColMatRef<usize, f64>,
+    row_start: usize,
+    nrows: usize,
+    col_start: usize,
+    ncols: usize,
+) -> na::DMatrix<f64> {
+    let mut block = na::DMatrix::zeros(nrows, ncols);
+    for c in 0..ncols {
+        for (r, v) in mat
+            .row_idx_of_col(col_start + c)
+            .zip(mat.val_of_col(col_start + c))
+        {
+            if r >= row_start && r < row_start + nrows {
+                block[(r - row_start, c)] = *v;
+            }
+        }
+    }
+    block
+}
+
+fn invert_or_diagonal(
+    block: na::DMatrix<f64>,
+    inv_diagonal: &na::DVector<f64>,
+    start: usize,
+) -> na::DMatrix<f64> {
+    let size = block.nrows();
+    block
+        .cholesky()
+        .map(|llt| llt.inverse())
+        .unwrap_or_else(|| {
+            na::DMatrix::from_diagonal(&inv_diagonal.rows(start, size).clone_owned())
+        })
+}
+
+fn sparse_mul(mat: SparseColMatRef<usize, f64>, x: &na::DVector<f64>) -> na::DVector<f64> {
+    let mut y = na::DVector::zeros(mat.nrows());
+    for c in 0..mat.ncols() {
+        let xc = x[c];
+        if xc == 0.0 {
+            continue;
+        }
+        for (r, v) in mat.row_idx_of_col(c).zip(mat.val_of_col(c)) {
+            y[r] += v * xc;
+        }
+    }
+    y
+}
+
+impl SparseLinearSolver for ConjugateGradientSolver {
+    fn solve(
+        &mut self,
+        residuals: &faer::Mat<f64>,
+        jacobians: &faer::sparse::SparseColMat<usize, f64>,
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        let jtj = normal_matrix(jacobians);
+        let jtr = jacobians.as_ref().transpose().mul(-residuals);
+
+        self.solve_jtj(&jtr, &jtj)
+    }
+
+    fn solve_jtj(
+        &mut self,
+        jtr: &faer::Mat<f64>,
+        jtj: &faer::sparse::SparseColMat<usize, f64>,
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        let n = jtj.ncols();
+        let jtj = jtj.as_ref();
+        let preconditioner = self.build_preconditioner(jtj);
+
+        let b = na::DVector::<f64>::from_fn(n, |r, _| jtr[(r, 0)]);
+        let b_norm = b.norm();
+        let mut x = na::DVector::<f64>::zeros(n);
+        if b_norm == 0.0 {
+            return Ok(faer::Mat::zeros(n, 1));
+        }
+        let tolerance = self.relative_tolerance * b_norm;
+
+        let mut r = b;
+        let mut z = preconditioner.apply(&r);
+        let mut p = z.clone();
+        let mut rz = r.dot(&z);
+        for i in 0..self.max_iterations {
+            let ap = sparse_mul(jtj, &p);
+            let pap = p.dot(&ap);
+            if pap <= 0.0 || !pap.is_finite() {
+                if i == 0 {
+                    return Err(TinySolverError::NotPositiveDefinite);
+                }
+                break;
+            }
+            let alpha = rz / pap;
+            x.axpy(alpha, &p, 1.0);
+            r.axpy(-alpha, &ap, 1.0);
+            if r.norm() <= tolerance {
+                log::trace!("conjugate gradient converged in {} iterations", i + 1);
+                break;
+            }
+            z = preconditioner.apply(&r);
+            let rz_new = r.dot(&z);
+            let beta = rz_new / rz;
+            rz = rz_new;
+            p = &z + p * beta;
+        }
+        if x.iter().any(|v| !v.is_finite()) {
+            return Err(TinySolverError::LinearSolverFailed(
+                "conjugate gradient diverged".to_string(),
+            ));
+        }
+        Ok(faer::Mat::from_fn(n, 1, |r, _| x[r]))
+    }
+
+    fn set_relative_tolerance(&mut self, eta: f64) {
+        self.relative_tolerance = eta;
+    }
+}
@@ -1,5 +1,9 @@
+pub mod conjugate_gradient;
+pub mod schur;
 pub mod sparse;
 pub mod sparse_cholesky;
 pub mod sparse_qr;
+pub use conjugate_gradient::*;
+pub use schur::*;
 pub use sparse_cholesky::*;
 pub use sparse_qr::*;
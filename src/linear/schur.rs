@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::ops::Mul;
+
+use faer::linalg::solvers::Solve;
+use faer::sparse::Triplet;
+use faer::sparse::linalg::solvers;
+use nalgebra as na;
+use rayon::prelude::*;
+
+use super::sparse::{SparseLinearSolver, normal_matrix};
+use crate::error::TinySolverError;
+
+/// Solves the normal equations by eliminating the column blocks in
+/// `eliminate_blocks` first (e.g. the landmarks in bundle adjustment).
+///
+/// With H = [[H_ee, H_ec], [H_ce, H_cc]] and H_ee block diagonal, the reduced
+/// system S = H_cc - H_ce * H_ee^-1 * H_ec is solved for the remaining columns
+/// and the eliminated columns are recovered by back substitution.
+/// https://github.com/ceres-solver/ceres-solver/blob/master/internal/ceres/schur_complement_solver.cc
+#[derive(Debug, Clone)]
+pub struct SchurComplementSolver {
+    /// (start column, size) of every eliminated block.
+    eliminate_blocks: Vec<(usize, usize)>,
+    dense: bool,
+    symbolic_pattern: Option<solvers::SymbolicLlt<usize>>,
+}
+
+struct EliminatedBlock {
+    start: usize,
+    hee: na::DMatrix<f64>,
+    /// reduced column -> the k values of H_ec in that column
+    hec: BTreeMap<usize, na::DVector<f64>>,
+}
+
+impl SchurComplementSolver {
+    /// Solve the reduced camera system with a dense Cholesky, like Ceres' `DENSE_SCHUR`.
+    pub fn new_dense(eliminate_blocks: Vec<(usize, usize)>) -> Self {
+        SchurComplementSolver {
+            eliminate_blocks,
+            dense: true,
+            symbolic_pattern: None,
+        }
+    }
+    /// Solve the reduced camera system with a sparse Cholesky, like Ceres' `SPARSE_SCHUR`.
+    pub fn new_sparse(eliminate_blocks: Vec<(usize, usize)>) -> Self {
+        SchurComplementSolver {
+            eliminate_blocks,
+            dense: false,
+            symbolic_pattern: None,
+        }
+    }
+
+    fn solve_reduced_dense(
+        &self,
+        triplets: &[Triplet<usize, usize, f64>],
+        rhs: &na::DVector<f64>,
+    ) -> Result<na::DVector<f64>, TinySolverError> {
+        let n = rhs.nrows();
+        let mut s = na::DMatrix::<f64>::zeros(n, n);
+        for t in triplets {
+            s[(t.row, t.col)] += t.val;
+        }
+        s.cholesky()
+            .map(|llt| llt.solve(rhs))
+            .ok_or(TinySolverError::NotPositiveDefinite)
+    }
+
+    fn solve_reduced_sparse(
+        &mut self,
+        triplets: &[Triplet<usize, usize, f64>],
+        rhs: &na::DVector<f64>,
+    ) -> Result<na::DVector<f64>, TinySolverError> {
+        let n = rhs.nrows();
+        let s = faer::sparse::SparseColMat::<usize, f64>::try_new_from_triplets(n, n, triplets)
+            .map_err(|e| TinySolverError::LinearSolverFailed(format!("{:?}", e)))?;
+        if self.symbolic_pattern.is_none() {
+            self.symbolic_pattern = Some(
+                solvers::SymbolicLlt::try_new(s.symbolic(), faer::Side::Lower)
+                    .map_err(|e| TinySolverError::LinearSolverFailed(format!("{:?}", e)))?,
+            );
+        }
+        let sym = self.symbolic_pattern.as_ref().unwrap();
+        let llt = solvers::Llt::try_new_with_symbolic(sym.clone(), s.as_ref(), faer::Side::Lower)
+            .map_err(|_| TinySolverError::NotPositiveDefinite)?;
+        let rhs_faer = faer::Mat::<f64>::from_fn(n, 1, |r, _| rhs[r]);
+        let dx = llt.solve(&rhs_faer);
+        Ok(na::DVector::from_fn(n, |r, _| dx[(r, 0)]))
+    }
+}
+
+impl SparseLinearSolver for SchurComplementSolver {
+    fn solve(
+        &mut self,
+        residuals: &faer::Mat<f64>,
+        jacobians: &faer::sparse::SparseColMat<usize, f64>,
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        let jtj = normal_matrix(jacobians);
+        let jtr = jacobians.as_ref().transpose().mul(-residuals);
+
+        self.solve_jtj(&jtr, &jtj)
+    }
+
+    fn solve_jtj(
+        &mut self,
+        jtr: &faer::Mat<f64>,
+        jtj: &faer::sparse::SparseColMat<usize, f64>,
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        let n = jtj.ncols();
+
+        // (block index, local index) of eliminated columns, reduced index of the others
+        let mut eliminated_of_col = vec![None; n];
+        for (block_idx, &(start, size)) in self.eliminate_blocks.iter().enumerate() {
+            for local in 0..size {
+                eliminated_of_col[start + local] = Some((block_idx, local));
+            }
+        }
+        let mut reduced_of_col = vec![usize::MAX; n];
+        let mut col_of_reduced = Vec::new();
+        for c in 0..n {
+            if eliminated_of_col[c].is_none() {
+                reduced_of_col[c] = col_of_reduced.len();
+                col_of_reduced.push(c);
+            }
+        }
+        let num_reduced = col_of_reduced.len();
+
+        let mut blocks: Vec<EliminatedBlock> = self
+            .eliminate_blocks
+            .iter()
+            .map(|&(start, size)| EliminatedBlock {
+                start,
+                hee: na::DMatrix::zeros(size, size),
+                hec: BTreeMap::new(),
+            })
+            .collect();
+        let mut triplets = Vec::new();
+        let jtj = jtj.as_ref();
+        for c in 0..n {
+            let rows = jtj.row_idx_of_col(c);
+            let values = jtj.val_of_col(c);
+            match eliminated_of_col[c] {
+                Some((block_idx, local_col)) => {
+                    for (r, &v) in rows.zip(values) {
+                        match eliminated_of_col[r] {
+                            Some((row_block_idx, local_row)) if row_block_idx == block_idx => {
+                                blocks[block_idx].hee[(local_row, local_col)] = v;
+                            }
+                            Some(_) => {
+                                return Err(TinySolverError::LinearSolverFailed(format!(
+                                    "eliminated columns {} and {} are coupled, H_ee is not block diagonal",
+                                    r, c
+                                )));
+                            }
+                            None => {
+                                let size = self.eliminate_blocks[block_idx].1;
+                                blocks[block_idx]
+                                    .hec
+                                    .entry(reduced_of_col[r])
+                                    .or_insert_with(|| na::DVector::zeros(size))[local_col] = v;
+                            }
+                        }
+                    }
+                }
+                None => {
+                    for (r, &v) in rows.zip(values) {
+                        if eliminated_of_col[r].is_none() {
+                            triplets.push(Triplet::new(reduced_of_col[r], reduced_of_col[c], v));
+                        }
+                    }
+                }
+            }
+        }
+
+        let b = na::DVector::<f64>::from_fn(n, |r, _| jtr[(r, 0)]);
+        let mut reduced_rhs = na::DVector::from_fn(num_reduced, |r, _| b[col_of_reduced[r]]);
+
+        // H_ee^-1 of every block, and its contribution to the reduced system
+        let eliminated: Result<Vec<_>, TinySolverError> = blocks
+            .par_iter()
+            .map(|block| {
+                let size = block.hee.nrows();
+                let hee_inv = block
+                    .hee
+                    .clone()
+                    .cholesky()
+                    .ok_or(TinySolverError::NotPositiveDefinite)?
+                    .inverse();
+                let be = b.rows(block.start, size);
+                let hinv_be = &hee_inv * be;
+                let cols: Vec<usize> = block.hec.keys().copied().collect();
+                let hec = na::DMatrix::from_fn(size, cols.len(), |r, c| block.hec[&cols[c]][r]);
+                let hinv_hec = &hee_inv * &hec;
+                let s_update = hec.transpose() * &hinv_hec;
+                let rhs_update = hec.transpose() * &hinv_be;
+                let mut block_triplets = Vec::with_capacity(cols.len() * cols.len());
+                for (i, &ci) in cols.iter().enumerate() {
+                    for (j, &cj) in cols.iter().enumerate() {
+                        block_triplets.push(Triplet::new(ci, cj, -s_update[(i, j)]));
+                    }
+                }
+                Ok((hee_inv, block_triplets, cols, rhs_update))
+            })
+            .collect();
+        let eliminated = eliminated?;
+        for (_, block_triplets, cols, rhs_update) in &eliminated {
+            triplets.extend_from_slice(block_triplets);
+            for (i, &ci) in cols.iter().enumerate() {
+                reduced_rhs[ci] -= rhs_update[i];
+            }
+        }
+        // keep the pattern of the reduced system stable when a block has no coupling
+        for i in 0..num_reduced {
+            triplets.push(Triplet::new(i, i, 0.0));
+        }
+
+        let dx_reduced = if num_reduced == 0 {
+            na::DVector::zeros(0)
+        } else if self.dense {
+            self.solve_reduced_dense(&triplets, &reduced_rhs)?
+        } else {
+            self.solve_reduced_sparse(&triplets, &reduced_rhs)?
+        };
+
+        // back substitution dx_e = H_ee^-1 * (b_e - H_ec * dx_c)
+        let mut dx = faer::Mat::<f64>::zeros(n, 1);
+        for (r, &c) in col_of_reduced.iter().enumerate() {
+            dx[(c, 0)] = dx_reduced[r];
+        }
+        for (block, (hee_inv, _, cols, _)) in blocks.iter().zip(eliminated.iter()) {
+            let size = block.hee.nrows();
+            let mut rhs = b.rows(block.start, size).clone_owned();
+            for (c, hec_col) in &block.hec {
+                rhs -= hec_col * dx_reduced[*c];
+            }
+            debug_assert_eq!(cols.len(), block.hec.len());
+            let dx_e = hee_inv * rhs;
+            for i in 0..size {
+                dx[(block.start + i, 0)] = dx_e[i];
+            }
+        }
+        Ok(dx)
+    }
+}
@@ -1,8 +1,19 @@
-#[derive(Default, Clone)]
+use std::ops::Mul;
+
+use super::conjugate_gradient::PreconditionerType;
+use crate::error::TinySolverError;
+
+#[derive(Default, Clone, Debug, PartialEq)]
 pub enum LinearSolverType {
     #[default]
     SparseCholesky,
     SparseQR,
+    /// Schur complement with a dense Cholesky on the reduced system.
+    DenseSchur,
+    /// Schur complement with a sparse Cholesky on the reduced system.
+    SparseSchur,
+    /// Inexact solve with preconditioned conjugate gradients.
+    ConjugateGradient(PreconditionerType),
 }
 
 pub trait SparseLinearSolver {
@@ -10,10 +21,26 @@ pub trait SparseLinearSolver {
         &mut self,
         residuals: &faer::Mat<f64>,
         jacobians: &faer::sparse::SparseColMat<usize, f64>,
-    ) -> Option<faer::Mat<f64>>;
+    ) -> Result<faer::Mat<f64>, TinySolverError>;
     fn solve_jtj(
         &mut self,
         jtr: &faer::Mat<f64>,
         jtj: &faer::sparse::SparseColMat<usize, f64>,
-    ) -> Option<faer::Mat<f64>>;
+    ) -> Result<faer::Mat<f64>, TinySolverError>;
+    /// Relative residual |A * x - b| / |b| at which iterative solvers stop.
+    /// Direct solvers ignore it.
+    fn set_relative_tolerance(&mut self, _eta: f64) {}
+}
+
+/// J^T * J, the matrix of the normal equations, shared by the solvers, the
+/// optimizers and the covariance estimation.
+pub(crate) fn normal_matrix(
+    jacobian: &faer::sparse::SparseColMat<usize, f64>,
+) -> faer::sparse::SparseColMat<usize, f64> {
+    jacobian
+        .as_ref()
+        .transpose()
+        .to_col_major()
+        .unwrap()
+        .mul(jacobian.as_ref())
 }
@@ -4,7 +4,8 @@ use std::ops::Mul;
 use faer::linalg::solvers::Solve;
 use faer::sparse::linalg::solvers;
 
-use super::sparse::SparseLinearSolver;
+use super::sparse::{SparseLinearSolver, normal_matrix};
+use crate::error::TinySolverError;
 
 // #[pyclass]
 #[derive(Debug, Clone)]
@@ -29,13 +30,8 @@ impl SparseLinearSolver for SparseCholeskySolver {
         &mut self,
         residuals: &faer::Mat<f64>,
         jacobians: &faer::sparse::SparseColMat<usize, f64>,
-    ) -> Option<faer::Mat<f64>> {
-        let jtj = jacobians
-            .as_ref()
-            .transpose()
-            .to_col_major()
-            .unwrap()
-            .mul(jacobians.as_ref());
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        let jtj = normal_matrix(jacobians);
         let jtr = jacobians.as_ref().transpose().mul(-residuals);
 
         self.solve_jtj(&jtr, &jtj)
@@ -45,22 +41,19 @@ impl SparseLinearSolver for SparseCholeskySolver {
         &mut self,
         jtr: &faer::Mat<f64>,
         jtj: &faer::sparse::SparseColMat<usize, f64>,
-    ) -> Option<faer::Mat<f64>> {
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
         // initialize the pattern
         if self.symbolic_pattern.is_none() {
-            self.symbolic_pattern =
-                Some(solvers::SymbolicLlt::try_new(jtj.symbolic(), faer::Side::Lower).unwrap());
+            self.symbolic_pattern = Some(
+                solvers::SymbolicLlt::try_new(jtj.symbolic(), faer::Side::Lower)
+                    .map_err(|e| TinySolverError::LinearSolverFailed(format!("{:?}", e)))?,
+            );
         }
 
         let sym = self.symbolic_pattern.as_ref().unwrap();
-        if let Ok(cholesky) =
+        let cholesky =
             solvers::Llt::try_new_with_symbolic(sym.clone(), jtj.as_ref(), faer::Side::Lower)
-        {
-            let dx = cholesky.solve(jtr);
-
-            Some(dx)
-        } else {
-            None
-        }
+                .map_err(|_| TinySolverError::NotPositiveDefinite)?;
+        Ok(cholesky.solve(jtr))
     }
 }
@@ -1,4 +1,5 @@
 use super::sparse::SparseLinearSolver;
+use crate::error::TinySolverError;
 use faer::linalg::solvers::SolveLstsqCore;
 // use faer::prelude::{SpSolver, SpSolverLstsq};
 use faer::sparse::linalg::solvers;
@@ -15,6 +16,22 @@ impl SparseQRSolver {
         }
     }
 }
+impl SparseQRSolver {
+    fn factorize(
+        &mut self,
+        mat: &faer::sparse::SparseColMat<usize, f64>,
+    ) -> Result<solvers::Qr<usize, f64>, TinySolverError> {
+        if self.symbolic_pattern.is_none() {
+            self.symbolic_pattern = Some(
+                solvers::SymbolicQr::try_new(mat.symbolic())
+                    .map_err(|e| TinySolverError::LinearSolverFailed(format!("{:?}", e)))?,
+            );
+        }
+        let sym = self.symbolic_pattern.as_ref().unwrap();
+        solvers::Qr::try_new_with_symbolic(sym.clone(), mat.as_ref())
+            .map_err(|e| TinySolverError::LinearSolverFailed(format!("{:?}", e)))
+    }
+}
 impl Default for SparseQRSolver {
     fn default() -> Self {
         Self::new()
@@ -25,38 +42,21 @@ impl SparseLinearSolver for SparseQRSolver {
         &mut self,
         residuals: &faer::Mat<f64>,
         jacobians: &faer::sparse::SparseColMat<usize, f64>,
-    ) -> Option<faer::Mat<f64>> {
-        if self.symbolic_pattern.is_none() {
-            self.symbolic_pattern =
-                Some(solvers::SymbolicQr::try_new(jacobians.symbolic()).unwrap());
-        }
-
-        let sym = self.symbolic_pattern.as_ref().unwrap();
-        if let Ok(qr) = solvers::Qr::try_new_with_symbolic(sym.clone(), jacobians.as_ref()) {
-            let mut minus_residuals = -residuals;
-            qr.solve_lstsq_in_place_with_conj(faer::Conj::No, minus_residuals.as_mut());
-            Some(minus_residuals)
-        } else {
-            None
-        }
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        let qr = self.factorize(jacobians)?;
+        let mut minus_residuals = -residuals;
+        qr.solve_lstsq_in_place_with_conj(faer::Conj::No, minus_residuals.as_mut());
+        Ok(minus_residuals)
     }
 
     fn solve_jtj(
         &mut self,
         jtr: &faer::Mat<f64>,
         jtj: &faer::sparse::SparseColMat<usize, f64>,
-    ) -> Option<faer::Mat<f64>> {
-        if self.symbolic_pattern.is_none() {
-            self.symbolic_pattern = Some(solvers::SymbolicQr::try_new(jtj.symbolic()).unwrap());
-        }
-
-        let sym = self.symbolic_pattern.as_ref().unwrap();
-        if let Ok(qr) = solvers::Qr::try_new_with_symbolic(sym.clone(), jtj.as_ref()) {
-            let mut minus_jtr = -jtr;
-            qr.solve_lstsq_in_place_with_conj(faer::Conj::No, minus_jtr.as_mut());
-            Some(minus_jtr)
-        } else {
-            None
-        }
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
+        let qr = self.factorize(jtj)?;
+        let mut minus_jtr = -jtr;
+        qr.solve_lstsq_in_place_with_conj(faer::Conj::No, minus_jtr.as_mut());
+        Ok(minus_jtr)
     }
 }
@@ -1,27 +1,58 @@
 use core::f64;
 
+use crate::error::TinySolverError;
+
 pub enum LossFunc {
     HuberLoss,
+    CauchyLoss,
+    ArctanLoss,
+    SoftLOneLoss,
+    TukeyLoss,
+    GemanMcClureLoss,
+    WelschLoss,
+    FairLoss,
+    DcsLoss,
+    BarronLoss,
+    ScaledLoss,
+    ComposedLoss,
+    TolerantLoss,
 }
 
+/// Robust loss `rho(s)` of the squared residual norm `s`, scaled such that
+/// `rho(s) ~ s` for small `s`.
 pub trait Loss: Send + Sync {
+    /// `[rho(s), rho'(s), rho''(s)]`
     fn evaluate(&self, s: f64) -> [f64; 3];
 }
 
+fn check_positive(name: &str, parameter: &str, value: f64) -> Result<(), TinySolverError> {
+    if value > 0.0 {
+        Ok(())
+    } else {
+        Err(TinySolverError::InvalidLossParameter(format!(
+            "{} {} needs to be larger than zero, got {}",
+            name, parameter, value
+        )))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HuberLoss {
     scale: f64,
     scale2: f64,
 }
 impl HuberLoss {
-    pub fn new(scale: f64) -> Self {
+    pub fn new(scale: f64) -> Result<Self, TinySolverError> {
         if scale <= 0.0 {
-            panic!("scale needs to be larger than zero");
+            return Err(TinySolverError::InvalidLossParameter(format!(
+                "huber scale needs to be larger than zero, got {}",
+                scale
+            )));
         }
-        HuberLoss {
+        Ok(HuberLoss {
             scale,
             scale2: scale * scale,
-        }
+        })
     }
 }
 
@@ -31,7 +62,7 @@ impl Loss for HuberLoss {
             // Outlier region.
             // 'r' is always positive.
             let r = s.sqrt();
-            let rho1 = (self.scale / r).max(f64::MIN);
+            let rho1 = (self.scale / r).max(f64::MIN_POSITIVE);
             [2.0 * self.scale * r - self.scale2, rho1, -rho1 / (2.0 * s)]
         } else {
             // Inlier region.
@@ -45,12 +76,18 @@ pub struct CauchyLoss {
     c: f64,
 }
 impl CauchyLoss {
-    pub fn new(scale: f64) -> Self {
+    pub fn new(scale: f64) -> Result<Self, TinySolverError> {
+        if scale <= 0.0 {
+            return Err(TinySolverError::InvalidLossParameter(format!(
+                "cauchy scale needs to be larger than zero, got {}",
+                scale
+            )));
+        }
         let scale2 = scale * scale;
-        CauchyLoss {
+        Ok(CauchyLoss {
             scale2,
             c: 1.0 / scale2,
-        }
+        })
     }
 }
 impl Loss for CauchyLoss {
@@ -59,8 +96,8 @@ impl Loss for CauchyLoss {
         let inv = 1.0 / sum;
         // 'sum' and 'inv' are always positive, assuming that 's' is.
         [
-            self.scale2 * sum.log2(),
-            inv.max(f64::MIN),
+            self.scale2 * sum.ln(),
+            inv.max(f64::MIN_POSITIVE),
             -self.c * (inv * inv),
         ]
     }
@@ -72,14 +109,17 @@ pub struct ArctanLoss {
 }
 
 impl ArctanLoss {
-    pub fn new(tolerance: f64) -> Self {
+    pub fn new(tolerance: f64) -> Result<Self, TinySolverError> {
         if tolerance <= 0.0 {
-            panic!("scale needs to be larger than zero");
+            return Err(TinySolverError::InvalidLossParameter(format!(
+                "arctan tolerance needs to be larger than zero, got {}",
+                tolerance
+            )));
         }
-        ArctanLoss {
+        Ok(ArctanLoss {
             tolerance,
             inv_of_squared_tolerance: 1.0 / (tolerance * tolerance),
-        }
+        })
     }
 }
 
@@ -90,8 +130,311 @@ impl Loss for ArctanLoss {
 
         [
             self.tolerance * s.atan2(self.tolerance),
-            inv.max(f64::MIN),
+            inv.max(f64::MIN_POSITIVE),
             -2.0 * s * self.inv_of_squared_tolerance * (inv * inv),
         ]
     }
 }
+
+/// `rho(s) = 2 * a^2 * (sqrt(1 + s / a^2) - 1)`, a smooth approximation of
+/// `HuberLoss`.
+#[derive(Debug, Clone)]
+pub struct SoftLOneLoss {
+    scale2: f64,
+    c: f64,
+}
+impl SoftLOneLoss {
+    pub fn new(scale: f64) -> Result<Self, TinySolverError> {
+        check_positive("soft l1", "scale", scale)?;
+        let scale2 = scale * scale;
+        Ok(SoftLOneLoss {
+            scale2,
+            c: 1.0 / scale2,
+        })
+    }
+}
+impl Loss for SoftLOneLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        let sum = 1.0 + s * self.c;
+        let tmp = sum.sqrt();
+        let rho1 = (1.0 / tmp).max(f64::MIN_POSITIVE);
+        [
+            2.0 * self.scale2 * (tmp - 1.0),
+            rho1,
+            -(self.c * rho1) / (2.0 * sum),
+        ]
+    }
+}
+
+/// Tukey's biweight, `rho(s) = a^2 / 3 * (1 - (1 - s / a^2)^3)` for `s <= a^2`
+/// and constant beyond, so outliers have no influence at all.
+#[derive(Debug, Clone)]
+pub struct TukeyLoss {
+    scale2: f64,
+}
+impl TukeyLoss {
+    pub fn new(scale: f64) -> Result<Self, TinySolverError> {
+        check_positive("tukey", "scale", scale)?;
+        Ok(TukeyLoss {
+            scale2: scale * scale,
+        })
+    }
+}
+impl Loss for TukeyLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        if s <= self.scale2 {
+            // Inlier region.
+            let value = 1.0 - s / self.scale2;
+            let value_sq = value * value;
+            [
+                self.scale2 / 3.0 * (1.0 - value_sq * value),
+                value_sq,
+                -2.0 / self.scale2 * value,
+            ]
+        } else {
+            // Outlier region.
+            [self.scale2 / 3.0, 0.0, 0.0]
+        }
+    }
+}
+
+/// `rho(s) = a^2 * s / (a^2 + s)`, bounded by `a^2`.
+#[derive(Debug, Clone)]
+pub struct GemanMcClureLoss {
+    scale2: f64,
+}
+impl GemanMcClureLoss {
+    pub fn new(scale: f64) -> Result<Self, TinySolverError> {
+        check_positive("geman-mcclure", "scale", scale)?;
+        Ok(GemanMcClureLoss {
+            scale2: scale * scale,
+        })
+    }
+}
+impl Loss for GemanMcClureLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        let sum = self.scale2 + s;
+        let inv = 1.0 / sum;
+        let scale4 = self.scale2 * self.scale2;
+        [
+            self.scale2 * s * inv,
+            (scale4 * inv * inv).max(f64::MIN_POSITIVE),
+            -2.0 * scale4 * inv * inv * inv,
+        ]
+    }
+}
+
+/// `rho(s) = a^2 * (1 - exp(-s / a^2))`, also known as Leclerc.
+#[derive(Debug, Clone)]
+pub struct WelschLoss {
+    scale2: f64,
+}
+impl WelschLoss {
+    pub fn new(scale: f64) -> Result<Self, TinySolverError> {
+        check_positive("welsch", "scale", scale)?;
+        Ok(WelschLoss {
+            scale2: scale * scale,
+        })
+    }
+}
+impl Loss for WelschLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        let e = (-s / self.scale2).exp();
+        [
+            self.scale2 * (1.0 - e),
+            e.max(f64::MIN_POSITIVE),
+            -e / self.scale2,
+        ]
+    }
+}
+
+/// `rho(s) = 2 * a^2 * (r / a - ln(1 + r / a))` with `r = sqrt(s)`, convex
+/// and with continuous derivatives of all orders in `r`.
+#[derive(Debug, Clone)]
+pub struct FairLoss {
+    scale: f64,
+}
+impl FairLoss {
+    pub fn new(scale: f64) -> Result<Self, TinySolverError> {
+        check_positive("fair", "scale", scale)?;
+        Ok(FairLoss { scale })
+    }
+}
+impl Loss for FairLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        if s == 0.0 {
+            // rho'' is unbounded at zero but s * rho'' vanishes
+            return [0.0, 1.0, 0.0];
+        }
+        let r = s.sqrt();
+        let x = r / self.scale;
+        let inv = 1.0 / (1.0 + x);
+        [
+            2.0 * self.scale * self.scale * (x - x.ln_1p()),
+            inv,
+            -inv * inv / (2.0 * self.scale * r),
+        ]
+    }
+}
+
+/// Dynamic covariance scaling (Agarwal et al., ICRA 2013), which scales the
+/// residual by `min(1, 2 * phi / (phi + s))`. As a loss this is `rho(s) = s`
+/// up to `phi` and `3 * phi - 4 * phi^2 / (phi + s)` beyond, whose weights
+/// `rho'(s)` are the squared scaling factors.
+#[derive(Debug, Clone)]
+pub struct DcsLoss {
+    phi: f64,
+}
+impl DcsLoss {
+    pub fn new(phi: f64) -> Result<Self, TinySolverError> {
+        check_positive("dcs", "phi", phi)?;
+        Ok(DcsLoss { phi })
+    }
+}
+impl Loss for DcsLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        if s <= self.phi {
+            return [s, 1.0, 0.0];
+        }
+        let phi = self.phi;
+        let inv = 1.0 / (phi + s);
+        [
+            3.0 * phi - 4.0 * phi * phi * inv,
+            (4.0 * phi * phi * inv * inv).max(f64::MIN_POSITIVE),
+            -8.0 * phi * phi * inv * inv * inv,
+        ]
+    }
+}
+
+/// Barron's general and adaptive loss (CVPR 2019) with shape `alpha` and
+/// scale `c`,
+/// `rho(s) = 2 * c^2 * |alpha - 2| / alpha * ((1 + s / (c^2 * |alpha - 2|))^(alpha / 2) - 1)`.
+/// `alpha = 2` is the squared loss, `1` is `SoftLOneLoss`, `0` is Cauchy,
+/// `-2` is Geman-McClure and `-inf` is Welsch.
+#[derive(Debug, Clone)]
+pub struct BarronLoss {
+    alpha: f64,
+    scale2: f64,
+}
+impl BarronLoss {
+    pub fn new(alpha: f64, scale: f64) -> Result<Self, TinySolverError> {
+        check_positive("barron", "scale", scale)?;
+        if alpha.is_nan() || alpha == f64::INFINITY {
+            return Err(TinySolverError::InvalidLossParameter(format!(
+                "barron alpha needs to be a number below infinity, got {}",
+                alpha
+            )));
+        }
+        Ok(BarronLoss {
+            alpha,
+            scale2: scale * scale,
+        })
+    }
+}
+impl Loss for BarronLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        let alpha = self.alpha;
+        if alpha == 2.0 {
+            [s, 1.0, 0.0]
+        } else if alpha == 0.0 {
+            let b = 2.0 * self.scale2;
+            let inv = 1.0 / (1.0 + s / b);
+            [b * (s / b).ln_1p(), inv, -inv * inv / b]
+        } else if alpha == f64::NEG_INFINITY {
+            let b = 2.0 * self.scale2;
+            let e = (-s / b).exp();
+            [b * (1.0 - e), e.max(f64::MIN_POSITIVE), -e / b]
+        } else {
+            let b = (alpha - 2.0).abs();
+            let d = self.scale2 * b;
+            let base = 1.0 + s / d;
+            let rho1 = base.powf(0.5 * alpha - 1.0);
+            [
+                2.0 * d / alpha * (base.powf(0.5 * alpha) - 1.0),
+                rho1.max(f64::MIN_POSITIVE),
+                (0.5 * alpha - 1.0) * rho1 / (base * d),
+            ]
+        }
+    }
+}
+
+/// `a * rho(s)`, or `a * s` without a loss, e.g. to weight residual blocks.
+pub struct ScaledLoss {
+    loss: Option<Box<dyn Loss>>,
+    scale: f64,
+}
+impl ScaledLoss {
+    pub fn new(loss: Option<Box<dyn Loss>>, scale: f64) -> Result<Self, TinySolverError> {
+        check_positive("scaled loss", "scale", scale)?;
+        Ok(ScaledLoss { loss, scale })
+    }
+}
+impl Loss for ScaledLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        match &self.loss {
+            Some(loss) => loss.evaluate(s).map(|rho| rho * self.scale),
+            None => [self.scale * s, self.scale, 0.0],
+        }
+    }
+}
+
+/// `f(g(s))`, e.g. a `TolerantLoss` on top of a `CauchyLoss`.
+pub struct ComposedLoss {
+    f: Box<dyn Loss>,
+    g: Box<dyn Loss>,
+}
+impl ComposedLoss {
+    pub fn new(f: Box<dyn Loss>, g: Box<dyn Loss>) -> Self {
+        ComposedLoss { f, g }
+    }
+}
+impl Loss for ComposedLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        let g = self.g.evaluate(s);
+        let f = self.f.evaluate(g[0]);
+        [f[0], f[1] * g[1], f[2] * g[1] * g[1] + f[1] * g[2]]
+    }
+}
+
+/// `rho(s) = b * ln(1 + exp((s - a) / b)) - b * ln(1 + exp(-a / b))`, which
+/// ignores residuals up to about `s = a` and grows like `s` beyond. `b`
+/// controls how fast the transition is.
+#[derive(Debug, Clone)]
+pub struct TolerantLoss {
+    a: f64,
+    b: f64,
+    c: f64,
+}
+impl TolerantLoss {
+    pub fn new(a: f64, b: f64) -> Result<Self, TinySolverError> {
+        if a < 0.0 {
+            return Err(TinySolverError::InvalidLossParameter(format!(
+                "tolerant loss a needs to be non-negative, got {}",
+                a
+            )));
+        }
+        check_positive("tolerant loss", "b", b)?;
+        Ok(TolerantLoss {
+            a,
+            b,
+            c: b * (-a / b).exp().ln_1p(),
+        })
+    }
+}
+impl Loss for TolerantLoss {
+    fn evaluate(&self, s: f64) -> [f64; 3] {
+        let x = (s - self.a) / self.b;
+        // 1 + e^x == e^x in double precision beyond ln(2^53)
+        const LOG_2_POW_53: f64 = 36.7;
+        if x > LOG_2_POW_53 {
+            [s - self.a - self.c, 1.0, 0.0]
+        } else {
+            let e_x = x.exp();
+            [
+                self.b * e_x.ln_1p() - self.c,
+                (e_x / (1.0 + e_x)).max(f64::MIN_POSITIVE),
+                0.5 / (self.b * (1.0 + x.cosh())),
+            ]
+        }
+    }
+}
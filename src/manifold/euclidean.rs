@@ -0,0 +1,48 @@
+use std::num::NonZero;
+
+use nalgebra as na;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold};
+
+/// Plain vectors in `R^n`, for the Euclidean parts of a `ProductManifold`.
+#[derive(Debug, Clone)]
+pub struct EuclideanManifold {
+    size: usize,
+}
+
+impl EuclideanManifold {
+    /// Panics if `size` is zero.
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "EuclideanManifold needs a positive size");
+        EuclideanManifold { size }
+    }
+}
+
+impl<T: na::RealField> AutoDiffManifold<T> for EuclideanManifold {
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        x + delta
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        y - x
+    }
+}
+impl Manifold for EuclideanManifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(self.size).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(self.size).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
+}
@@ -0,0 +1,92 @@
+use std::num::NonZero;
+
+use nalgebra as na;
+
+use super::sphere::{apply_householder, householder, sphere_minus, sphere_plus};
+use super::{AutoDiffManifold, Manifold, StaticDualManifold};
+
+/// 3D lines in Plücker coordinates `[d, m]` with the direction `d` and the
+/// moment `m = o x d` for any point `o` on the line. The tangent space is
+/// ordered `[delta_d, delta_o]`: `delta_d` rotates the direction as on
+/// `SphereManifold`, and `delta_o` moves the closest point to the origin in
+/// the plane orthogonal to `d`, which is then carried along with the
+/// rotation of the direction. `plus` keeps the norm of `d`.
+#[derive(Debug, Clone)]
+pub struct LineManifold;
+
+/// Direction and the closest point of the line to the origin.
+fn direction_and_origin<T: na::RealField>(
+    x: na::DVectorView<T>,
+) -> (na::Vector3<T>, na::Vector3<T>) {
+    let d = na::Vector3::new(x[0].clone(), x[1].clone(), x[2].clone());
+    let m = na::Vector3::new(x[3].clone(), x[4].clone(), x[5].clone());
+    let o = d.cross(&m) / d.norm_squared();
+    (d, o)
+}
+
+/// Smallest rotation taking the direction of `a` to the direction of `b`.
+fn rotation_between<T: na::RealField>(a: &na::Vector3<T>, b: &na::Vector3<T>) -> na::Matrix3<T> {
+    let a = a.normalize();
+    let b = b.normalize();
+    let k_hat = a.cross(&b).cross_matrix();
+    let c = a.dot(&b);
+    na::Matrix3::identity() + &k_hat + &k_hat * &k_hat / (T::one() + c)
+}
+
+fn to_pluecker<T: na::RealField>(d: &na::Vector3<T>, o: &na::Vector3<T>) -> na::DVector<T> {
+    let m = o.cross(d);
+    na::DVector::from_iterator(6, d.iter().chain(m.iter()).cloned())
+}
+
+impl<T: na::RealField> AutoDiffManifold<T> for LineManifold {
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        let (d, o) = direction_and_origin(x);
+        let d_dyn = na::DVector::from_column_slice(d.as_slice());
+        let d_plus = sphere_plus(d_dyn.as_view(), delta.rows(0, 2));
+        let d_plus = na::Vector3::new(d_plus[0].clone(), d_plus[1].clone(), d_plus[2].clone());
+
+        let (v, beta) = householder(d_dyn.as_view());
+        let delta_o = na::dvector![delta[2].clone(), delta[3].clone(), T::zero()];
+        let o_moved = o + apply_householder(delta_o.as_view(), &v, beta).fixed_rows::<3>(0);
+        let o_plus = rotation_between(&d, &d_plus) * o_moved;
+        to_pluecker(&d_plus, &o_plus)
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        let (d_x, o_x) = direction_and_origin(x);
+        let (d_y, o_y) = direction_and_origin(y);
+        let d_x_dyn = na::DVector::from_column_slice(d_x.as_slice());
+        let d_y_dyn = na::DVector::from_column_slice(d_y.as_slice());
+        let delta_d = sphere_minus(d_y_dyn.as_view(), d_x_dyn.as_view());
+
+        let o_moved = rotation_between(&d_x, &d_y).transpose() * o_y - o_x;
+        let o_moved = na::DVector::from_column_slice(o_moved.as_slice());
+        let (v, beta) = householder(d_x_dyn.as_view());
+        let delta_o = apply_householder(o_moved.as_view(), &v, beta);
+        na::dvector![
+            delta_d[0].clone(),
+            delta_d[1].clone(),
+            delta_o[0].clone(),
+            delta_o[1].clone()
+        ]
+    }
+}
+impl Manifold for LineManifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(4).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(6).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
+}
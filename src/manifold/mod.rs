@@ -1,18 +1,36 @@
 use std::num::NonZero;
 
 use nalgebra as na;
-use num_dual::DualDVec64;
+use num_dual::{DualDVec64, DualSVec64};
 
+pub mod euclidean;
+pub mod line;
+pub mod product;
+pub mod se2;
+pub mod se23;
 pub mod se3;
+pub mod sim3;
+pub mod so2;
 pub mod so3;
+pub mod sphere;
+pub mod subset;
 
 pub trait AutoDiffManifold<T: na::RealField> {
     fn plus(&self, x: na::DVectorView<T>, delta: na::DVectorView<T>) -> na::DVector<T>;
     fn minus(&self, y: na::DVectorView<T>, x: na::DVectorView<T>) -> na::DVector<T>;
 }
 
-pub trait Manifold: AutoDiffManifold<f64> + AutoDiffManifold<num_dual::DualDVec64> {
+pub trait Manifold: AutoDiffManifold<f64> + AutoDiffManifold<DualDVec64> {
     fn tangent_size(&self) -> NonZero<usize>;
+    /// Size of the parameter vector, e.g. 4 for a quaternion. Required
+    /// since 0.19, `Problem::validate` checks the values against it.
+    fn ambient_size(&self) -> NonZero<usize>;
+    /// `Some(self)` to differentiate `factors::StaticDual` factors with
+    /// statically sized dual numbers on this manifold, see
+    /// `StaticDualManifold`. With `None` they use `DualDVec64`.
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        None
+    }
     fn plus_f64(&self, x: na::DVectorView<f64>, delta: na::DVectorView<f64>) -> na::DVector<f64> {
         self.plus(x, delta)
     }
@@ -34,3 +52,22 @@ pub trait Manifold: AutoDiffManifold<f64> + AutoDiffManifold<num_dual::DualDVec6
         self.minus(y, x)
     }
 }
+
+/// A manifold that also supports the statically sized dual numbers of
+/// `factors::STATIC_DUAL_SIZES`, which is automatic for manifolds
+/// implemented for any `na::RealField`.
+pub trait StaticDualManifold:
+    AutoDiffManifold<DualSVec64<4>>
+    + AutoDiffManifold<DualSVec64<8>>
+    + AutoDiffManifold<DualSVec64<12>>
+    + AutoDiffManifold<DualSVec64<16>>
+{
+}
+
+impl<M> StaticDualManifold for M where
+    M: AutoDiffManifold<DualSVec64<4>>
+        + AutoDiffManifold<DualSVec64<8>>
+        + AutoDiffManifold<DualSVec64<12>>
+        + AutoDiffManifold<DualSVec64<16>>
+{
+}
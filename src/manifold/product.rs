@@ -0,0 +1,172 @@
+use std::{num::NonZero, sync::Arc};
+
+use nalgebra as na;
+use num_dual::DualSVec64;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold};
+
+/// Cartesian product of manifolds, so that one parameter block can hold e.g.
+/// `[quaternion, translation, velocity, bias]`. The ambient and the tangent
+/// vectors are the concatenations of those of the parts, in order.
+///
+/// ```
+/// use std::sync::Arc;
+/// use tiny_solver::manifold::Manifold;
+/// use tiny_solver::manifold::euclidean::EuclideanManifold;
+/// use tiny_solver::manifold::product::ProductManifold;
+/// use tiny_solver::manifold::so3::QuaternionManifold;
+///
+/// let manifold = ProductManifold::new(vec![
+///     Arc::new(QuaternionManifold),
+///     Arc::new(EuclideanManifold::new(3)),
+///     Arc::new(EuclideanManifold::new(3)),
+///     Arc::new(EuclideanManifold::new(6)),
+/// ]);
+/// assert_eq!(manifold.ambient_size().get(), 16);
+/// assert_eq!(manifold.tangent_size().get(), 15);
+/// ```
+#[derive(Clone)]
+pub struct ProductManifold {
+    manifolds: Vec<Arc<dyn Manifold + Sync + Send>>,
+}
+
+impl ProductManifold {
+    /// Panics if `manifolds` is empty.
+    pub fn new(manifolds: Vec<Arc<dyn Manifold + Sync + Send>>) -> Self {
+        assert!(
+            !manifolds.is_empty(),
+            "ProductManifold needs at least one manifold"
+        );
+        ProductManifold { manifolds }
+    }
+
+    pub fn manifolds(&self) -> &[Arc<dyn Manifold + Sync + Send>] {
+        &self.manifolds
+    }
+
+    /// Applies `f` to each part, with the parts of the first argument split
+    /// by `first_size` and those of the second by `second_size`.
+    fn map_parts<T: na::RealField>(
+        &self,
+        first: na::DVectorView<T>,
+        second: na::DVectorView<T>,
+        first_size: impl Fn(&dyn Manifold) -> usize,
+        second_size: impl Fn(&dyn Manifold) -> usize,
+        out_size: usize,
+        f: impl Fn(
+            &(dyn Manifold + Sync + Send + 'static),
+            na::DVectorView<T>,
+            na::DVectorView<T>,
+        ) -> na::DVector<T>,
+    ) -> na::DVector<T> {
+        let mut out = na::DVector::zeros(out_size);
+        let (mut first_start, mut second_start, mut out_start) = (0, 0, 0);
+        for manifold in &self.manifolds {
+            let first_len = first_size(manifold.as_ref());
+            let second_len = second_size(manifold.as_ref());
+            let part = f(
+                manifold.as_ref(),
+                first.rows(first_start, first_len),
+                second.rows(second_start, second_len),
+            );
+            out.rows_mut(out_start, part.len()).copy_from(&part);
+            first_start += first_len;
+            second_start += second_len;
+            out_start += part.len();
+        }
+        out
+    }
+}
+
+impl<T: na::RealField> AutoDiffManifold<T> for ProductManifold
+where
+    dyn Manifold + Sync + Send: AutoDiffManifold<T>,
+{
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        self.map_parts(
+            x,
+            delta,
+            |m| m.ambient_size().get(),
+            |m| m.tangent_size().get(),
+            self.ambient_size().get(),
+            |m, x, delta| m.plus(x, delta),
+        )
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        self.map_parts(
+            y,
+            x,
+            |m| m.ambient_size().get(),
+            |m| m.ambient_size().get(),
+            self.tangent_size().get(),
+            |m, y, x| m.minus(y, x),
+        )
+    }
+}
+
+/// Panics if a part does not support the statically sized dual numbers,
+/// `as_static_dual` returns `None` in that case.
+impl<const N: usize> AutoDiffManifold<DualSVec64<N>> for ProductManifold
+where
+    dyn StaticDualManifold: AutoDiffManifold<DualSVec64<N>>,
+{
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<DualSVec64<N>>,
+        delta: nalgebra::DVectorView<DualSVec64<N>>,
+    ) -> nalgebra::DVector<DualSVec64<N>> {
+        self.map_parts(
+            x,
+            delta,
+            |m| m.ambient_size().get(),
+            |m| m.tangent_size().get(),
+            self.ambient_size().get(),
+            |m, x, delta| static_part(m).plus(x, delta),
+        )
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<DualSVec64<N>>,
+        x: nalgebra::DVectorView<DualSVec64<N>>,
+    ) -> nalgebra::DVector<DualSVec64<N>> {
+        self.map_parts(
+            y,
+            x,
+            |m| m.ambient_size().get(),
+            |m| m.ambient_size().get(),
+            self.tangent_size().get(),
+            |m, y, x| static_part(m).minus(y, x),
+        )
+    }
+}
+
+fn static_part(manifold: &dyn Manifold) -> &(dyn StaticDualManifold + 'static) {
+    manifold
+        .as_static_dual()
+        .expect("the part does not support statically sized dual numbers")
+}
+
+impl Manifold for ProductManifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(self.manifolds.iter().map(|m| m.tangent_size().get()).sum()).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(self.manifolds.iter().map(|m| m.ambient_size().get()).sum()).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        self.manifolds
+            .iter()
+            .all(|m| m.as_static_dual().is_some())
+            .then_some(self)
+    }
+}
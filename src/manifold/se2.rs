@@ -0,0 +1,182 @@
+use std::{num::NonZero, ops::Mul};
+
+use nalgebra as na;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold, so2::wrap_angle};
+
+/// Rigid transformation in 2D, stored as `[theta, x, y]` with the angle
+/// wrapped to `(-pi, pi]`. The tangent space is ordered the same way,
+/// `[theta, rho_x, rho_y]`.
+#[derive(Debug, Clone)]
+pub struct SE2<T: na::RealField> {
+    pub theta: T,
+    pub xy: na::Vector2<T>,
+}
+
+impl<T: na::RealField> SE2<T> {
+    pub fn new(theta: T, x: T, y: T) -> Self {
+        SE2 {
+            theta: wrap_angle(theta),
+            xy: na::Vector2::new(x, y),
+        }
+    }
+    /// [theta, x, y]
+    pub fn from_vec(theta_xy: na::DVectorView<T>) -> Self {
+        SE2::new(
+            theta_xy[0].clone(),
+            theta_xy[1].clone(),
+            theta_xy[2].clone(),
+        )
+    }
+    pub fn identity() -> Self {
+        SE2 {
+            theta: T::zero(),
+            xy: na::Vector2::zeros(),
+        }
+    }
+
+    pub fn exp(xi: na::DVectorView<T>) -> Self {
+        let theta = xi[0].clone();
+        let rho = na::Vector2::new(xi[1].clone(), xi[2].clone());
+        let xy = left_jacobian(theta.clone()) * rho;
+        SE2 {
+            theta: wrap_angle(theta),
+            xy,
+        }
+    }
+
+    pub fn log(&self) -> na::DVector<T> {
+        let theta = self.theta.clone();
+        let v = left_jacobian(theta.clone());
+        // V = [[a, -b], [b, a]], V^-1 = [[a, b], [-b, a]] / (a^2 + b^2)
+        let (a, b) = (v[(0, 0)].clone(), v[(1, 0)].clone());
+        let det = a.clone() * a.clone() + b.clone() * b.clone();
+        let x = self.xy[0].clone();
+        let y = self.xy[1].clone();
+        na::dvector![
+            theta,
+            (a.clone() * x.clone() + b.clone() * y.clone()) / det.clone(),
+            (a * y - b * x) / det
+        ]
+    }
+
+    pub fn hat(xi: na::VectorView3<T>) -> na::Matrix3<T> {
+        let mut xi_hat = na::Matrix3::zeros();
+        xi_hat[(0, 1)] = -xi[0].clone();
+        xi_hat[(1, 0)] = xi[0].clone();
+        xi_hat[(0, 2)] = xi[1].clone();
+        xi_hat[(1, 2)] = xi[2].clone();
+        xi_hat
+    }
+
+    pub fn rotation_matrix(&self) -> na::Matrix2<T> {
+        let (sin, cos) = self.theta.clone().sin_cos();
+        na::Matrix2::new(cos.clone(), -sin.clone(), sin, cos)
+    }
+
+    pub fn to_dvec(&self) -> na::DVector<T> {
+        na::dvector![self.theta.clone(), self.xy[0].clone(), self.xy[1].clone()]
+    }
+
+    pub fn cast<U: na::RealField + simba::scalar::SupersetOf<T>>(&self) -> SE2<U> {
+        SE2 {
+            theta: U::from_subset(&self.theta),
+            xy: self.xy.clone().cast(),
+        }
+    }
+    pub fn inverse(&self) -> Self {
+        let xy = -(self.rotation_matrix().transpose() * self.xy.clone());
+        SE2 {
+            theta: -self.theta.clone(),
+            xy,
+        }
+    }
+    pub fn compose(&self, rhs: &Self) -> Self {
+        SE2 {
+            theta: wrap_angle(self.theta.clone() + rhs.theta.clone()),
+            xy: self.rotation_matrix() * rhs.xy.clone() + self.xy.clone(),
+        }
+    }
+}
+
+/// `V(theta)` with `exp([theta, rho]) = (theta, V * rho)`.
+fn left_jacobian<T: na::RealField>(theta: T) -> na::Matrix2<T> {
+    let theta2 = theta.clone() * theta.clone();
+    let (a, b) = if theta2 < T::from_f64(1e-10).unwrap() {
+        (
+            T::one() - theta2.clone() / T::from_f64(6.0).unwrap(),
+            theta.clone() / T::from_f64(2.0).unwrap()
+                - theta.clone() * theta2 / T::from_f64(24.0).unwrap(),
+        )
+    } else {
+        let (sin, cos) = theta.clone().sin_cos();
+        (sin / theta.clone(), (T::one() - cos) / theta)
+    };
+    na::Matrix2::new(a.clone(), -b.clone(), b, a)
+}
+
+impl<T: na::RealField> Mul for SE2<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(&rhs)
+    }
+}
+impl<T: na::RealField> Mul for &SE2<T> {
+    type Output = SE2<T>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(rhs)
+    }
+}
+
+impl<T: na::RealField> Mul<na::VectorView2<'_, T>> for SE2<T> {
+    type Output = na::Vector2<T>;
+
+    fn mul(self, rhs: na::VectorView2<'_, T>) -> Self::Output {
+        &self * rhs
+    }
+}
+
+impl<T: na::RealField> Mul<na::VectorView2<'_, T>> for &SE2<T> {
+    type Output = na::Vector2<T>;
+
+    fn mul(self, rhs: na::VectorView2<'_, T>) -> Self::Output {
+        self.rotation_matrix() * rhs + self.xy.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SE2Manifold;
+impl<T: na::RealField> AutoDiffManifold<T> for SE2Manifold {
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        let d = SE2::exp(delta);
+        let x_se2 = SE2::from_vec(x);
+        (x_se2 * d).to_dvec()
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        let y_se2 = SE2::from_vec(y);
+        let x_se2_inv = SE2::from_vec(x).inverse();
+        (x_se2_inv * y_se2).log()
+    }
+}
+impl Manifold for SE2Manifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(3).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(3).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
+}
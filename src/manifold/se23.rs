@@ -0,0 +1,209 @@
+use std::{num::NonZero, ops::Mul};
+
+use nalgebra as na;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold, se3::q_matrix, so3::SO3};
+
+/// Extended pose `SE_2(3)` of a rotation, a velocity and a position, as used
+/// by IMU preintegration. The tangent space is ordered `[omega, nu, rho]`,
+/// rotation first as in `SE3`.
+pub struct SE23<T: na::RealField> {
+    pub rot: SO3<T>,
+    pub vel: na::Vector3<T>,
+    pub pos: na::Vector3<T>,
+}
+
+impl<T: na::RealField> SE23<T> {
+    /// [qx, qy, qz, qw, vx, vy, vz, px, py, pz]
+    pub fn from_vec(qxyzw_vxyz_pxyz: na::DVectorView<T>) -> Self {
+        let v = qxyzw_vxyz_pxyz;
+        SE23 {
+            rot: SO3::from_vec(v.rows(0, 4).as_view()),
+            vel: na::Vector3::new(v[4].clone(), v[5].clone(), v[6].clone()),
+            pos: na::Vector3::new(v[7].clone(), v[8].clone(), v[9].clone()),
+        }
+    }
+    pub fn identity() -> Self {
+        SE23 {
+            rot: SO3::identity(),
+            vel: na::Vector3::zeros(),
+            pos: na::Vector3::zeros(),
+        }
+    }
+
+    pub fn exp(xi: na::DVectorView<T>) -> Self {
+        let omega = xi.fixed_rows::<3>(0).into_owned();
+        let jl = SO3::left_jacobian(omega.as_view());
+        SE23 {
+            rot: SO3::exp(xi.rows(0, 3)),
+            vel: &jl * xi.fixed_rows::<3>(3),
+            pos: jl * xi.fixed_rows::<3>(6),
+        }
+    }
+
+    pub fn log(&self) -> na::DVector<T> {
+        let omega = na::Vector3::from_iterator(self.rot.log().iter().cloned());
+        let jl_inv = SO3::left_jacobian_inverse(omega.as_view());
+        let nu = &jl_inv * &self.vel;
+        let rho = jl_inv * &self.pos;
+        na::DVector::from_iterator(9, omega.iter().chain(nu.iter()).chain(rho.iter()).cloned())
+    }
+
+    /// 5x5 matrix `[[hat(omega), nu, rho], [0, 0, 0]]` of the Lie algebra.
+    pub fn hat(xi: na::DVectorView<T>) -> na::Matrix5<T> {
+        let mut xi_hat = na::Matrix5::zeros();
+        xi_hat
+            .fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&SO3::hat(xi.fixed_rows::<3>(0).into_owned().as_view()));
+        xi_hat
+            .fixed_view_mut::<3, 1>(0, 3)
+            .copy_from(&xi.fixed_rows::<3>(3));
+        xi_hat
+            .fixed_view_mut::<3, 1>(0, 4)
+            .copy_from(&xi.fixed_rows::<3>(6));
+        xi_hat
+    }
+
+    /// `ad(xi)` with `hat(ad(xi) * eta) = [hat(xi), hat(eta)]`.
+    pub fn small_adjoint(xi: na::DVectorView<T>) -> na::SMatrix<T, 9, 9> {
+        let omega_hat = SO3::hat(xi.fixed_rows::<3>(0).into_owned().as_view());
+        let mut ad = na::SMatrix::<T, 9, 9>::zeros();
+        for i in 0..3 {
+            ad.fixed_view_mut::<3, 3>(3 * i, 3 * i)
+                .copy_from(&omega_hat);
+        }
+        ad.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&SO3::hat(xi.fixed_rows::<3>(3).into_owned().as_view()));
+        ad.fixed_view_mut::<3, 3>(6, 0)
+            .copy_from(&SO3::hat(xi.fixed_rows::<3>(6).into_owned().as_view()));
+        ad
+    }
+
+    /// `Adj(x)` with `x * exp(xi) = exp(Adj(x) * xi) * x`.
+    pub fn adjoint(&self) -> na::SMatrix<T, 9, 9> {
+        let r = self.rot.to_rotation_matrix();
+        let mut adj = na::SMatrix::<T, 9, 9>::zeros();
+        for i in 0..3 {
+            adj.fixed_view_mut::<3, 3>(3 * i, 3 * i).copy_from(&r);
+        }
+        adj.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&(SO3::hat(self.vel.as_view()) * &r));
+        adj.fixed_view_mut::<3, 3>(6, 0)
+            .copy_from(&(SO3::hat(self.pos.as_view()) * &r));
+        adj
+    }
+
+    /// Left Jacobian of `exp`, `exp(xi + d) = exp(left_jacobian(xi) * d) * exp(xi)`
+    /// to first order, in closed form (Barfoot, State Estimation for Robotics).
+    pub fn left_jacobian(xi: na::DVectorView<T>) -> na::SMatrix<T, 9, 9> {
+        let omega = xi.fixed_rows::<3>(0).into_owned();
+        let jl = SO3::left_jacobian(omega.as_view());
+        let mut jacobian = na::SMatrix::<T, 9, 9>::zeros();
+        for i in 0..3 {
+            jacobian.fixed_view_mut::<3, 3>(3 * i, 3 * i).copy_from(&jl);
+        }
+        jacobian.fixed_view_mut::<3, 3>(3, 0).copy_from(&q_matrix(
+            omega.as_view(),
+            xi.fixed_rows::<3>(3).into_owned().as_view(),
+        ));
+        jacobian.fixed_view_mut::<3, 3>(6, 0).copy_from(&q_matrix(
+            omega.as_view(),
+            xi.fixed_rows::<3>(6).into_owned().as_view(),
+        ));
+        jacobian
+    }
+
+    /// Right Jacobian of `exp`, `exp(xi + d) = exp(xi) * exp(right_jacobian(xi) * d)`
+    /// to first order.
+    pub fn right_jacobian(xi: na::DVectorView<T>) -> na::SMatrix<T, 9, 9> {
+        Self::left_jacobian((-xi).as_view())
+    }
+
+    /// 5x5 homogeneous matrix `[[R, v, p], [0, 1, 0], [0, 0, 1]]`.
+    pub fn to_matrix(&self) -> na::Matrix5<T> {
+        let mut m = na::Matrix5::identity();
+        m.fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&self.rot.to_rotation_matrix());
+        m.fixed_view_mut::<3, 1>(0, 3).copy_from(&self.vel);
+        m.fixed_view_mut::<3, 1>(0, 4).copy_from(&self.pos);
+        m
+    }
+
+    pub fn to_dvec(&self) -> na::DVector<T> {
+        let quat = self.rot.to_vec();
+        na::DVector::from_iterator(
+            10,
+            quat.iter()
+                .chain(self.vel.iter())
+                .chain(self.pos.iter())
+                .cloned(),
+        )
+    }
+
+    pub fn cast<U: na::RealField + simba::scalar::SupersetOf<T>>(&self) -> SE23<U> {
+        SE23 {
+            rot: self.rot.cast(),
+            vel: self.vel.clone().cast(),
+            pos: self.pos.clone().cast(),
+        }
+    }
+    pub fn inverse(&self) -> Self {
+        let inv = self.rot.inverse();
+        let vel = -(&inv * self.vel.as_view());
+        let pos = -(&inv * self.pos.as_view());
+        SE23 { rot: inv, vel, pos }
+    }
+    pub fn compose(&self, rhs: &Self) -> Self {
+        SE23 {
+            rot: &self.rot * &rhs.rot,
+            vel: (&self.rot * rhs.vel.as_view()) + self.vel.clone(),
+            pos: (&self.rot * rhs.pos.as_view()) + self.pos.clone(),
+        }
+    }
+}
+
+impl<T: na::RealField> Mul for SE23<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(&rhs)
+    }
+}
+impl<T: na::RealField> Mul for &SE23<T> {
+    type Output = SE23<T>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(rhs)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SE23Manifold;
+impl<T: na::RealField> AutoDiffManifold<T> for SE23Manifold {
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        (SE23::from_vec(x) * SE23::exp(delta)).to_dvec()
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        (SE23::from_vec(x).inverse() * SE23::from_vec(y)).log()
+    }
+}
+impl Manifold for SE23Manifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(9).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(10).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
+}
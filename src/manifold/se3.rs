@@ -2,8 +2,10 @@ use std::{num::NonZero, ops::Mul};
 
 use nalgebra as na;
 
-use super::{AutoDiffManifold, Manifold, so3::SO3};
+use super::{AutoDiffManifold, Manifold, StaticDualManifold, so3::SO3};
 
+/// Rigid transformation in 3D. The tangent space is ordered `[omega, rho]`,
+/// rotation first.
 pub struct SE3<T: na::RealField> {
     pub xyz: na::Vector3<T>,
     pub rot: SO3<T>,
@@ -37,19 +39,16 @@ impl<T: na::RealField> SE3<T> {
     }
 
     pub fn exp(xi: na::DVectorView<T>) -> Self {
-        // fake exp
+        let omega = xi.fixed_rows::<3>(0).into_owned();
         let rot = SO3::<T>::exp(xi.rows(0, 3).as_view());
-        let xyz = na::Vector3::new(xi[3].clone(), xi[4].clone(), xi[5].clone());
+        let xyz = SO3::left_jacobian(omega.as_view()) * xi.fixed_rows::<3>(3);
         SE3 { xyz, rot }
     }
 
     pub fn log(&self) -> na::DVector<T> {
-        let mut xi = na::DVector::zeros(6);
-        let xi_theta = self.rot.log();
-        // let xyz = self.xyz;
-        xi.as_mut_slice()[0..3].clone_from_slice(xi_theta.as_slice());
-        xi.as_mut_slice()[3..6].clone_from_slice(self.xyz.as_slice());
-        xi
+        let omega = na::Vector3::from_iterator(self.rot.log().iter().cloned());
+        let rho = SO3::left_jacobian_inverse(omega.as_view()) * &self.xyz;
+        na::DVector::from_iterator(6, omega.iter().chain(rho.iter()).cloned())
     }
 
     pub fn to_dvec(&self) -> na::DVector<T> {
@@ -65,17 +64,100 @@ impl<T: na::RealField> SE3<T> {
         ]
     }
 
-    // pub fn hat(xi: na::VectorView3<T>) -> na::Matrix3<T> {
-    //     let mut xi_hat = na::Matrix3::zeros();
-    //     xi_hat[(0, 1)] = -xi[2].clone();
-    //     xi_hat[(0, 2)] = xi[1].clone();
-    //     xi_hat[(1, 0)] = xi[2].clone();
-    //     xi_hat[(1, 2)] = -xi[0].clone();
-    //     xi_hat[(2, 0)] = -xi[1].clone();
-    //     xi_hat[(2, 1)] = xi[0].clone();
+    /// 4x4 matrix `[[hat(omega), rho], [0, 0]]` of the Lie algebra.
+    pub fn hat(xi: na::DVectorView<T>) -> na::Matrix4<T> {
+        let mut xi_hat = na::Matrix4::zeros();
+        xi_hat
+            .fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&SO3::hat(xi.fixed_rows::<3>(0).into_owned().as_view()));
+        xi_hat
+            .fixed_view_mut::<3, 1>(0, 3)
+            .copy_from(&xi.fixed_rows::<3>(3));
+        xi_hat
+    }
+
+    /// `ad(xi)` with `hat(ad(xi) * eta) = [hat(xi), hat(eta)]`.
+    pub fn small_adjoint(xi: na::DVectorView<T>) -> na::Matrix6<T> {
+        let omega_hat = SO3::hat(xi.fixed_rows::<3>(0).into_owned().as_view());
+        let mut ad = na::Matrix6::zeros();
+        ad.fixed_view_mut::<3, 3>(0, 0).copy_from(&omega_hat);
+        ad.fixed_view_mut::<3, 3>(3, 3).copy_from(&omega_hat);
+        ad.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&SO3::hat(xi.fixed_rows::<3>(3).into_owned().as_view()));
+        ad
+    }
+
+    /// `Adj(x)` with `x * exp(xi) = exp(Adj(x) * xi) * x`, which maps a
+    /// covariance in the frame of `x` to the frame `x` is expressed in.
+    pub fn adjoint(&self) -> na::Matrix6<T> {
+        let r = self.rot.to_rotation_matrix();
+        let mut adj = na::Matrix6::zeros();
+        adj.fixed_view_mut::<3, 3>(0, 0).copy_from(&r);
+        adj.fixed_view_mut::<3, 3>(3, 3).copy_from(&r);
+        adj.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&(SO3::hat(self.xyz.as_view()) * r));
+        adj
+    }
+
+    /// Left Jacobian of `exp`, `exp(xi + d) = exp(left_jacobian(xi) * d) * exp(xi)`
+    /// to first order.
+    pub fn left_jacobian(xi: na::DVectorView<T>) -> na::Matrix6<T> {
+        let omega = xi.fixed_rows::<3>(0).into_owned();
+        let rho = xi.fixed_rows::<3>(3).into_owned();
+        let jl = SO3::left_jacobian(omega.as_view());
+        let mut jacobian = na::Matrix6::zeros();
+        jacobian.fixed_view_mut::<3, 3>(0, 0).copy_from(&jl);
+        jacobian.fixed_view_mut::<3, 3>(3, 3).copy_from(&jl);
+        jacobian
+            .fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&q_matrix(omega.as_view(), rho.as_view()));
+        jacobian
+    }
+
+    pub fn left_jacobian_inverse(xi: na::DVectorView<T>) -> na::Matrix6<T> {
+        let omega = xi.fixed_rows::<3>(0).into_owned();
+        let rho = xi.fixed_rows::<3>(3).into_owned();
+        let jl_inv = SO3::left_jacobian_inverse(omega.as_view());
+        let q = q_matrix(omega.as_view(), rho.as_view());
+        let mut jacobian = na::Matrix6::zeros();
+        jacobian.fixed_view_mut::<3, 3>(0, 0).copy_from(&jl_inv);
+        jacobian.fixed_view_mut::<3, 3>(3, 3).copy_from(&jl_inv);
+        jacobian
+            .fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&-(&jl_inv * q * &jl_inv));
+        jacobian
+    }
+
+    /// Right Jacobian of `exp`, `exp(xi + d) = exp(xi) * exp(right_jacobian(xi) * d)`
+    /// to first order.
+    pub fn right_jacobian(xi: na::DVectorView<T>) -> na::Matrix6<T> {
+        Self::left_jacobian((-xi).as_view())
+    }
+
+    pub fn right_jacobian_inverse(xi: na::DVectorView<T>) -> na::Matrix6<T> {
+        Self::left_jacobian_inverse((-xi).as_view())
+    }
+
+    /// Jacobians of `x * p` with respect to the right perturbation
+    /// `x * exp(xi)` and to `p`.
+    pub fn action_jacobians(&self, p: na::VectorView3<T>) -> (na::Matrix3x6<T>, na::Matrix3<T>) {
+        let r = self.rot.to_rotation_matrix();
+        let mut jacobian_xi = na::Matrix3x6::zeros();
+        jacobian_xi
+            .fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&-(&r * SO3::hat(p)));
+        jacobian_xi.fixed_view_mut::<3, 3>(0, 3).copy_from(&r);
+        (jacobian_xi, r)
+    }
 
-    //     xi_hat
-    // }
+    /// 4x4 homogeneous matrix `[[R, t], [0, 1]]`.
+    pub fn to_matrix(&self) -> na::Matrix4<T> {
+        let mut m = na::Matrix4::identity();
+        m.fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&self.rot.to_rotation_matrix());
+        m.fixed_view_mut::<3, 1>(0, 3).copy_from(&self.xyz);
+        m
+    }
 
     pub fn cast<U: na::RealField + simba::scalar::SupersetOf<T>>(&self) -> SE3<U> {
         SE3 {
@@ -96,6 +178,48 @@ impl<T: na::RealField> SE3<T> {
     }
 }
 
+/// Off-diagonal block of the left Jacobian, eq. 7.86 in Barfoot, State
+/// Estimation for Robotics.
+pub(super) fn q_matrix<T: na::RealField>(
+    omega: na::VectorView3<T>,
+    rho: na::VectorView3<T>,
+) -> na::Matrix3<T> {
+    let theta2 = omega.norm_squared();
+    let (c1, c2, c3) = if theta2 < T::from_f64(1e-2).unwrap() {
+        let theta4 = theta2.clone() * theta2.clone();
+        (
+            T::from_f64(1.0 / 6.0).unwrap() - theta2.clone() / T::from_f64(120.0).unwrap()
+                + theta4.clone() / T::from_f64(5040.0).unwrap(),
+            T::from_f64(1.0 / 24.0).unwrap() - theta2.clone() / T::from_f64(720.0).unwrap()
+                + theta4.clone() / T::from_f64(40320.0).unwrap(),
+            T::from_f64(1.0 / 120.0).unwrap() - theta2 / T::from_f64(2520.0).unwrap()
+                + theta4 / T::from_f64(120960.0).unwrap(),
+        )
+    } else {
+        let theta = theta2.clone().sqrt();
+        let (sin, cos) = theta.clone().sin_cos();
+        let two = T::from_f64(2.0).unwrap();
+        let theta3 = theta2.clone() * theta.clone();
+        let theta4 = theta2.clone() * theta2.clone();
+        (
+            (theta.clone() - sin.clone()) / theta3,
+            (theta2.clone() + two.clone() * cos.clone() - two.clone())
+                / (two.clone() * theta4.clone()),
+            (two.clone() * theta.clone() - T::from_f64(3.0).unwrap() * sin + theta.clone() * cos)
+                / (two * theta4 * theta),
+        )
+    };
+    let w = SO3::hat(omega);
+    let r = SO3::hat(rho);
+    let wr = &w * &r;
+    let rw = &r * &w;
+    let wrw = &wr * &w;
+    &r * T::from_f64(0.5).unwrap()
+        + (&wr + &rw + &wrw) * c1
+        + (&w * &wr + &rw * &w - &wrw * T::from_f64(3.0).unwrap()) * c2
+        + (&wrw * &w + &w * &wrw) * c3
+}
+
 impl<T: na::RealField> Mul for SE3<T> {
     type Output = Self;
 
@@ -171,4 +295,10 @@ impl Manifold for SE3Manifold {
     fn tangent_size(&self) -> NonZero<usize> {
         NonZero::new(6).unwrap()
     }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(7).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
 }
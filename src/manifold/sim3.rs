@@ -0,0 +1,295 @@
+use std::{num::NonZero, ops::Mul};
+
+use nalgebra as na;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold, so3::SO3};
+
+/// Similarity transformation `x -> s * R * x + t` in 3D, e.g. for monocular
+/// loop closures with scale drift. The tangent space is ordered
+/// `[omega, rho, sigma]` with the log scale `sigma` last.
+pub struct Sim3<T: na::RealField> {
+    pub rot: SO3<T>,
+    pub xyz: na::Vector3<T>,
+    pub scale: T,
+}
+
+impl<T: na::RealField> Sim3<T> {
+    /// [qx, qy, qz, qw, tx, ty, tz, s]
+    pub fn from_vec(qxyzw_txyz_s: na::DVectorView<T>) -> Self {
+        let v = qxyzw_txyz_s;
+        Sim3 {
+            rot: SO3::from_vec(v.rows(0, 4).as_view()),
+            xyz: na::Vector3::new(v[4].clone(), v[5].clone(), v[6].clone()),
+            scale: v[7].clone(),
+        }
+    }
+    pub fn identity() -> Self {
+        Sim3 {
+            rot: SO3::identity(),
+            xyz: na::Vector3::zeros(),
+            scale: T::one(),
+        }
+    }
+
+    pub fn exp(xi: na::DVectorView<T>) -> Self {
+        let omega = xi.fixed_rows::<3>(0).into_owned();
+        let sigma = xi[6].clone();
+        let w = w_matrix(omega.as_view(), sigma.clone());
+        Sim3 {
+            rot: SO3::exp(xi.rows(0, 3)),
+            xyz: w * xi.fixed_rows::<3>(3),
+            scale: sigma.exp(),
+        }
+    }
+
+    pub fn log(&self) -> na::DVector<T> {
+        let omega = na::Vector3::from_iterator(self.rot.log().iter().cloned());
+        let sigma = self.scale.clone().ln();
+        let rho = w_inverse(omega.as_view(), sigma.clone()) * &self.xyz;
+        na::DVector::from_iterator(
+            7,
+            omega
+                .iter()
+                .chain(rho.iter())
+                .cloned()
+                .chain(std::iter::once(sigma)),
+        )
+    }
+
+    /// 4x4 matrix `[[hat(omega) + sigma * I, rho], [0, 0]]` of the Lie algebra.
+    pub fn hat(xi: na::DVectorView<T>) -> na::Matrix4<T> {
+        let mut xi_hat = na::Matrix4::zeros();
+        xi_hat.fixed_view_mut::<3, 3>(0, 0).copy_from(
+            &(SO3::hat(xi.fixed_rows::<3>(0).into_owned().as_view())
+                + na::Matrix3::identity() * xi[6].clone()),
+        );
+        xi_hat
+            .fixed_view_mut::<3, 1>(0, 3)
+            .copy_from(&xi.fixed_rows::<3>(3));
+        xi_hat
+    }
+
+    /// `ad(xi)` with `hat(ad(xi) * eta) = [hat(xi), hat(eta)]`.
+    pub fn small_adjoint(xi: na::DVectorView<T>) -> na::SMatrix<T, 7, 7> {
+        let omega_hat = SO3::hat(xi.fixed_rows::<3>(0).into_owned().as_view());
+        let mut ad = na::SMatrix::<T, 7, 7>::zeros();
+        ad.fixed_view_mut::<3, 3>(0, 0).copy_from(&omega_hat);
+        ad.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&SO3::hat(xi.fixed_rows::<3>(3).into_owned().as_view()));
+        ad.fixed_view_mut::<3, 3>(3, 3)
+            .copy_from(&(omega_hat + na::Matrix3::identity() * xi[6].clone()));
+        ad.fixed_view_mut::<3, 1>(3, 6)
+            .copy_from(&(-xi.fixed_rows::<3>(3)));
+        ad
+    }
+
+    /// `Adj(x)` with `x * exp(xi) = exp(Adj(x) * xi) * x`.
+    pub fn adjoint(&self) -> na::SMatrix<T, 7, 7> {
+        let r = self.rot.to_rotation_matrix();
+        let mut adj = na::SMatrix::<T, 7, 7>::zeros();
+        adj.fixed_view_mut::<3, 3>(0, 0).copy_from(&r);
+        adj.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&(SO3::hat(self.xyz.as_view()) * &r));
+        adj.fixed_view_mut::<3, 3>(3, 3)
+            .copy_from(&(&r * self.scale.clone()));
+        adj.fixed_view_mut::<3, 1>(3, 6).copy_from(&(-&self.xyz));
+        adj[(6, 6)] = T::one();
+        adj
+    }
+
+    /// Left Jacobian of `exp`, `exp(xi + d) = exp(left_jacobian(xi) * d) * exp(xi)`
+    /// to first order. The closed form is unwieldy, so this sums the series
+    /// `sum_k ad(xi)^k / (k + 1)!` until the terms are below machine
+    /// precision, after a few terms for the steps of an optimization.
+    pub fn left_jacobian(xi: na::DVectorView<T>) -> na::SMatrix<T, 7, 7> {
+        let ad = Self::small_adjoint(xi);
+        let eps = T::from_f64(f64::EPSILON).unwrap();
+        let mut term = na::SMatrix::<T, 7, 7>::identity();
+        let mut jacobian = term.clone();
+        for k in 1..MAX_SERIES_TERMS {
+            term = &term * &ad / T::from_usize(k + 1).unwrap();
+            jacobian += &term;
+            if term.norm() < eps {
+                break;
+            }
+        }
+        jacobian
+    }
+
+    /// Right Jacobian of `exp`, `exp(xi + d) = exp(xi) * exp(right_jacobian(xi) * d)`
+    /// to first order.
+    pub fn right_jacobian(xi: na::DVectorView<T>) -> na::SMatrix<T, 7, 7> {
+        Self::left_jacobian((-xi).as_view())
+    }
+
+    /// 4x4 homogeneous matrix `[[s * R, t], [0, 1]]`.
+    pub fn to_matrix(&self) -> na::Matrix4<T> {
+        let mut m = na::Matrix4::identity();
+        m.fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&(self.rot.to_rotation_matrix() * self.scale.clone()));
+        m.fixed_view_mut::<3, 1>(0, 3).copy_from(&self.xyz);
+        m
+    }
+
+    pub fn to_dvec(&self) -> na::DVector<T> {
+        let quat = self.rot.to_vec();
+        na::DVector::from_iterator(
+            8,
+            quat.iter()
+                .chain(self.xyz.iter())
+                .cloned()
+                .chain(std::iter::once(self.scale.clone())),
+        )
+    }
+
+    pub fn cast<U: na::RealField + simba::scalar::SupersetOf<T>>(&self) -> Sim3<U> {
+        Sim3 {
+            rot: self.rot.cast(),
+            xyz: self.xyz.clone().cast(),
+            scale: U::from_subset(&self.scale),
+        }
+    }
+    pub fn inverse(&self) -> Self {
+        let inv = self.rot.inverse();
+        let scale = T::one() / self.scale.clone();
+        let xyz = -(&inv * self.xyz.as_view()) * scale.clone();
+        Sim3 {
+            rot: inv,
+            xyz,
+            scale,
+        }
+    }
+    pub fn compose(&self, rhs: &Self) -> Self {
+        Sim3 {
+            rot: &self.rot * &rhs.rot,
+            xyz: (&self.rot * rhs.xyz.as_view()) * self.scale.clone() + self.xyz.clone(),
+            scale: self.scale.clone() * rhs.scale.clone(),
+        }
+    }
+}
+
+/// Upper bound of the terms of the series in `Sim3::left_jacobian`.
+const MAX_SERIES_TERMS: usize = 40;
+
+/// `W` with `exp([omega, rho, sigma]) = (exp(sigma), exp(omega), W * rho)`.
+fn w_matrix<T: na::RealField>(omega: na::VectorView3<T>, sigma: T) -> na::Matrix3<T> {
+    let (c, a, b) = w_coefficients(omega.as_view(), sigma);
+    let omega_hat = SO3::hat(omega);
+    na::Matrix3::identity() * c + &omega_hat * a + &omega_hat * &omega_hat * b
+}
+
+/// `W^-1` in closed form. With `hat(omega)^3 = -theta^2 * hat(omega)` the
+/// inverse of `W = c * I + a * hat(omega) + b * hat(omega)^2` is also a
+/// polynomial of degree 2 in `hat(omega)`.
+fn w_inverse<T: na::RealField>(omega: na::VectorView3<T>, sigma: T) -> na::Matrix3<T> {
+    let (c, a, b) = w_coefficients(omega.as_view(), sigma);
+    let theta2 = omega.norm_squared();
+    let d = c.clone() - theta2.clone() * b.clone();
+    let det = d.clone() * d.clone() + theta2 * a.clone() * a.clone();
+    let omega_hat = SO3::hat(omega);
+    na::Matrix3::identity() / c.clone() - &omega_hat * (a.clone() / det.clone())
+        + &omega_hat * &omega_hat * ((a.clone() * a - b * d) / (c * det))
+}
+
+/// `(c, a, b)` with `W = c * I + a * hat(omega) + b * hat(omega)^2`,
+/// following `calcW` in Sophus.
+fn w_coefficients<T: na::RealField>(omega: na::VectorView3<T>, sigma: T) -> (T, T, T) {
+    let eps = T::from_f64(1e-10).unwrap();
+    let one = T::one();
+    let half = T::from_f64(0.5).unwrap();
+    let theta2 = omega.norm_squared();
+    let scale = sigma.clone().exp();
+    let small_theta = theta2 < eps.clone() * eps.clone();
+    let (a, b, c) = if sigma.clone().abs() < eps {
+        let (a, b) = if small_theta {
+            (half, T::from_f64(1.0 / 6.0).unwrap())
+        } else {
+            let theta = theta2.clone().sqrt();
+            let (sin, cos) = theta.clone().sin_cos();
+            (
+                (one.clone() - cos) / theta2.clone(),
+                (theta.clone() - sin) / (theta2 * theta),
+            )
+        };
+        (a, b, one)
+    } else {
+        let c = sigma.clone().exp_m1() / sigma.clone();
+        let sigma2 = sigma.clone() * sigma.clone();
+        let (a, b) = if small_theta {
+            (
+                ((sigma.clone() - one.clone()) * scale.clone() + one.clone()) / sigma2.clone(),
+                (scale.clone() * half * sigma2.clone() + scale.clone()
+                    - one
+                    - sigma.clone() * scale)
+                    / (sigma2 * sigma),
+            )
+        } else {
+            let theta = theta2.clone().sqrt();
+            let (sin, cos) = theta.clone().sin_cos();
+            let a = scale.clone() * sin;
+            let b = scale * cos;
+            let d = theta2.clone() + sigma2;
+            (
+                (a.clone() * sigma.clone() + (one.clone() - b.clone()) * theta.clone())
+                    / (theta.clone() * d.clone()),
+                (c.clone() - ((b - one) * sigma + a * theta) / d) / theta2,
+            )
+        };
+        (a, b, c)
+    };
+    (c, a, b)
+}
+
+impl<T: na::RealField> Mul for Sim3<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(&rhs)
+    }
+}
+impl<T: na::RealField> Mul for &Sim3<T> {
+    type Output = Sim3<T>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(rhs)
+    }
+}
+
+impl<T: na::RealField> Mul<na::VectorView3<'_, T>> for &Sim3<T> {
+    type Output = na::Vector3<T>;
+
+    fn mul(self, rhs: na::VectorView3<'_, T>) -> Self::Output {
+        (&self.rot * rhs) * self.scale.clone() + self.xyz.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sim3Manifold;
+impl<T: na::RealField> AutoDiffManifold<T> for Sim3Manifold {
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        (Sim3::from_vec(x) * Sim3::exp(delta)).to_dvec()
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        (Sim3::from_vec(x).inverse() * Sim3::from_vec(y)).log()
+    }
+}
+impl Manifold for Sim3Manifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(7).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(8).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
+}
@@ -0,0 +1,133 @@
+use std::{num::NonZero, ops::Mul};
+
+use nalgebra as na;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold};
+
+/// Rotation in 2D, stored as the angle wrapped to `(-pi, pi]`.
+#[derive(Debug, Clone)]
+pub struct SO2<T: na::RealField> {
+    pub theta: T,
+}
+
+impl<T: na::RealField> SO2<T> {
+    pub fn new(theta: T) -> Self {
+        SO2 {
+            theta: wrap_angle(theta),
+        }
+    }
+    /// [theta]
+    pub fn from_vec(theta: na::DVectorView<T>) -> Self {
+        SO2::new(theta[0].clone())
+    }
+    pub fn identity() -> Self {
+        SO2 { theta: T::zero() }
+    }
+
+    pub fn exp(xi: na::DVectorView<T>) -> Self {
+        SO2::new(xi[0].clone())
+    }
+
+    pub fn log(&self) -> na::DVector<T> {
+        na::dvector![self.theta.clone()]
+    }
+
+    pub fn hat(theta: T) -> na::Matrix2<T> {
+        na::Matrix2::new(T::zero(), -theta.clone(), theta, T::zero())
+    }
+
+    /// The group is commutative, so the adjoint and the Jacobians of `exp`
+    /// are the identity.
+    pub fn adjoint(&self) -> na::Matrix1<T> {
+        na::Matrix1::identity()
+    }
+    pub fn left_jacobian(_xi: na::DVectorView<T>) -> na::Matrix1<T> {
+        na::Matrix1::identity()
+    }
+    pub fn right_jacobian(_xi: na::DVectorView<T>) -> na::Matrix1<T> {
+        na::Matrix1::identity()
+    }
+
+    pub fn rotation_matrix(&self) -> na::Matrix2<T> {
+        let (sin, cos) = self.theta.clone().sin_cos();
+        na::Matrix2::new(cos.clone(), -sin.clone(), sin, cos)
+    }
+
+    pub fn to_dvec(&self) -> na::DVector<T> {
+        na::dvector![self.theta.clone()]
+    }
+
+    pub fn cast<U: na::RealField + simba::scalar::SupersetOf<T>>(&self) -> SO2<U> {
+        SO2 {
+            theta: U::from_subset(&self.theta),
+        }
+    }
+    pub fn inverse(&self) -> Self {
+        SO2 {
+            theta: -self.theta.clone(),
+        }
+    }
+    pub fn compose(&self, rhs: &Self) -> Self {
+        SO2::new(self.theta.clone() + rhs.theta.clone())
+    }
+}
+
+/// Wraps the angle to `(-pi, pi]`, differentiable everywhere.
+pub(super) fn wrap_angle<T: na::RealField>(theta: T) -> T {
+    let (sin, cos) = theta.sin_cos();
+    sin.atan2(cos)
+}
+
+impl<T: na::RealField> Mul for SO2<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(&rhs)
+    }
+}
+impl<T: na::RealField> Mul for &SO2<T> {
+    type Output = SO2<T>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(rhs)
+    }
+}
+
+impl<T: na::RealField> Mul<na::VectorView2<'_, T>> for &SO2<T> {
+    type Output = na::Vector2<T>;
+
+    fn mul(self, rhs: na::VectorView2<'_, T>) -> Self::Output {
+        self.rotation_matrix() * rhs
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SO2Manifold;
+impl<T: na::RealField> AutoDiffManifold<T> for SO2Manifold {
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        (SO2::from_vec(x) * SO2::exp(delta)).to_dvec()
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        (SO2::from_vec(x).inverse() * SO2::from_vec(y)).log()
+    }
+}
+impl Manifold for SO2Manifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(1).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(1).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
+}
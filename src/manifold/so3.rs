@@ -2,7 +2,7 @@ use std::{num::NonZero, ops::Mul};
 
 use nalgebra as na;
 
-use super::{AutoDiffManifold, Manifold};
+use super::{AutoDiffManifold, Manifold, StaticDualManifold};
 
 pub struct SO3<T: na::RealField> {
     qx: T,
@@ -104,6 +104,87 @@ impl<T: na::RealField> SO3<T> {
         xi_hat
     }
 
+    /// Left Jacobian of `exp`, `exp(omega + d) = exp(left_jacobian(omega) * d) * exp(omega)`
+    /// to first order, `I + (1 - cos) / theta^2 * hat + (theta - sin) / theta^3 * hat^2`.
+    pub fn left_jacobian(omega: na::VectorView3<T>) -> na::Matrix3<T> {
+        let theta2 = omega.norm_squared();
+        let (a, b) = if theta2 < T::from_f64(1e-6).unwrap() {
+            (
+                T::from_f64(0.5).unwrap() - theta2.clone() / T::from_f64(24.0).unwrap(),
+                T::from_f64(1.0 / 6.0).unwrap() - theta2 / T::from_f64(120.0).unwrap(),
+            )
+        } else {
+            let theta = theta2.clone().sqrt();
+            let (sin, cos) = theta.clone().sin_cos();
+            (
+                (T::one() - cos) / theta2.clone(),
+                (theta.clone() - sin) / (theta2 * theta),
+            )
+        };
+        let omega_hat = Self::hat(omega);
+        na::Matrix3::identity() + &omega_hat * a + &omega_hat * &omega_hat * b
+    }
+
+    /// Inverse of `left_jacobian`, singular at `theta = pi`.
+    pub fn left_jacobian_inverse(omega: na::VectorView3<T>) -> na::Matrix3<T> {
+        let theta2 = omega.norm_squared();
+        let c = if theta2 < T::from_f64(1e-6).unwrap() {
+            T::from_f64(1.0 / 12.0).unwrap() + theta2 / T::from_f64(720.0).unwrap()
+        } else {
+            let theta = theta2.clone().sqrt();
+            let (sin, cos) = theta.clone().sin_cos();
+            T::one() / theta2 - (T::one() + cos) / (T::from_f64(2.0).unwrap() * theta * sin)
+        };
+        let omega_hat = Self::hat(omega);
+        na::Matrix3::identity() - &omega_hat * T::from_f64(0.5).unwrap()
+            + &omega_hat * &omega_hat * c
+    }
+
+    /// Right Jacobian of `exp`, `exp(omega + d) = exp(omega) * exp(right_jacobian(omega) * d)`
+    /// to first order.
+    pub fn right_jacobian(omega: na::VectorView3<T>) -> na::Matrix3<T> {
+        Self::left_jacobian((-omega).as_view())
+    }
+
+    pub fn right_jacobian_inverse(omega: na::VectorView3<T>) -> na::Matrix3<T> {
+        Self::left_jacobian_inverse((-omega).as_view())
+    }
+
+    /// `Adj(r)` with `r * exp(omega) = exp(Adj(r) * omega) * r`, the rotation
+    /// matrix.
+    pub fn adjoint(&self) -> na::Matrix3<T> {
+        self.to_rotation_matrix()
+    }
+
+    /// Jacobians of `r * p` with respect to the right perturbation
+    /// `r * exp(omega)` and to `p`.
+    pub fn action_jacobians(&self, p: na::VectorView3<T>) -> (na::Matrix3<T>, na::Matrix3<T>) {
+        let r = self.to_rotation_matrix();
+        (-(&r * Self::hat(p)), r)
+    }
+
+    pub fn to_rotation_matrix(&self) -> na::Matrix3<T> {
+        let (x, y, z, w) = (
+            self.qx.clone(),
+            self.qy.clone(),
+            self.qz.clone(),
+            self.qw.clone(),
+        );
+        let one = T::one();
+        let two = T::from_f64(2.0).unwrap();
+        na::Matrix3::new(
+            one.clone() - two.clone() * (y.clone() * y.clone() + z.clone() * z.clone()),
+            two.clone() * (x.clone() * y.clone() - z.clone() * w.clone()),
+            two.clone() * (x.clone() * z.clone() + y.clone() * w.clone()),
+            two.clone() * (x.clone() * y.clone() + z.clone() * w.clone()),
+            one.clone() - two.clone() * (x.clone() * x.clone() + z.clone() * z.clone()),
+            two.clone() * (y.clone() * z.clone() - x.clone() * w.clone()),
+            two.clone() * (x.clone() * z.clone() - y.clone() * w.clone()),
+            two.clone() * (y.clone() * z.clone() + x.clone() * w.clone()),
+            one - two * (x.clone() * x + y.clone() * y),
+        )
+    }
+
     pub fn to_vec(&self) -> na::Vector4<T> {
         na::Vector4::new(
             self.qx.clone(),
@@ -220,4 +301,10 @@ impl Manifold for QuaternionManifold {
     fn tangent_size(&self) -> NonZero<usize> {
         NonZero::new(3).unwrap()
     }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(4).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
 }
@@ -0,0 +1,139 @@
+use std::num::NonZero;
+
+use nalgebra as na;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold};
+
+/// Vectors of constant norm in `R^n`, e.g. unit normals or bearing vectors.
+/// The tangent space at `x` is the plane orthogonal to `x`, parameterized
+/// through the Householder reflection that maps `x` to the last axis, as in
+/// Ceres' `SphereManifold`. `plus` keeps the norm of `x`.
+#[derive(Debug, Clone)]
+pub struct SphereManifold {
+    ambient_size: usize,
+}
+
+impl SphereManifold {
+    /// Panics if `ambient_size < 2`, which leaves no tangent space.
+    pub fn new(ambient_size: usize) -> Self {
+        assert!(
+            ambient_size >= 2,
+            "SphereManifold needs an ambient size of at least 2"
+        );
+        SphereManifold { ambient_size }
+    }
+}
+
+/// Householder vector `v` and `beta` such that
+/// `(I - beta * v * v^T) * x = |x| * e_n`.
+pub(super) fn householder<T: na::RealField>(x: na::DVectorView<T>) -> (na::DVector<T>, T) {
+    let n = x.len();
+    let sigma = x.rows(0, n - 1).norm_squared();
+    let x_n = x[n - 1].clone();
+    let mut v = x.clone_owned();
+    v[n - 1] = T::one();
+    if sigma <= T::from_f64(f64::MIN_POSITIVE).unwrap() {
+        // x is already on the last axis, reflect it if it points the other way
+        let beta = if x_n >= T::zero() {
+            T::zero()
+        } else {
+            T::from_f64(2.0).unwrap()
+        };
+        return (v, beta);
+    }
+    let mu = (x_n.clone() * x_n.clone() + sigma.clone()).sqrt();
+    let v_n = if x_n <= T::zero() {
+        x_n - mu
+    } else {
+        -sigma.clone() / (x_n + mu)
+    };
+    let v_n2 = v_n.clone() * v_n.clone();
+    let beta = T::from_f64(2.0).unwrap() * v_n2.clone() / (sigma + v_n2);
+    v[n - 1] = v_n.clone();
+    (v / v_n, beta)
+}
+
+pub(super) fn apply_householder<T: na::RealField>(
+    y: na::DVectorView<T>,
+    v: &na::DVector<T>,
+    beta: T,
+) -> na::DVector<T> {
+    let scale = beta * v.dot(&y);
+    y - v * scale
+}
+
+pub(super) fn sphere_plus<T: na::RealField>(
+    x: na::DVectorView<T>,
+    delta: na::DVectorView<T>,
+) -> na::DVector<T> {
+    let n = x.len();
+    let theta2 = delta.norm_squared();
+    // sin(theta) / theta and cos(theta), with series that keep the
+    // derivatives at delta = 0
+    let (sinc, cos) = if theta2 < T::from_f64(1e-8).unwrap() {
+        (
+            T::one() - theta2.clone() / T::from_f64(6.0).unwrap(),
+            T::one() - theta2 / T::from_f64(2.0).unwrap(),
+        )
+    } else {
+        let theta = theta2.sqrt();
+        let (sin, cos) = theta.clone().sin_cos();
+        (sin / theta, cos)
+    };
+    let mut y = na::DVector::zeros(n);
+    y.rows_mut(0, n - 1).copy_from(&(delta * sinc));
+    y[n - 1] = cos;
+    let x_norm = x.norm();
+    let (v, beta) = householder(x);
+    apply_householder(y.as_view(), &v, beta) * x_norm
+}
+
+pub(super) fn sphere_minus<T: na::RealField>(
+    y: na::DVectorView<T>,
+    x: na::DVectorView<T>,
+) -> na::DVector<T> {
+    let n = x.len();
+    let (v, beta) = householder(x);
+    let y_norm = y.norm();
+    let hy = apply_householder(y, &v, beta) / y_norm;
+    let head = hy.rows(0, n - 1).into_owned();
+    let c = hy[n - 1].clone();
+    let s2 = head.norm_squared();
+    // theta = 2 * atan2(s, 1 + c), the angle between x and y
+    let theta_by_s = if s2 < T::from_f64(1e-16).unwrap() {
+        T::from_f64(2.0).unwrap() / (T::one() + c)
+    } else {
+        let s = s2.sqrt();
+        T::from_f64(2.0).unwrap() * s.clone().atan2(T::one() + c) / s
+    };
+    head * theta_by_s
+}
+
+impl<T: na::RealField> AutoDiffManifold<T> for SphereManifold {
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        sphere_plus(x, delta)
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        sphere_minus(y, x)
+    }
+}
+impl Manifold for SphereManifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(self.ambient_size - 1).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        NonZero::new(self.ambient_size).unwrap()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        Some(self)
+    }
+}
@@ -0,0 +1,126 @@
+use std::{num::NonZero, sync::Arc};
+
+use nalgebra as na;
+use num_dual::DualSVec64;
+
+use super::{AutoDiffManifold, Manifold, StaticDualManifold};
+
+/// Keeps some tangent coordinates of another manifold constant, e.g. the
+/// yaw and the position of the first pose to remove the 4-DoF gauge freedom
+/// of a visual-inertial problem, where roll and pitch are observable from
+/// gravity. The tangent space is that of `manifold` without the fixed
+/// coordinates. `Problem::fix_variable` does the same for a single problem
+/// without changing the manifold.
+#[derive(Clone)]
+pub struct SubsetManifold {
+    manifold: Arc<dyn Manifold + Sync + Send>,
+    /// Tangent indexes of `manifold` that are not fixed, in order.
+    free_indexes: Vec<usize>,
+}
+
+impl SubsetManifold {
+    /// Panics if an index is out of the tangent space of `manifold` or if no
+    /// tangent coordinate is left.
+    pub fn new(manifold: Arc<dyn Manifold + Sync + Send>, fixed_indexes: &[usize]) -> Self {
+        let tangent_size = manifold.tangent_size().get();
+        assert!(
+            fixed_indexes.iter().all(|&i| i < tangent_size),
+            "fixed index out of the tangent space of size {tangent_size}"
+        );
+        let free_indexes: Vec<usize> = (0..tangent_size)
+            .filter(|i| !fixed_indexes.contains(i))
+            .collect();
+        assert!(
+            !free_indexes.is_empty(),
+            "SubsetManifold needs at least one free tangent index"
+        );
+        SubsetManifold {
+            manifold,
+            free_indexes,
+        }
+    }
+
+    /// Tangent vector of `manifold` with zeros at the fixed indexes.
+    fn full_delta<T: na::RealField>(&self, delta: na::DVectorView<T>) -> na::DVector<T> {
+        let mut delta_full = na::DVector::zeros(self.manifold.tangent_size().get());
+        for (i, &idx) in self.free_indexes.iter().enumerate() {
+            delta_full[idx] = delta[i].clone();
+        }
+        delta_full
+    }
+
+    fn free_delta<T: na::RealField>(&self, delta_full: &na::DVector<T>) -> na::DVector<T> {
+        na::DVector::from_iterator(
+            self.free_indexes.len(),
+            self.free_indexes.iter().map(|&idx| delta_full[idx].clone()),
+        )
+    }
+
+    fn static_manifold(&self) -> &(dyn StaticDualManifold + 'static) {
+        self.manifold
+            .as_static_dual()
+            .expect("the manifold does not support statically sized dual numbers")
+    }
+}
+
+impl<T: na::RealField> AutoDiffManifold<T> for SubsetManifold
+where
+    dyn Manifold + Sync + Send: AutoDiffManifold<T>,
+{
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<T>,
+        delta: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        let delta_full = self.full_delta(delta);
+        self.manifold.as_ref().plus(x, delta_full.as_view())
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<T>,
+        x: nalgebra::DVectorView<T>,
+    ) -> nalgebra::DVector<T> {
+        let delta_full = self.manifold.as_ref().minus(y, x);
+        self.free_delta(&delta_full)
+    }
+}
+
+/// Panics if `manifold` does not support the statically sized dual numbers,
+/// `as_static_dual` returns `None` in that case.
+impl<const N: usize> AutoDiffManifold<DualSVec64<N>> for SubsetManifold
+where
+    dyn StaticDualManifold: AutoDiffManifold<DualSVec64<N>>,
+{
+    fn plus(
+        &self,
+        x: nalgebra::DVectorView<DualSVec64<N>>,
+        delta: nalgebra::DVectorView<DualSVec64<N>>,
+    ) -> nalgebra::DVector<DualSVec64<N>> {
+        let delta_full = self.full_delta(delta);
+        self.static_manifold().plus(x, delta_full.as_view())
+    }
+
+    fn minus(
+        &self,
+        y: nalgebra::DVectorView<DualSVec64<N>>,
+        x: nalgebra::DVectorView<DualSVec64<N>>,
+    ) -> nalgebra::DVector<DualSVec64<N>> {
+        let delta_full = self.static_manifold().minus(y, x);
+        self.free_delta(&delta_full)
+    }
+}
+
+impl Manifold for SubsetManifold {
+    fn tangent_size(&self) -> NonZero<usize> {
+        NonZero::new(self.free_indexes.len()).unwrap()
+    }
+    fn ambient_size(&self) -> NonZero<usize> {
+        self.manifold.ambient_size()
+    }
+    fn as_static_dual(&self) -> Option<&(dyn StaticDualManifold + 'static)> {
+        self.manifold
+            .as_static_dual()
+            .map(|_| self as &(dyn StaticDualManifold + 'static))
+    }
+}
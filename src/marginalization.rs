@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use nalgebra as na;
+
+use crate::factors::{Factor, mul_f64_matrix};
+use crate::manifold::{AutoDiffManifold, Manifold};
+
+/// Eigenvalues below this are treated as zero when inverting the
+/// information of the marginalized variables and factoring the prior.
+const EIGENVALUE_THRESHOLD: f64 = 1e-8;
+
+/// Linear prior left by `Problem::marginalize_variables` on the variables
+/// that shared residual blocks with the marginalized ones,
+/// `r = sqrt_information * (x minus x0) + residual` with `x minus x0`
+/// stacked over the variables in the tangent space of their manifolds
+/// at the linearization points `x0`.
+#[derive(Clone)]
+pub struct MarginalizationFactor {
+    pub linearization_points: Vec<na::DVector<f64>>,
+    pub manifolds: Vec<Option<Arc<dyn Manifold + Sync + Send>>>,
+    pub sqrt_information: na::DMatrix<f64>,
+    pub residual: na::DVector<f64>,
+}
+
+impl<T: na::RealField> Factor<T> for MarginalizationFactor
+where
+    dyn Manifold + Sync + Send: AutoDiffManifold<T>,
+{
+    fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+        let mut dx = Vec::with_capacity(self.sqrt_information.ncols());
+        for ((param, x0), manifold) in params
+            .iter()
+            .zip(&self.linearization_points)
+            .zip(&self.manifolds)
+        {
+            let x0 = x0.map(|v| T::from_f64(v).unwrap());
+            let delta = match manifold {
+                Some(m) => m.as_ref().minus(param.as_view(), x0.as_view()),
+                None => param - x0,
+            };
+            dx.extend(delta.iter().cloned());
+        }
+        let mut residual = mul_f64_matrix(&self.sqrt_information, &na::DVector::from_vec(dx));
+        for (r, &e) in residual.iter_mut().zip(self.residual.iter()) {
+            *r += T::from_f64(e).unwrap();
+        }
+        residual
+    }
+}
+
+/// Prior `A * dx + e` on the last columns of `jacobian`, with `A^T * A` and
+/// `A^T * e` the Schur complements of `J^T * J` and `J^T * r` after
+/// eliminating the first `marginalized_size` columns. `None` if the prior
+/// carries no information.
+pub(crate) fn schur_complement_prior(
+    jacobian: &na::DMatrix<f64>,
+    residual: &na::DVector<f64>,
+    marginalized_size: usize,
+) -> Option<(na::DMatrix<f64>, na::DVector<f64>)> {
+    let kept_size = jacobian.ncols() - marginalized_size;
+    if kept_size == 0 {
+        return None;
+    }
+    let hessian = jacobian.tr_mul(jacobian);
+    let gradient = jacobian.tr_mul(residual);
+
+    let h_mm = hessian.view((0, 0), (marginalized_size, marginalized_size));
+    let h_km = hessian.view((marginalized_size, 0), (kept_size, marginalized_size));
+    let h_kk = hessian.view(
+        (marginalized_size, marginalized_size),
+        (kept_size, kept_size),
+    );
+    let h_mm_inv = if marginalized_size == 0 {
+        na::DMatrix::zeros(0, 0)
+    } else {
+        // pseudo-inverse, directions the residual blocks do not constrain are dropped
+        let eigen = na::SymmetricEigen::new(h_mm.into_owned());
+        let inv_eigenvalues = eigen.eigenvalues.map(|lambda| {
+            if lambda > EIGENVALUE_THRESHOLD {
+                1.0 / lambda
+            } else {
+                0.0
+            }
+        });
+        &eigen.eigenvectors
+            * na::DMatrix::from_diagonal(&inv_eigenvalues)
+            * eigen.eigenvectors.transpose()
+    };
+    let h_km_h_mm_inv = h_km * h_mm_inv;
+    let information = h_kk - &h_km_h_mm_inv * h_km.transpose();
+    let marginal_gradient = gradient.rows(marginalized_size, kept_size)
+        - h_km_h_mm_inv * gradient.rows(0, marginalized_size);
+
+    let eigen = na::SymmetricEigen::new(information);
+    let kept: Vec<usize> = (0..kept_size)
+        .filter(|&i| eigen.eigenvalues[i] > EIGENVALUE_THRESHOLD)
+        .collect();
+    if kept.is_empty() {
+        return None;
+    }
+    let mut sqrt_information = na::DMatrix::zeros(kept.len(), kept_size);
+    let mut prior_residual = na::DVector::zeros(kept.len());
+    for (row, &i) in kept.iter().enumerate() {
+        let sqrt_lambda = eigen.eigenvalues[i].sqrt();
+        let v = eigen.eigenvectors.column(i);
+        sqrt_information.set_row(row, &(v.transpose() * sqrt_lambda));
+        prior_residual[row] = v.dot(&marginal_gradient) / sqrt_lambda;
+    }
+    Some((sqrt_information, prior_residual))
+}
@@ -0,0 +1,196 @@
+use nalgebra as na;
+use num_dual::DualDVec64;
+
+use crate::factors::{FactorImpl, first_order_dual};
+use crate::parameter_block::ParameterBlock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericDiffMethod {
+    /// `(f(x + h) - f(x)) / h`, one evaluation per column.
+    Forward,
+    /// `(f(x + h) - f(x - h)) / 2h`, two evaluations per column.
+    Central,
+    /// Richardson extrapolation of central differences with shrinking steps,
+    /// the most accurate and the most expensive.
+    Ridders,
+}
+
+#[derive(Debug, Clone)]
+pub struct NumericDiffOptions {
+    pub method: NumericDiffMethod,
+    /// Step of forward and central differences relative to the parameter,
+    /// or the absolute step if the parameter is zero or on a manifold.
+    pub relative_step_size: f64,
+    /// Same as `relative_step_size` for the first step of Ridders' method.
+    pub ridders_relative_initial_step_size: f64,
+    pub ridders_step_shrink_factor: f64,
+    pub max_num_ridders_extrapolations: usize,
+    /// Ridders' method stops once the error estimate is below this value.
+    pub ridders_epsilon: f64,
+}
+
+impl Default for NumericDiffOptions {
+    fn default() -> Self {
+        Self {
+            method: NumericDiffMethod::Central,
+            relative_step_size: 1e-6,
+            ridders_relative_initial_step_size: 1e-2,
+            ridders_step_shrink_factor: 2.0,
+            max_num_ridders_extrapolations: 10,
+            ridders_epsilon: 1e-12,
+        }
+    }
+}
+
+/// Factor of an `f64`-only residual function, e.g. a lookup table or a call
+/// into a C library, with Jacobians from finite differences. Parameters on a
+/// manifold are perturbed in the tangent space with `ParameterBlock::plus_f64`.
+///
+/// ```
+/// use tiny_solver::na;
+/// use tiny_solver::numeric_diff::NumericDiffFactor;
+///
+/// let factor = NumericDiffFactor::new(|params: &[na::DVector<f64>]| {
+///     na::dvector![params[0][0].exp() - 2.0]
+/// });
+/// ```
+pub struct NumericDiffFactor<F> {
+    residual_func: F,
+    pub options: NumericDiffOptions,
+}
+
+impl<F> NumericDiffFactor<F>
+where
+    F: Fn(&[na::DVector<f64>]) -> na::DVector<f64> + Send + Sync,
+{
+    pub fn new(residual_func: F) -> Self {
+        Self::with_options(residual_func, NumericDiffOptions::default())
+    }
+    pub fn with_options(residual_func: F, options: NumericDiffOptions) -> Self {
+        NumericDiffFactor {
+            residual_func,
+            options,
+        }
+    }
+
+    /// Residual and Jacobian with respect to the tangent space of `params`.
+    pub fn residual_and_jacobian(
+        &self,
+        params: &[&ParameterBlock],
+    ) -> (na::DVector<f64>, na::DMatrix<f64>) {
+        let mut values: Vec<na::DVector<f64>> = params.iter().map(|p| p.params.clone()).collect();
+        let residual = (self.residual_func)(&values);
+        let dim_variable = params.iter().map(|p| p.tangent_size()).sum();
+        let mut jacobian = na::DMatrix::zeros(residual.nrows(), dim_variable);
+
+        let mut col = 0;
+        for (param_idx, param) in params.iter().enumerate() {
+            let tangent_size = param.tangent_size();
+            for j in 0..tangent_size {
+                // parameters on a manifold are perturbed around zero
+                let scale = if param.manifold.is_none() {
+                    param.params[j].abs()
+                } else {
+                    0.0
+                };
+                let mut evaluate = |h: f64| {
+                    let mut dx = na::DVector::zeros(tangent_size);
+                    dx[j] = h;
+                    values[param_idx] = param.plus_f64(dx.as_view());
+                    let perturbed = (self.residual_func)(&values);
+                    values[param_idx] = param.params.clone();
+                    perturbed
+                };
+                let column = match self.options.method {
+                    NumericDiffMethod::Forward => {
+                        let h = step_size(self.options.relative_step_size, scale);
+                        (evaluate(h) - &residual) / h
+                    }
+                    NumericDiffMethod::Central => {
+                        let h = step_size(self.options.relative_step_size, scale);
+                        (evaluate(h) - evaluate(-h)) / (2.0 * h)
+                    }
+                    NumericDiffMethod::Ridders => ridders(&mut evaluate, scale, &self.options),
+                };
+                jacobian.column_mut(col).copy_from(&column);
+                col += 1;
+            }
+        }
+        (residual, jacobian)
+    }
+}
+
+impl<F> FactorImpl for NumericDiffFactor<F>
+where
+    F: Fn(&[na::DVector<f64>]) -> na::DVector<f64> + Send + Sync,
+{
+    /// First order expansion with the finite difference Jacobian.
+    fn residual_func_dual(&self, params: &[na::DVector<DualDVec64>]) -> na::DVector<DualDVec64> {
+        let blocks: Vec<ParameterBlock> = params
+            .iter()
+            .map(|p| ParameterBlock::from_vec(p.map(|x| x.re)))
+            .collect();
+        let blocks: Vec<&ParameterBlock> = blocks.iter().collect();
+        let (residual, jacobian) = self.residual_and_jacobian(&blocks);
+        first_order_dual(params, &residual, &jacobian)
+    }
+    fn residual_func_f64(&self, params: &[na::DVector<f64>]) -> na::DVector<f64> {
+        (self.residual_func)(params)
+    }
+    fn tangent_residual_and_jacobian(
+        &self,
+        params: &[&ParameterBlock],
+    ) -> Option<(na::DVector<f64>, na::DMatrix<f64>)> {
+        Some(self.residual_and_jacobian(params))
+    }
+}
+
+fn step_size(relative_step_size: f64, scale: f64) -> f64 {
+    if scale > 0.0 {
+        relative_step_size * scale
+    } else {
+        relative_step_size
+    }
+}
+
+/// Ridders' method, see Numerical Recipes 5.7. The table holds central
+/// differences with steps shrinking by `ridders_step_shrink_factor`, which are
+/// extrapolated to a zero step.
+fn ridders(
+    evaluate: &mut impl FnMut(f64) -> na::DVector<f64>,
+    scale: f64,
+    options: &NumericDiffOptions,
+) -> na::DVector<f64> {
+    let mut central = |h: f64| (evaluate(h) - evaluate(-h)) / (2.0 * h);
+    let shrink2 = options.ridders_step_shrink_factor * options.ridders_step_shrink_factor;
+    let mut h = step_size(options.ridders_relative_initial_step_size, scale);
+
+    let mut previous_row = vec![central(h)];
+    let mut best = previous_row[0].clone();
+    let mut best_error = f64::INFINITY;
+    for _ in 1..options.max_num_ridders_extrapolations.max(1) {
+        h /= options.ridders_step_shrink_factor;
+        let mut row = vec![central(h)];
+        let mut factor = shrink2;
+        for j in 1..=previous_row.len() {
+            let extrapolated = (&row[j - 1] * factor - &previous_row[j - 1]) / (factor - 1.0);
+            factor *= shrink2;
+            let error = (&extrapolated - &row[j - 1])
+                .amax()
+                .max((&extrapolated - &previous_row[j - 1]).amax());
+            if error <= best_error {
+                best_error = error;
+                best = extrapolated.clone();
+            }
+            row.push(extrapolated);
+        }
+        // the higher order estimates got worse, round off errors dominate
+        let diverged = (&row[row.len() - 1] - &previous_row[previous_row.len() - 1]).amax()
+            >= 2.0 * best_error;
+        if diverged || best_error < options.ridders_epsilon {
+            break;
+        }
+        previous_row = row;
+    }
+    best
+}
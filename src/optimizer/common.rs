@@ -1,26 +1,65 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::ops::Add;
+use std::time::{Duration, Instant};
 
 use nalgebra as na;
 
+use crate::error::TinySolverError;
+use crate::linear;
 use crate::parameter_block::ParameterBlock;
 use crate::problem;
-use crate::sparse::LinearSolverType;
+use crate::solver_context::SolverContext;
+use crate::sparse::{LinearSolverType, SparseLinearSolver};
+use crate::variable_key::{VariableKey, key_to_string};
 
 pub trait Optimizer {
-    fn optimize(
+    fn optimize<K: VariableKey>(
         &self,
-        problem: &problem::Problem,
-        initial_values: &HashMap<String, na::DVector<f64>>,
-        optimizer_option: Option<OptimizerOptions>,
-    ) -> Option<HashMap<String, na::DVector<f64>>>;
-    fn apply_dx(
+        problem: &problem::Problem<K>,
+        initial_values: &HashMap<K, na::DVector<f64>>,
+        optimizer_option: Option<OptimizerOptions<K>>,
+    ) -> Result<HashMap<K, na::DVector<f64>>, TinySolverError> {
+        self.optimize_with_summary(problem, initial_values, optimizer_option)
+            .0
+    }
+    /// Same as `optimize` but also returns a summary of the solve, which is
+    /// available even if the optimization failed.
+    fn optimize_with_summary<K: VariableKey>(
+        &self,
+        problem: &problem::Problem<K>,
+        initial_values: &HashMap<K, na::DVector<f64>>,
+        optimizer_option: Option<OptimizerOptions<K>>,
+    ) -> (
+        Result<HashMap<K, na::DVector<f64>>, TinySolverError>,
+        SolverSummary,
+    ) {
+        self.optimize_with_context(
+            problem,
+            initial_values,
+            optimizer_option,
+            &mut SolverContext::new(),
+        )
+    }
+    /// Same as `optimize_with_summary` but reuses the symbolic analysis kept
+    /// in `context` by earlier solves of a problem with the same structure.
+    fn optimize_with_context<K: VariableKey>(
+        &self,
+        problem: &problem::Problem<K>,
+        initial_values: &HashMap<K, na::DVector<f64>>,
+        optimizer_option: Option<OptimizerOptions<K>>,
+        context: &mut SolverContext<K>,
+    ) -> (
+        Result<HashMap<K, na::DVector<f64>>, TinySolverError>,
+        SolverSummary,
+    );
+    fn apply_dx<K: VariableKey>(
         &self,
         dx: &na::DVector<f64>,
-        params: &mut HashMap<String, na::DVector<f64>>,
-        variable_name_to_col_idx_dict: &HashMap<String, usize>,
-        fixed_var_indexes: &HashMap<String, HashSet<usize>>,
-        variable_bounds: &HashMap<String, HashMap<usize, (f64, f64)>>,
+        params: &mut HashMap<K, na::DVector<f64>>,
+        variable_name_to_col_idx_dict: &HashMap<K, usize>,
+        fixed_var_indexes: &HashMap<K, HashSet<usize>>,
+        variable_bounds: &HashMap<K, HashMap<usize, (f64, f64)>>,
     ) {
         for (key, param) in params.iter_mut() {
             if let Some(col_idx) = variable_name_to_col_idx_dict.get(key) {
@@ -28,7 +67,7 @@ pub trait Optimizer {
                 let mut updated_param = param.clone().add(dx.rows(*col_idx, var_size));
                 if let Some(indexes_to_fix) = fixed_var_indexes.get(key) {
                     for &idx in indexes_to_fix {
-                        log::debug!("Fix {} {}", key, idx);
+                        log::debug!("Fix {} {}", key_to_string(key), idx);
                         updated_param[idx] = param[idx];
                     }
                 }
@@ -36,7 +75,13 @@ pub trait Optimizer {
                     for (&idx, &(lower, upper)) in indexes_to_bound {
                         let old = updated_param[idx];
                         updated_param[idx] = updated_param[idx].max(lower).min(upper);
-                        log::debug!("bound {} {} {} -> {}", key, idx, old, updated_param[idx]);
+                        log::debug!(
+                            "bound {} {} {} -> {}",
+                            key_to_string(key),
+                            idx,
+                            old,
+                            updated_param[idx]
+                        );
                     }
                 }
                 param.copy_from(&updated_param);
@@ -46,30 +91,21 @@ pub trait Optimizer {
     fn apply_dx2(
         &self,
         dx: &na::DVector<f64>,
-        params: &mut HashMap<String, ParameterBlock>,
-        variable_name_to_col_idx_dict: &HashMap<String, usize>,
+        params: &mut [Option<ParameterBlock>],
+        variable_idx_to_col_idx: &[usize],
     ) {
-        params.iter_mut().for_each(|(key, param)| {
-            if let Some(col_idx) = variable_name_to_col_idx_dict.get(key) {
+        params.iter_mut().enumerate().for_each(|(var_idx, param)| {
+            if let Some(param) = param {
+                let col_idx = variable_idx_to_col_idx[var_idx];
                 let tangent_size = param.tangent_size();
-                let effective_size = if param.manifold.is_some() {
-                    tangent_size
-                } else {
-                    tangent_size - param.fixed_variables.len()
-                };
-
-                let dx_reduced = dx.rows(*col_idx, effective_size);
+                let dx_reduced = dx.rows(col_idx, param.free_tangent_size());
 
                 let mut dx_full = na::DVector::zeros(tangent_size);
-                if param.manifold.is_some() {
-                    dx_full.copy_from(&dx_reduced);
-                } else {
-                    let mut reduced_idx = 0;
-                    for i in 0..tangent_size {
-                        if !param.fixed_variables.contains(&i) {
-                            dx_full[i] = dx_reduced[reduced_idx];
-                            reduced_idx += 1;
-                        }
+                let mut reduced_idx = 0;
+                for i in 0..tangent_size {
+                    if !param.fixed_variables.contains(&i) {
+                        dx_full[i] = dx_reduced[reduced_idx];
+                        reduced_idx += 1;
                     }
                 }
                 param.update_params(param.plus_f64(dx_full.rows(0, tangent_size)));
@@ -78,51 +114,277 @@ pub trait Optimizer {
         // for (key, param) in params.par_iter_mut() {
         // }
     }
-    fn compute_error(
+    fn compute_error<K: VariableKey>(
         &self,
-        problem: &problem::Problem,
-        params: &HashMap<String, ParameterBlock>,
-    ) -> f64 {
-        problem
-            .compute_residuals(params, true)
-            .as_ref()
-            .squared_norm_l2()
+        problem: &problem::Problem<K>,
+        params: &[Option<ParameterBlock>],
+    ) -> Result<f64, TinySolverError> {
+        problem.compute_cost(params)
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
 pub enum SolverStatus {
+    #[default]
     Running,
     // Resulting solution may be OK to use.
-    GradientTooSmall,         // eps > max(J'*f(x))
-    RelativeStepSizeTooSmall, // eps > ||dx|| / ||x||
-    ErrorTooSmall,            // eps > ||f(x)||
+    GradientTooSmall,              // eps > max(J'*f(x))
+    RelativeStepSizeTooSmall,      // eps > ||dx|| / ||x||
+    ErrorTooSmall,                 // eps > ||f(x)||
+    AbsoluteErrorDecreaseTooSmall, // eps > |f(x_k-1) - f(x_k)|
+    RelativeErrorDecreaseTooSmall, // eps > |f(x_k-1) - f(x_k)| / f(x_k-1)
     HitMaxIterations,
+    // The problem does not match the initial values, see `Problem::validate`.
+    InvalidProblem,
     // Numerical issues
-    // FAILED_TO_EVALUATE_COST_FUNCTION,
-    // FAILED_TO_SOLVER_LINEAR_SYSTEM,
+    FailedToEvaluateCostFunction,
+    FailedToSolveLinearSystem,
+}
+
+impl SolverStatus {
+    pub fn is_solution_usable(&self) -> bool {
+        !matches!(
+            self,
+            SolverStatus::Running
+                | SolverStatus::InvalidProblem
+                | SolverStatus::FailedToEvaluateCostFunction
+                | SolverStatus::FailedToSolveLinearSystem
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IterationSummary {
+    pub iteration: usize,
+    /// Cost after this iteration.
+    pub cost: f64,
+    /// Cost decrease of this iteration, zero for rejected steps.
+    pub cost_change: f64,
+    /// max(|J^T * r|) at the start of this iteration.
+    pub gradient_max_norm: f64,
+    pub step_norm: f64,
+    /// Damping (lambda) used to compute the step, zero for Gauss-Newton.
+    pub damping: f64,
+    /// Ratio between the actual and the predicted cost decrease.
+    pub trust_region_ratio: f64,
+    pub step_is_successful: bool,
+    pub residual_evaluation_time: Duration,
+    pub jacobian_evaluation_time: Duration,
+    pub linear_solver_time: Duration,
+    pub iteration_time: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SolverSummary {
+    pub initial_cost: f64,
+    pub final_cost: f64,
+    pub iterations: Vec<IterationSummary>,
+    pub num_successful_steps: usize,
+    pub num_unsuccessful_steps: usize,
+    pub residual_evaluation_time: Duration,
+    pub jacobian_evaluation_time: Duration,
+    pub linear_solver_time: Duration,
+    pub total_time: Duration,
+    pub status: SolverStatus,
+}
+
+impl SolverSummary {
+    pub fn is_solution_usable(&self) -> bool {
+        self.status.is_solution_usable()
+    }
+    pub(crate) fn add_iteration(&mut self, iteration_summary: IterationSummary) {
+        if iteration_summary.step_is_successful {
+            self.num_successful_steps += 1;
+        } else {
+            self.num_unsuccessful_steps += 1;
+        }
+        self.residual_evaluation_time += iteration_summary.residual_evaluation_time;
+        self.jacobian_evaluation_time += iteration_summary.jacobian_evaluation_time;
+        self.linear_solver_time += iteration_summary.linear_solver_time;
+        self.final_cost = iteration_summary.cost;
+        self.iterations.push(iteration_summary);
+    }
+    /// Ends the solve with `err`, for early returns from the optimizers.
+    pub(crate) fn failed<T>(
+        mut self,
+        status: SolverStatus,
+        err: TinySolverError,
+        total_start: Instant,
+    ) -> (Result<T, TinySolverError>, SolverSummary) {
+        log::debug!("{}", err);
+        self.status = status;
+        self.total_time = total_start.elapsed();
+        (Err(err), self)
+    }
+}
+
+impl fmt::Display for SolverSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "iter {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            "cost", "cost_change", "|gradient|", "|step|", "lambda", "tr_ratio"
+        )?;
+        for it in &self.iterations {
+            writeln!(
+                f,
+                "{:4} {:12.4e} {:12.4e} {:12.4e} {:12.4e} {:12.4e} {:12.4e}",
+                it.iteration,
+                it.cost,
+                it.cost_change,
+                it.gradient_max_norm,
+                it.step_norm,
+                it.damping,
+                it.trust_region_ratio
+            )?;
+        }
+        writeln!(f, "Initial cost: {:e}", self.initial_cost)?;
+        writeln!(f, "Final cost: {:e}", self.final_cost)?;
+        writeln!(
+            f,
+            "Steps: {} successful, {} unsuccessful",
+            self.num_successful_steps, self.num_unsuccessful_steps
+        )?;
+        writeln!(
+            f,
+            "Time: residual {:?}, jacobian {:?}, linear solver {:?}, total {:?}",
+            self.residual_evaluation_time,
+            self.jacobian_evaluation_time,
+            self.linear_solver_time,
+            self.total_time
+        )?;
+        write!(f, "Termination: {:?}", self.status)
+    }
 }
 
 #[derive(Clone)]
-pub struct OptimizerOptions {
+pub struct OptimizerOptions<K = String> {
     pub max_iteration: usize,
     pub linear_solver_type: LinearSolverType,
     pub verbosity_level: usize,
+    /// Stops when the cost decreases by less than this. The cost is
+    /// `0.5 * sum(rho(|f(x)|^2))`, half the squared norm of the residuals
+    /// without loss functions.
     pub min_abs_error_decrease_threshold: f64,
     pub min_rel_error_decrease_threshold: f64,
+    /// Stops when the cost, on the same scale as
+    /// `min_abs_error_decrease_threshold`, is below this.
     pub min_error_threshold: f64,
     // pub relative_step_threshold: 1e-16,
+    /// Variables eliminated first by the Schur complement solvers, e.g. the
+    /// landmarks in bundle adjustment. No two of them may share a residual block.
+    /// If `None`, an independent set is picked by `Problem::independent_variable_set`.
+    pub elimination_ordering: Option<Vec<K>>,
+    /// Max iterations of iterative linear solvers.
+    pub max_linear_solver_iteration: usize,
+    /// Upper bound of the forcing sequence, the relative tolerance of
+    /// iterative linear solvers.
+    pub eta: f64,
 }
 
-impl Default for OptimizerOptions {
+impl<K> Default for OptimizerOptions<K> {
     fn default() -> Self {
         OptimizerOptions {
             max_iteration: 100,
             linear_solver_type: LinearSolverType::SparseCholesky,
             verbosity_level: 0,
-            min_abs_error_decrease_threshold: 1e-5,
+            min_abs_error_decrease_threshold: 5e-6,
             min_rel_error_decrease_threshold: 1e-5,
-            min_error_threshold: 1e-10,
+            min_error_threshold: 5e-11,
+            elimination_ordering: None,
+            max_linear_solver_iteration: 500,
+            eta: 1e-1,
+        }
+    }
+}
+
+/// Forcing sequence of the inexact (iterative) linear solvers,
+/// `min(eta, sqrt(|g| / |g0|))` with `g0` the first gradient of the solve, so
+/// the linear system is solved more accurately as the gradient vanishes.
+pub(crate) struct ForcingSequence {
+    eta: f64,
+    initial_gradient_max_norm: Option<f64>,
+}
+
+impl ForcingSequence {
+    pub(crate) fn new(eta: f64) -> Self {
+        ForcingSequence {
+            eta,
+            initial_gradient_max_norm: None,
+        }
+    }
+    /// Sets the relative tolerance of `linear_solver` for the gradient at
+    /// the current iterate.
+    pub(crate) fn update(
+        &mut self,
+        linear_solver: &mut dyn SparseLinearSolver,
+        gradient_max_norm: f64,
+    ) {
+        let initial_gradient_max_norm = *self
+            .initial_gradient_max_norm
+            .get_or_insert(gradient_max_norm);
+        if initial_gradient_max_norm > 0.0 {
+            linear_solver.set_relative_tolerance(
+                self.eta
+                    .min((gradient_max_norm / initial_gradient_max_norm).sqrt()),
+            );
+        }
+    }
+}
+
+pub(crate) fn create_linear_solver<K: VariableKey>(
+    problem: &problem::Problem<K>,
+    parameter_blocks: &[Option<ParameterBlock>],
+    variable_idx_to_col_idx: &[usize],
+    opt_option: &OptimizerOptions<K>,
+) -> Box<dyn SparseLinearSolver + Send> {
+    // (start column, size) of the given variables, sorted by column
+    let column_blocks = |variables: &mut dyn Iterator<Item = usize>| {
+        let mut blocks: Vec<(usize, usize)> = variables
+            .filter_map(|var_idx| {
+                let param = parameter_blocks[var_idx].as_ref()?;
+                let col_idx = variable_idx_to_col_idx[var_idx];
+                let effective_size = param.free_tangent_size();
+                (effective_size > 0).then_some((col_idx, effective_size))
+            })
+            .collect();
+        blocks.sort();
+        blocks
+    };
+    let eliminate_blocks = || {
+        let eliminate_variables = match &opt_option.elimination_ordering {
+            Some(keys) => keys
+                .iter()
+                .filter_map(|key| problem.get_variable_idx(key))
+                .collect(),
+            None => problem.independent_variable_idx_set(parameter_blocks),
+        };
+        column_blocks(&mut eliminate_variables.into_iter())
+    };
+    match opt_option.linear_solver_type {
+        LinearSolverType::SparseCholesky => Box::new(linear::SparseCholeskySolver::new()),
+        LinearSolverType::SparseQR => Box::new(linear::SparseQRSolver::new()),
+        LinearSolverType::DenseSchur => {
+            Box::new(linear::SchurComplementSolver::new_dense(eliminate_blocks()))
+        }
+        LinearSolverType::SparseSchur => {
+            Box::new(linear::SchurComplementSolver::new_sparse(eliminate_blocks()))
+        }
+        LinearSolverType::ConjugateGradient(preconditioner_type) => {
+            let eliminate_blocks = if preconditioner_type == linear::PreconditionerType::SchurJacobi
+            {
+                eliminate_blocks()
+            } else {
+                Vec::new()
+            };
+            Box::new(
+                linear::ConjugateGradientSolver::new(
+                    preconditioner_type,
+                    column_blocks(&mut (0..parameter_blocks.len())),
+                    eliminate_blocks,
+                )
+                .with_max_iterations(opt_option.max_linear_solver_iteration),
+            )
         }
     }
 }
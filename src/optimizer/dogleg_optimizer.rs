@@ -0,0 +1,437 @@
+use log::trace;
+use std::{collections::HashMap, time::Instant};
+
+use faer_ext::{IntoFaer, IntoNalgebra};
+use nalgebra as na;
+
+use crate::common::{
+    ForcingSequence, IterationSummary, OptimizerOptions, SolverStatus, SolverSummary,
+};
+use crate::error::TinySolverError;
+use crate::optimizer;
+use crate::parameter_block::ParameterBlock;
+use crate::solver_context::SolverContext;
+use crate::sparse::SparseLinearSolver;
+use crate::variable_key::VariableKey;
+
+const DEFAULT_INITIAL_TRUST_REGION_RADIUS: f64 = 1e4;
+const DEFAULT_MAX_TRUST_REGION_RADIUS: f64 = 1e16;
+const DEFAULT_MIN_TRUST_REGION_RADIUS: f64 = 1e-32;
+
+/// https://github.com/ceres-solver/ceres-solver/blob/master/internal/ceres/dogleg_strategy.cc
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DoglegType {
+    /// Piecewise linear path from the Cauchy point to the Gauss-Newton step.
+    #[default]
+    Traditional,
+    /// Minimize the model over the 2D subspace spanned by the gradient and
+    /// the Gauss-Newton step.
+    Subspace,
+}
+
+#[derive(Debug)]
+pub struct DoglegOptimizer {
+    dogleg_type: DoglegType,
+    initial_trust_region_radius: f64,
+    max_trust_region_radius: f64,
+    min_trust_region_radius: f64,
+}
+
+impl DoglegOptimizer {
+    pub fn new(
+        dogleg_type: DoglegType,
+        initial_trust_region_radius: f64,
+        max_trust_region_radius: f64,
+        min_trust_region_radius: f64,
+    ) -> Self {
+        Self {
+            dogleg_type,
+            initial_trust_region_radius,
+            max_trust_region_radius,
+            min_trust_region_radius,
+        }
+    }
+    pub fn with_type(dogleg_type: DoglegType) -> Self {
+        Self {
+            dogleg_type,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for DoglegOptimizer {
+    fn default() -> Self {
+        Self {
+            dogleg_type: DoglegType::Traditional,
+            initial_trust_region_radius: DEFAULT_INITIAL_TRUST_REGION_RADIUS,
+            max_trust_region_radius: DEFAULT_MAX_TRUST_REGION_RADIUS,
+            min_trust_region_radius: DEFAULT_MIN_TRUST_REGION_RADIUS,
+        }
+    }
+}
+
+/// Linearization of the problem at the current parameters.
+/// The model is |r + J * h|^2 = |r|^2 + 2 * g^T * h + |J * h|^2 with g = J^T * r.
+struct Linearization {
+    jacobian: faer::sparse::SparseColMat<usize, f64>,
+    gradient: na::DVector<f64>,
+    gauss_newton_step: Option<na::DVector<f64>>,
+    cauchy_step: na::DVector<f64>,
+}
+
+impl Linearization {
+    fn jacobian_mul(&self, v: &na::DVector<f64>) -> na::DVector<f64> {
+        let jv = self.jacobian.as_ref() * v.view_range(.., ..).into_faer();
+        jv.as_ref().into_nalgebra().column(0).clone_owned()
+    }
+
+    /// Decrease of the cost 0.5 * |r|^2 predicted by the linear model.
+    fn model_decrease(&self, step: &na::DVector<f64>) -> f64 {
+        -(self.gradient.dot(step) + 0.5 * self.jacobian_mul(step).norm_squared())
+    }
+}
+
+impl DoglegOptimizer {
+    fn traditional_step(&self, lin: &Linearization, radius: f64) -> na::DVector<f64> {
+        let Some(gauss_newton_step) = &lin.gauss_newton_step else {
+            return truncate(&lin.cauchy_step, radius);
+        };
+        if gauss_newton_step.norm() <= radius {
+            return gauss_newton_step.clone();
+        }
+        let cauchy_norm = lin.cauchy_step.norm();
+        if cauchy_norm >= radius {
+            return truncate(&lin.cauchy_step, radius);
+        }
+        // find beta so that |a + beta * (b - a)| = radius
+        let a = &lin.cauchy_step;
+        let b_minus_a = gauss_newton_step - a;
+        let c = a.dot(&b_minus_a);
+        let b_minus_a_sq = b_minus_a.norm_squared();
+        let d = (c * c + b_minus_a_sq * (radius * radius - cauchy_norm * cauchy_norm)).sqrt();
+        let beta = if c <= 0.0 {
+            (d - c) / b_minus_a_sq
+        } else {
+            (radius * radius - cauchy_norm * cauchy_norm) / (d + c)
+        };
+        a + b_minus_a * beta
+    }
+
+    fn subspace_step(&self, lin: &Linearization, radius: f64) -> na::DVector<f64> {
+        let Some(gauss_newton_step) = &lin.gauss_newton_step else {
+            return truncate(&lin.cauchy_step, radius);
+        };
+        let gradient_norm = lin.gradient.norm();
+        if gradient_norm == 0.0 {
+            return truncate(gauss_newton_step, radius);
+        }
+        // orthonormal basis of span{g, gn}
+        let q0 = &lin.gradient / gradient_norm;
+        let gn_perp = gauss_newton_step - &q0 * q0.dot(gauss_newton_step);
+        let gn_perp_norm = gn_perp.norm();
+        if gn_perp_norm <= 1e-10 * gauss_newton_step.norm() {
+            // the subspace is one dimensional
+            return self.traditional_step(lin, radius);
+        }
+        let q1 = gn_perp / gn_perp_norm;
+
+        let jq0 = lin.jacobian_mul(&q0);
+        let jq1 = lin.jacobian_mul(&q1);
+        let b = na::Matrix2::new(jq0.dot(&jq0), jq0.dot(&jq1), jq1.dot(&jq0), jq1.dot(&jq1));
+        let q = na::Vector2::new(q0.dot(&lin.gradient), q1.dot(&lin.gradient));
+        let y = minimize_2d_trust_region(&b, &q, radius);
+        q0 * y[0] + q1 * y[1]
+    }
+
+    fn linearize<K: VariableKey>(
+        &self,
+        problem: &crate::problem::Problem<K>,
+        parameter_blocks: &[Option<ParameterBlock>],
+        variable_idx_to_col_idx: &[usize],
+        symbolic_structure: &crate::problem::SymbolicStructure,
+        linear_solver: &mut dyn SparseLinearSolver,
+        forcing_sequence: &mut ForcingSequence,
+    ) -> Result<(Linearization, std::time::Duration, std::time::Duration), TinySolverError> {
+        let start = Instant::now();
+        let (residuals, jacobian) = problem.compute_residual_and_jacobian(
+            parameter_blocks,
+            variable_idx_to_col_idx,
+            symbolic_structure,
+        )?;
+        let jacobian_evaluation_time = start.elapsed();
+
+        let gradient = (jacobian.as_ref().transpose() * &residuals)
+            .as_ref()
+            .into_nalgebra()
+            .column(0)
+            .clone_owned();
+        forcing_sequence.update(linear_solver, gradient.amax());
+
+        let start = Instant::now();
+        let gauss_newton_step = linear_solver
+            .solve(&residuals, &jacobian)
+            .ok()
+            .map(|dx| dx.as_ref().into_nalgebra().column(0).clone_owned())
+            .filter(|dx| dx.iter().all(|v| v.is_finite()));
+        if gauss_newton_step.is_none() {
+            log::debug!("gauss newton step failed, fall back to steepest descent");
+        }
+        let linear_solver_time = start.elapsed();
+
+        let mut lin = Linearization {
+            jacobian,
+            gradient,
+            gauss_newton_step,
+            cauchy_step: na::DVector::zeros(0),
+        };
+        // minimizer of the model along -g
+        let jg = lin.jacobian_mul(&lin.gradient);
+        let jg_sq = jg.norm_squared();
+        lin.cauchy_step = if jg_sq > 0.0 {
+            &lin.gradient * (-lin.gradient.norm_squared() / jg_sq)
+        } else {
+            na::DVector::zeros(lin.gradient.nrows())
+        };
+        Ok((lin, jacobian_evaluation_time, linear_solver_time))
+    }
+}
+
+fn truncate(step: &na::DVector<f64>, radius: f64) -> na::DVector<f64> {
+    let norm = step.norm();
+    if norm > radius {
+        step * (radius / norm)
+    } else {
+        step.clone()
+    }
+}
+
+/// Minimizes 0.5 * y^T * B * y + q^T * y subject to |y| <= radius.
+fn minimize_2d_trust_region(
+    b: &na::Matrix2<f64>,
+    q: &na::Vector2<f64>,
+    radius: f64,
+) -> na::Vector2<f64> {
+    let eigen = na::SymmetricEigen::new(*b);
+    let min_eigenvalue = eigen.eigenvalues.min();
+    if min_eigenvalue > 0.0 {
+        let y = -(eigen.eigenvectors
+            * na::Matrix2::from_diagonal(&eigen.eigenvalues.map(|v| 1.0 / v))
+            * eigen.eigenvectors.transpose()
+            * q);
+        if y.norm() <= radius {
+            return y;
+        }
+    }
+    // (B + lambda * I) * y = -q with |y| = radius
+    let q_rot = eigen.eigenvectors.transpose() * q;
+    let y_of = |lambda: f64| {
+        let y_rot = na::Vector2::new(
+            -q_rot[0] / (eigen.eigenvalues[0] + lambda),
+            -q_rot[1] / (eigen.eigenvalues[1] + lambda),
+        );
+        eigen.eigenvectors * y_rot
+    };
+    let mut lower = (-min_eigenvalue).max(0.0);
+    let mut upper = lower + q.norm() / radius + 1.0;
+    while y_of(upper).norm() > radius {
+        upper *= 2.0;
+    }
+    for _ in 0..100 {
+        let mid = 0.5 * (lower + upper);
+        if y_of(mid).norm() > radius {
+            lower = mid;
+        } else {
+            upper = mid;
+        }
+    }
+    let y = y_of(upper);
+    if y.iter().all(|v| v.is_finite()) {
+        y
+    } else {
+        // hard case, move along the eigenvector of the smallest eigenvalue
+        let idx = if eigen.eigenvalues[0] <= eigen.eigenvalues[1] {
+            0
+        } else {
+            1
+        };
+        let v = eigen.eigenvectors.column(idx).clone_owned() * radius;
+        if v.dot(q) > 0.0 { -v } else { v }
+    }
+}
+
+impl optimizer::Optimizer for DoglegOptimizer {
+    fn optimize_with_context<K: VariableKey>(
+        &self,
+        problem: &crate::problem::Problem<K>,
+        initial_values: &HashMap<K, nalgebra::DVector<f64>>,
+        optimizer_option: Option<OptimizerOptions<K>>,
+        context: &mut SolverContext<K>,
+    ) -> (
+        Result<HashMap<K, nalgebra::DVector<f64>>, TinySolverError>,
+        SolverSummary,
+    ) {
+        let total_start = Instant::now();
+        let mut summary = SolverSummary::default();
+        if let Err(err) = problem.validate(initial_values) {
+            return summary.failed(SolverStatus::InvalidProblem, err, total_start);
+        }
+
+        let mut parameter_blocks: Vec<Option<ParameterBlock>> =
+            problem.initialize_parameter_blocks(initial_values);
+
+        let opt_option = optimizer_option.unwrap_or_default();
+        let (structure, linear_solver) = context.prepare(problem, &parameter_blocks, &opt_option);
+        let variable_idx_to_col_idx = &structure.variable_idx_to_col_idx;
+        let symbolic_structure = &structure.symbolic_structure;
+
+        let mut radius = self.initial_trust_region_radius;
+
+        let mut last_err;
+        let mut start = Instant::now();
+        let mut current_error = match self.compute_error(problem, &parameter_blocks) {
+            Ok(error) => error,
+            Err(err) => {
+                return summary.failed(
+                    SolverStatus::FailedToEvaluateCostFunction,
+                    err,
+                    total_start,
+                );
+            }
+        };
+        summary.residual_evaluation_time += start.elapsed();
+        summary.initial_cost = current_error;
+        summary.final_cost = current_error;
+        summary.status = SolverStatus::HitMaxIterations;
+
+        // the linearization is only recomputed after a successful step
+        let mut linearization = None;
+        let mut forcing_sequence = ForcingSequence::new(opt_option.eta);
+        for i in 0..opt_option.max_iteration {
+            let iteration_start = Instant::now();
+            last_err = current_error;
+
+            let mut jacobian_evaluation_time = std::time::Duration::ZERO;
+            let mut linear_solver_time = std::time::Duration::ZERO;
+            if linearization.is_none() {
+                let (lin, jacobian_time, solver_time) = match self.linearize(
+                    problem,
+                    &parameter_blocks,
+                    variable_idx_to_col_idx,
+                    symbolic_structure,
+                    linear_solver,
+                    &mut forcing_sequence,
+                ) {
+                    Ok(linearized) => linearized,
+                    Err(err) => {
+                        return summary.failed(
+                            SolverStatus::FailedToEvaluateCostFunction,
+                            err,
+                            total_start,
+                        );
+                    }
+                };
+                jacobian_evaluation_time = jacobian_time;
+                linear_solver_time = solver_time;
+                linearization = Some(lin);
+            }
+            let lin = linearization.as_ref().unwrap();
+            let gradient_max_norm = lin.gradient.amax();
+
+            let step = match self.dogleg_type {
+                DoglegType::Traditional => self.traditional_step(lin, radius),
+                DoglegType::Subspace => self.subspace_step(lin, radius),
+            };
+            let step_norm = step.norm();
+
+            let mut new_param_blocks = parameter_blocks.clone();
+            self.apply_dx2(&step, &mut new_param_blocks, variable_idx_to_col_idx);
+
+            start = Instant::now();
+            let new_error = match self.compute_error(problem, &new_param_blocks) {
+                Ok(error) => error,
+                Err(err) => {
+                    return summary.failed(
+                        SolverStatus::FailedToEvaluateCostFunction,
+                        err,
+                        total_start,
+                    );
+                }
+            };
+            let residual_evaluation_time = start.elapsed();
+
+            let actual_decrease = current_error - new_error;
+            let predicted_decrease = lin.model_decrease(&step);
+            let rho = actual_decrease / predicted_decrease;
+            trace!(
+                "iter:{} radius:{} rho:{} new err:{}",
+                i, radius, rho, new_error
+            );
+            let damping = 1.0 / radius;
+
+            let step_is_successful = rho > 0.0 && new_error.is_finite();
+            if step_is_successful {
+                parameter_blocks = new_param_blocks;
+                current_error = new_error;
+                linearization = None;
+            }
+            if rho > 0.75 {
+                radius = radius
+                    .max(3.0 * step_norm)
+                    .min(self.max_trust_region_radius);
+            } else if rho < 0.25 || rho.is_nan() {
+                radius *= 0.5;
+            }
+
+            summary.add_iteration(IterationSummary {
+                iteration: i,
+                cost: current_error,
+                cost_change: last_err - current_error,
+                gradient_max_norm,
+                step_norm,
+                damping,
+                trust_region_ratio: rho,
+                step_is_successful,
+                residual_evaluation_time,
+                jacobian_evaluation_time,
+                linear_solver_time,
+                iteration_time: iteration_start.elapsed(),
+            });
+
+            if current_error < opt_option.min_error_threshold {
+                trace!("error too low");
+                summary.status = SolverStatus::ErrorTooSmall;
+                break;
+            } else if !current_error.is_finite() {
+                return summary.failed(
+                    SolverStatus::FailedToEvaluateCostFunction,
+                    TinySolverError::NonFiniteCost,
+                    total_start,
+                );
+            }
+            if radius < self.min_trust_region_radius {
+                trace!("trust region radius too small");
+                summary.status = SolverStatus::RelativeStepSizeTooSmall;
+                break;
+            }
+            if !step_is_successful {
+                continue;
+            }
+
+            if (last_err - current_error).abs() < opt_option.min_abs_error_decrease_threshold {
+                trace!("absolute error decrease low");
+                summary.status = SolverStatus::AbsoluteErrorDecreaseTooSmall;
+                break;
+            } else if last_err > 0.0
+                && (last_err - current_error).abs() / last_err
+                    < opt_option.min_rel_error_decrease_threshold
+            {
+                trace!("relative error decrease low");
+                summary.status = SolverStatus::RelativeErrorDecreaseTooSmall;
+                break;
+            }
+        }
+        let params = problem.parameter_blocks_to_values(&parameter_blocks, initial_values);
+        summary.total_time = total_start.elapsed();
+        (Ok(params), summary)
+    }
+}
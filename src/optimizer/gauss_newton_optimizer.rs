@@ -3,12 +3,14 @@ use std::{collections::HashMap, time::Instant};
 
 use faer_ext::IntoNalgebra;
 
-use crate::common::OptimizerOptions;
-use crate::linear;
+use crate::common::{
+    ForcingSequence, IterationSummary, OptimizerOptions, SolverStatus, SolverSummary,
+};
+use crate::error::TinySolverError;
 use crate::optimizer;
 use crate::parameter_block::ParameterBlock;
-use crate::sparse::LinearSolverType;
-use crate::sparse::SparseLinearSolver;
+use crate::solver_context::SolverContext;
+use crate::variable_key::VariableKey;
 
 #[derive(Debug)]
 pub struct GaussNewtonOptimizer {}
@@ -24,98 +26,145 @@ impl Default for GaussNewtonOptimizer {
 }
 
 impl optimizer::Optimizer for GaussNewtonOptimizer {
-    fn optimize(
+    fn optimize_with_context<K: VariableKey>(
         &self,
-        problem: &crate::problem::Problem,
-        initial_values: &std::collections::HashMap<String, nalgebra::DVector<f64>>,
-        optimizer_option: Option<OptimizerOptions>,
-    ) -> Option<HashMap<String, nalgebra::DVector<f64>>> {
-        let mut parameter_blocks: HashMap<String, ParameterBlock> =
-            problem.initialize_parameter_blocks(initial_values);
+        problem: &crate::problem::Problem<K>,
+        initial_values: &HashMap<K, nalgebra::DVector<f64>>,
+        optimizer_option: Option<OptimizerOptions<K>>,
+        context: &mut SolverContext<K>,
+    ) -> (
+        Result<HashMap<K, nalgebra::DVector<f64>>, TinySolverError>,
+        SolverSummary,
+    ) {
+        let total_start = Instant::now();
+        let mut summary = SolverSummary::default();
+        if let Err(err) = problem.validate(initial_values) {
+            return summary.failed(SolverStatus::InvalidProblem, err, total_start);
+        }
 
-        let variable_name_to_col_idx_dict =
-            problem.get_variable_name_to_col_idx_dict(&parameter_blocks);
-        let total_variable_dimension = parameter_blocks
-            .values()
-            .map(|p| {
-                if p.manifold.is_some() {
-                    p.tangent_size()
-                } else {
-                    p.tangent_size() - p.fixed_variables.len()
-                }
-            })
-            .sum();
+        let mut parameter_blocks: Vec<Option<ParameterBlock>> =
+            problem.initialize_parameter_blocks(initial_values);
 
         let opt_option = optimizer_option.unwrap_or_default();
-        let mut linear_solver: Box<dyn SparseLinearSolver> = match opt_option.linear_solver_type {
-            LinearSolverType::SparseCholesky => Box::new(linear::SparseCholeskySolver::new()),
-            LinearSolverType::SparseQR => Box::new(linear::SparseQRSolver::new()),
-        };
-
-        let symbolic_structure = problem.build_symbolic_structure(
-            &parameter_blocks,
-            total_variable_dimension,
-            &variable_name_to_col_idx_dict,
-        );
+        let (structure, linear_solver) = context.prepare(problem, &parameter_blocks, &opt_option);
+        let variable_idx_to_col_idx = &structure.variable_idx_to_col_idx;
+        let symbolic_structure = &structure.symbolic_structure;
 
         let mut last_err;
-        let mut current_error = self.compute_error(problem, &parameter_blocks);
+        let mut start = Instant::now();
+        let mut current_error = match self.compute_error(problem, &parameter_blocks) {
+            Ok(error) => error,
+            Err(err) => {
+                return summary.failed(
+                    SolverStatus::FailedToEvaluateCostFunction,
+                    err,
+                    total_start,
+                );
+            }
+        };
+        summary.residual_evaluation_time += start.elapsed();
+        summary.initial_cost = current_error;
+        summary.final_cost = current_error;
+        summary.status = SolverStatus::HitMaxIterations;
 
+        let mut forcing_sequence = ForcingSequence::new(opt_option.eta);
         for i in 0..opt_option.max_iteration {
+            let iteration_start = Instant::now();
             last_err = current_error;
-            let mut start = Instant::now();
+            start = Instant::now();
 
-            let (residuals, jac) = problem.compute_residual_and_jacobian(
+            let (residuals, jac) = match problem.compute_residual_and_jacobian(
                 &parameter_blocks,
-                &variable_name_to_col_idx_dict,
-                &symbolic_structure,
-            );
+                variable_idx_to_col_idx,
+                symbolic_structure,
+            ) {
+                Ok(linearization) => linearization,
+                Err(err) => {
+                    return summary.failed(
+                        SolverStatus::FailedToEvaluateCostFunction,
+                        err,
+                        total_start,
+                    );
+                }
+            };
             let residual_and_jacobian_duration = start.elapsed();
+            let gradient_max_norm = (jac.as_ref().transpose() * &residuals).norm_max();
+            forcing_sequence.update(linear_solver, gradient_max_norm);
 
             start = Instant::now();
-            let solving_duration;
-            if let Some(dx) = linear_solver.solve(&residuals, &jac) {
-                solving_duration = start.elapsed();
-                let dx_na = dx.as_ref().into_nalgebra().column(0).clone_owned();
-                self.apply_dx2(
-                    &dx_na,
-                    &mut parameter_blocks,
-                    &variable_name_to_col_idx_dict,
-                );
-            } else {
-                log::debug!("solve ax=b failed");
-                return None;
-            }
+            let dx = match linear_solver.solve(&residuals, &jac) {
+                Ok(dx) => dx,
+                Err(err) => {
+                    return summary.failed(
+                        SolverStatus::FailedToSolveLinearSystem,
+                        err,
+                        total_start,
+                    );
+                }
+            };
+            let solving_duration = start.elapsed();
+            let dx_na = dx.as_ref().into_nalgebra().column(0).clone_owned();
+            let step_norm = dx_na.norm();
+            self.apply_dx2(&dx_na, &mut parameter_blocks, variable_idx_to_col_idx);
 
-            current_error = self.compute_error(problem, &parameter_blocks);
+            start = Instant::now();
+            current_error = match self.compute_error(problem, &parameter_blocks) {
+                Ok(error) => error,
+                Err(err) => {
+                    return summary.failed(
+                        SolverStatus::FailedToEvaluateCostFunction,
+                        err,
+                        total_start,
+                    );
+                }
+            };
+            let residual_evaluation_time = start.elapsed();
             trace!(
                 "iter:{}, total err:{}, residual + jacobian duration: {:?}, solving duration: {:?}",
                 i, current_error, residual_and_jacobian_duration, solving_duration
             );
+            summary.add_iteration(IterationSummary {
+                iteration: i,
+                cost: current_error,
+                cost_change: last_err - current_error,
+                gradient_max_norm,
+                step_norm,
+                damping: 0.0,
+                trust_region_ratio: 1.0,
+                step_is_successful: true,
+                residual_evaluation_time,
+                jacobian_evaluation_time: residual_and_jacobian_duration,
+                linear_solver_time: solving_duration,
+                iteration_time: iteration_start.elapsed(),
+            });
 
             if current_error < opt_option.min_error_threshold {
                 trace!("error too low");
+                summary.status = SolverStatus::ErrorTooSmall;
                 break;
-            } else if current_error.is_nan() {
-                log::debug!("solve ax=b failed, current error is nan");
-                return None;
+            } else if !current_error.is_finite() {
+                return summary.failed(
+                    SolverStatus::FailedToEvaluateCostFunction,
+                    TinySolverError::NonFiniteCost,
+                    total_start,
+                );
             }
 
             if (last_err - current_error).abs() < opt_option.min_abs_error_decrease_threshold {
                 trace!("absolute error decrease low");
+                summary.status = SolverStatus::AbsoluteErrorDecreaseTooSmall;
                 break;
             } else if last_err > 0.0
                 && (last_err - current_error).abs() / last_err
                     < opt_option.min_rel_error_decrease_threshold
             {
                 trace!("relative error decrease low");
+                summary.status = SolverStatus::RelativeErrorDecreaseTooSmall;
                 break;
             }
         }
-        let params = parameter_blocks
-            .iter()
-            .map(|(k, v)| (k.to_owned(), v.params.clone()))
-            .collect();
-        Some(params)
+        let params = problem.parameter_blocks_to_values(&parameter_blocks, initial_values);
+        summary.total_time = total_start.elapsed();
+        (Ok(params), summary)
     }
 }
@@ -0,0 +1,739 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use log::trace;
+use nalgebra as na;
+
+use crate::error::TinySolverError;
+use crate::factors::FactorImpl;
+use crate::loss_functions::Loss;
+use crate::manifold::Manifold;
+use crate::parameter_block::ParameterBlock;
+use crate::problem::{Problem, ResidualBlockId, check_residual_dimension};
+use crate::variable_key::{VariableKey, key_to_string};
+
+const DEFAULT_RELINEARIZE_THRESHOLD: f64 = 0.1;
+const DEFAULT_WILDFIRE_THRESHOLD: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct IncrementalOptions {
+    /// Variables whose step has a coordinate larger than this are
+    /// relinearized in the next `update`.
+    pub relinearize_threshold: f64,
+    /// Back substitution stops at cliques whose separator moved less than
+    /// this, their steps are then slightly outdated.
+    pub wildfire_threshold: f64,
+}
+
+impl Default for IncrementalOptions {
+    fn default() -> Self {
+        IncrementalOptions {
+            relinearize_threshold: DEFAULT_RELINEARIZE_THRESHOLD,
+            wildfire_threshold: DEFAULT_WILDFIRE_THRESHOLD,
+        }
+    }
+}
+
+/// What one `IncrementalOptimizer::update` did.
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalSummary {
+    pub num_new_residual_blocks: usize,
+    pub num_removed_residual_blocks: usize,
+    pub num_relinearized_variables: usize,
+    /// Variables in the part of the Bayes tree that was eliminated again.
+    pub num_reeliminated_variables: usize,
+    pub num_cliques: usize,
+}
+
+/// Linearized residual block `|sum_k A_k * dx_k - b|^2`, over the free
+/// tangent coordinates of the variables `keys`.
+#[derive(Debug, Clone)]
+struct LinearFactor {
+    keys: Vec<usize>,
+    blocks: Vec<na::DMatrix<f64>>,
+    b: na::DVector<f64>,
+}
+
+/// `r * dx_frontal + sum_k s_k * dx_parents[k] = d` with `r` upper triangular.
+#[derive(Debug, Clone)]
+struct Conditional {
+    frontal: usize,
+    r: na::DMatrix<f64>,
+    parents: Vec<usize>,
+    s: Vec<na::DMatrix<f64>>,
+    d: na::DVector<f64>,
+}
+
+struct Clique {
+    /// One per frontal variable, in elimination order.
+    conditionals: Vec<Conditional>,
+    separator: Vec<usize>,
+    /// Factor on the separator left by eliminating the clique and its
+    /// subtree, it stands in for the subtree when the clique is eliminated
+    /// again.
+    cached_factor: Option<LinearFactor>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// iSAM2 style incremental smoother over a `Problem`.
+///
+/// Residual blocks are added to the problem between calls to `update`. The
+/// linear system is kept as a Bayes tree, a tree of cliques of conditionals
+/// from the QR elimination of the linearized problem. `update` linearizes
+/// the new residual blocks, relinearizes the variables whose step exceeds
+/// `relinearize_threshold` and eliminates again only the cliques holding the
+/// affected variables and their ancestors, the subtrees below are kept and
+/// summarized by their cached factors. Each `update` is one Gauss-Newton
+/// step, call it without new residual blocks to keep iterating.
+///
+/// Fixed indexes, bounds and manifolds of a variable are taken from the
+/// problem when the variable gets its value, later changes are ignored.
+pub struct IncrementalOptimizer<K = String> {
+    problem: Problem<K>,
+    options: IncrementalOptions,
+    /// Linearization points indexed by the variable index.
+    parameter_blocks: Vec<Option<ParameterBlock>>,
+    /// Steps from the linearization points over the free tangent coordinates.
+    delta: Vec<na::DVector<f64>>,
+    linear_factors: HashMap<ResidualBlockId, LinearFactor>,
+    variable_factors: Vec<HashSet<ResidualBlockId>>,
+    new_residual_blocks: Vec<ResidualBlockId>,
+    removed_residual_blocks: Vec<ResidualBlockId>,
+    cliques: HashMap<usize, Clique>,
+    next_clique_id: usize,
+    roots: Vec<usize>,
+    variable_clique: Vec<Option<usize>>,
+    /// Position of the variables in the elimination order of the tree.
+    elimination_stamp: Vec<usize>,
+    next_stamp: usize,
+}
+
+impl<K: VariableKey> IncrementalOptimizer<K> {
+    /// The residual blocks already in `problem` are added in the first
+    /// `update`.
+    pub fn new(problem: Problem<K>, options: IncrementalOptions) -> Self {
+        let mut new_residual_blocks: Vec<ResidualBlockId> =
+            problem.residual_blocks().keys().copied().collect();
+        new_residual_blocks.sort();
+        IncrementalOptimizer {
+            problem,
+            options,
+            parameter_blocks: Vec::new(),
+            delta: Vec::new(),
+            linear_factors: HashMap::new(),
+            variable_factors: Vec::new(),
+            new_residual_blocks,
+            removed_residual_blocks: Vec::new(),
+            cliques: HashMap::new(),
+            next_clique_id: 0,
+            roots: Vec::new(),
+            variable_clique: Vec::new(),
+            elimination_stamp: Vec::new(),
+            next_stamp: 0,
+        }
+    }
+
+    pub fn problem(&self) -> &Problem<K> {
+        &self.problem
+    }
+
+    pub fn add_residual_block<Q: Into<K> + Clone>(
+        &mut self,
+        dim_residual: usize,
+        variable_keys: &[Q],
+        factor: Box<dyn FactorImpl + Send>,
+        loss_func: Option<Box<dyn Loss + Send>>,
+    ) -> Result<ResidualBlockId, TinySolverError> {
+        let block_id =
+            self.problem
+                .add_residual_block(dim_residual, variable_keys, factor, loss_func)?;
+        self.new_residual_blocks.push(block_id);
+        Ok(block_id)
+    }
+
+    /// Removed from the problem and the solution in the next `update`, a
+    /// block added since the last `update` is removed right away. If the
+    /// removal leaves a variable unconstrained, `update` fails until
+    /// `restore_residual_block` is called or another block constrains it.
+    /// Returns `false` if the block is unknown or already removed.
+    pub fn remove_residual_block(&mut self, block_id: ResidualBlockId) -> bool {
+        if let Some(idx) = self
+            .new_residual_blocks
+            .iter()
+            .position(|&id| id == block_id)
+        {
+            self.new_residual_blocks.remove(idx);
+            self.problem.remove_residual_block(block_id);
+            true
+        } else if self.linear_factors.contains_key(&block_id)
+            && !self.removed_residual_blocks.contains(&block_id)
+        {
+            self.removed_residual_blocks.push(block_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Keeps a block given to `remove_residual_block` since the last
+    /// `update`. Returns `false` if its removal is not pending.
+    pub fn restore_residual_block(&mut self, block_id: ResidualBlockId) -> bool {
+        let len = self.removed_residual_blocks.len();
+        self.removed_residual_blocks.retain(|&id| id != block_id);
+        self.removed_residual_blocks.len() != len
+    }
+
+    /// Only applies to variables that have no value yet.
+    pub fn set_variable_manifold<Q: Into<K>>(
+        &mut self,
+        var_key: Q,
+        manifold: Arc<dyn Manifold + Sync + Send>,
+    ) {
+        self.problem.set_variable_manifold(var_key, manifold);
+    }
+
+    /// Only applies to variables that have no value yet.
+    pub fn fix_variable<Q: Into<K>>(&mut self, var_to_fix: Q, idx: usize) {
+        self.problem.fix_variable(var_to_fix, idx);
+    }
+
+    /// Adds the residual blocks and removes the ones given since the last
+    /// update and takes one Gauss-Newton step. `new_values` are the initial
+    /// values of the new variables, values of known variables are ignored.
+    /// Nothing changes if an error is returned.
+    pub fn update(
+        &mut self,
+        new_values: &HashMap<K, na::DVector<f64>>,
+    ) -> Result<IncrementalSummary, TinySolverError> {
+        let num_variables = self.problem.variable_keys().len();
+        self.parameter_blocks.resize(num_variables, None);
+        self.delta.resize(num_variables, na::DVector::zeros(0));
+        self.variable_factors.resize(num_variables, HashSet::new());
+        self.variable_clique.resize(num_variables, None);
+        self.elimination_stamp.resize(num_variables, 0);
+
+        let mut added_variables = Vec::new();
+        for (key, value) in new_values {
+            let Some(var_idx) = self.problem.get_variable_idx(key) else {
+                continue;
+            };
+            if self.parameter_blocks[var_idx].is_some() {
+                continue;
+            }
+            let param = self.problem.parameter_block(key, value.clone());
+            if let Some(manifold) = &param.manifold
+                && manifold.ambient_size().get() != value.len()
+            {
+                for var_idx in added_variables {
+                    self.parameter_blocks[var_idx] = None;
+                }
+                return Err(TinySolverError::VariableSizeMismatch {
+                    key: key_to_string(&key),
+                    expected: manifold.ambient_size().get(),
+                    actual: value.len(),
+                });
+            }
+            self.delta[var_idx] = na::DVector::zeros(param.free_tangent_size());
+            self.parameter_blocks[var_idx] = Some(param);
+            added_variables.push(var_idx);
+        }
+
+        let mut old_points = Vec::new();
+        let result = self.update_impl(&mut old_points);
+        if result.is_err() {
+            for (var_idx, param) in old_points {
+                self.parameter_blocks[var_idx] = Some(param);
+            }
+            for var_idx in added_variables {
+                self.parameter_blocks[var_idx] = None;
+            }
+        }
+        result
+    }
+
+    fn update_impl(
+        &mut self,
+        old_points: &mut Vec<(usize, ParameterBlock)>,
+    ) -> Result<IncrementalSummary, TinySolverError> {
+        // fluid relinearization of the variables that moved too far
+        let relinearized_variables: Vec<usize> = (0..self.delta.len())
+            .filter(|&var_idx| {
+                !self.delta[var_idx].is_empty()
+                    && self.delta[var_idx].amax() > self.options.relinearize_threshold
+            })
+            .collect();
+        for &var_idx in &relinearized_variables {
+            let param = self.parameter_blocks[var_idx].as_ref().unwrap();
+            let new_point = retract(param, &self.delta[var_idx]);
+            old_points.push((var_idx, param.clone()));
+            self.parameter_blocks[var_idx].as_mut().unwrap().params = new_point;
+        }
+        let mut linearized = HashMap::new();
+        for &block_id in &self.new_residual_blocks {
+            linearized.insert(block_id, self.linearize(block_id)?);
+        }
+        let removed: HashSet<ResidualBlockId> =
+            self.removed_residual_blocks.iter().copied().collect();
+        for &var_idx in &relinearized_variables {
+            for &block_id in &self.variable_factors[var_idx] {
+                if !removed.contains(&block_id) && !linearized.contains_key(&block_id) {
+                    linearized.insert(block_id, self.linearize(block_id)?);
+                }
+            }
+        }
+
+        let mut affected: HashSet<usize> = relinearized_variables.iter().copied().collect();
+        for factor in linearized.values() {
+            affected.extend(&factor.keys);
+        }
+        for block_id in &removed {
+            affected.extend(&self.linear_factors[block_id].keys);
+        }
+
+        // the top of the tree holds the affected variables and everything
+        // above them, the subtrees below are kept as orphans
+        let mut removed_cliques = HashSet::new();
+        let mut top_variables = HashSet::new();
+        for &var_idx in &affected {
+            let mut clique_id = self.variable_clique[var_idx];
+            if clique_id.is_none() {
+                top_variables.insert(var_idx);
+            }
+            while let Some(id) = clique_id {
+                if !removed_cliques.insert(id) {
+                    break;
+                }
+                clique_id = self.cliques[&id].parent;
+            }
+        }
+        let mut orphans = Vec::new();
+        for id in &removed_cliques {
+            let clique = &self.cliques[id];
+            top_variables.extend(clique.conditionals.iter().map(|c| c.frontal));
+            orphans.extend(
+                clique
+                    .children
+                    .iter()
+                    .filter(|child| !removed_cliques.contains(child)),
+            );
+        }
+
+        // factors eliminated in the top, the others are summarized by the
+        // cached factors of the orphans
+        let mut factor_ids: HashSet<ResidualBlockId> =
+            self.new_residual_blocks.iter().copied().collect();
+        for &var_idx in &top_variables {
+            for block_id in &self.variable_factors[var_idx] {
+                if removed.contains(block_id) {
+                    continue;
+                }
+                let factor = linearized
+                    .get(block_id)
+                    .unwrap_or_else(|| &self.linear_factors[block_id]);
+                if factor.keys.iter().all(|k| top_variables.contains(k)) {
+                    factor_ids.insert(*block_id);
+                }
+            }
+        }
+        let mut factor_ids: Vec<ResidualBlockId> = factor_ids.into_iter().collect();
+        factor_ids.sort();
+        let mut factors: Vec<LinearFactor> = factor_ids
+            .iter()
+            .map(|block_id| {
+                linearized
+                    .get(block_id)
+                    .unwrap_or_else(|| &self.linear_factors[block_id])
+                    .clone()
+            })
+            .filter(|factor| !factor.keys.is_empty())
+            .collect();
+        factors.extend(
+            orphans
+                .iter()
+                .filter_map(|id| self.cliques[id].cached_factor.clone()),
+        );
+
+        let mut top_variables: Vec<usize> = top_variables.into_iter().collect();
+        top_variables.sort();
+        let last: HashSet<usize> = self
+            .new_residual_blocks
+            .iter()
+            .flat_map(|block_id| linearized[block_id].keys.iter().copied())
+            .collect();
+        let ordering = minimum_degree_ordering(&top_variables, &factors, &last);
+        let eliminated = eliminate(factors, &ordering, |var_idx| self.delta[var_idx].len())
+            .map_err(|var_idx| {
+                TinySolverError::LinearSolverFailed(format!(
+                    "variable {} is not constrained",
+                    key_to_string(&self.problem.variable_keys()[var_idx])
+                ))
+            })?;
+        trace!(
+            "relinearized {} variables, eliminating {} of {}",
+            relinearized_variables.len(),
+            ordering.len(),
+            self.delta.len()
+        );
+
+        // nothing can fail from here on
+        let summary = IncrementalSummary {
+            num_new_residual_blocks: self.new_residual_blocks.len(),
+            num_removed_residual_blocks: removed.len(),
+            num_relinearized_variables: relinearized_variables.len(),
+            num_reeliminated_variables: ordering.len(),
+            num_cliques: 0,
+        };
+        for block_id in self.removed_residual_blocks.drain(..) {
+            self.problem.remove_residual_block(block_id);
+            let factor = self.linear_factors.remove(&block_id).unwrap();
+            for var_idx in factor.keys {
+                self.variable_factors[var_idx].remove(&block_id);
+            }
+        }
+        for block_id in self.new_residual_blocks.drain(..) {
+            for &var_idx in &linearized[&block_id].keys {
+                self.variable_factors[var_idx].insert(block_id);
+            }
+        }
+        self.linear_factors.extend(linearized);
+        for &var_idx in &relinearized_variables {
+            self.delta[var_idx].fill(0.0);
+        }
+
+        for id in &removed_cliques {
+            self.cliques.remove(id);
+        }
+        self.roots.retain(|id| !removed_cliques.contains(id));
+        for &var_idx in &ordering {
+            self.elimination_stamp[var_idx] = self.next_stamp;
+            self.next_stamp += 1;
+        }
+        let new_cliques = self.build_cliques(eliminated);
+        for orphan in orphans {
+            let parent = self.parent_clique(&self.cliques[&orphan].separator);
+            self.cliques.get_mut(&orphan).unwrap().parent = Some(parent);
+            self.cliques.get_mut(&parent).unwrap().children.push(orphan);
+        }
+
+        self.back_substitute(&new_cliques);
+        Ok(IncrementalSummary {
+            num_cliques: self.cliques.len(),
+            ..summary
+        })
+    }
+
+    /// Current estimate of the variables with a value, the linearization
+    /// points moved by the last step.
+    pub fn estimate(&self) -> HashMap<K, na::DVector<f64>> {
+        self.problem
+            .variable_keys()
+            .iter()
+            .zip(&self.parameter_blocks)
+            .zip(&self.delta)
+            .filter_map(|((key, param), delta)| {
+                Some((key.clone(), retract(param.as_ref()?, delta)))
+            })
+            .collect()
+    }
+
+    fn linearize(&self, block_id: ResidualBlockId) -> Result<LinearFactor, TinySolverError> {
+        let residual_block = &self.problem.residual_blocks()[&block_id];
+        let params = self
+            .problem
+            .residual_block_params(residual_block, &self.parameter_blocks)?;
+        let (residual, jacobian) = residual_block.residual_and_jacobian(&params);
+        check_residual_dimension(residual_block, residual.nrows())?;
+
+        let mut keys = Vec::new();
+        let mut blocks = Vec::new();
+        let mut col = 0;
+        for (param, &var_idx) in params.iter().zip(&residual_block.variable_idx_list) {
+            let free_cols: Vec<usize> = (0..param.tangent_size())
+                .filter(|i| !param.fixed_variables.contains(i))
+                .collect();
+            if !free_cols.is_empty() {
+                keys.push(var_idx);
+                blocks.push(na::DMatrix::from_fn(
+                    residual.nrows(),
+                    free_cols.len(),
+                    |r, c| jacobian[(r, col + free_cols[c])],
+                ));
+            }
+            col += param.tangent_size();
+        }
+        Ok(LinearFactor {
+            keys,
+            blocks,
+            b: -residual,
+        })
+    }
+
+    /// Clique of the separator variable eliminated first.
+    fn parent_clique(&self, separator: &[usize]) -> usize {
+        let first = separator
+            .iter()
+            .min_by_key(|&&var_idx| self.elimination_stamp[var_idx])
+            .unwrap();
+        self.variable_clique[*first].unwrap()
+    }
+
+    /// Groups the conditionals into cliques, in reverse elimination order a
+    /// conditional joins the clique of its parent if it depends on all of it.
+    fn build_cliques(
+        &mut self,
+        eliminated: Vec<(Conditional, Option<LinearFactor>)>,
+    ) -> HashSet<usize> {
+        let mut new_cliques = HashSet::new();
+        for (conditional, marginal) in eliminated.into_iter().rev() {
+            let frontal = conditional.frontal;
+            if conditional.parents.is_empty() {
+                let id = self.insert_clique(conditional, Vec::new(), None, None);
+                self.roots.push(id);
+                new_cliques.insert(id);
+                continue;
+            }
+            let parent = self.parent_clique(&conditional.parents);
+            let parent_clique = self.cliques.get_mut(&parent).unwrap();
+            if parent_clique.conditionals.len() + parent_clique.separator.len()
+                == conditional.parents.len()
+            {
+                parent_clique.conditionals.insert(0, conditional);
+                self.variable_clique[frontal] = Some(parent);
+            } else {
+                let separator = conditional.parents.clone();
+                let id = self.insert_clique(conditional, separator, marginal, Some(parent));
+                self.cliques.get_mut(&parent).unwrap().children.push(id);
+                new_cliques.insert(id);
+            }
+        }
+        new_cliques
+    }
+
+    fn insert_clique(
+        &mut self,
+        conditional: Conditional,
+        separator: Vec<usize>,
+        cached_factor: Option<LinearFactor>,
+        parent: Option<usize>,
+    ) -> usize {
+        let id = self.next_clique_id;
+        self.next_clique_id += 1;
+        self.variable_clique[conditional.frontal] = Some(id);
+        self.cliques.insert(
+            id,
+            Clique {
+                conditionals: vec![conditional],
+                separator,
+                cached_factor,
+                parent,
+                children: Vec::new(),
+            },
+        );
+        id
+    }
+
+    /// Solves the cliques from the roots down. The new cliques are always
+    /// solved, the others only if a separator variable changed by more than
+    /// `wildfire_threshold`.
+    fn back_substitute(&mut self, new_cliques: &HashSet<usize>) {
+        let mut changed = HashSet::new();
+        let mut stack = self.roots.clone();
+        while let Some(id) = stack.pop() {
+            let clique = &self.cliques[&id];
+            if !new_cliques.contains(&id) && !clique.separator.iter().any(|v| changed.contains(v)) {
+                continue;
+            }
+            for conditional in clique.conditionals.iter().rev() {
+                let mut rhs = conditional.d.clone();
+                for (parent, s) in conditional.parents.iter().zip(&conditional.s) {
+                    rhs -= s * &self.delta[*parent];
+                }
+                // the diagonal is checked in `eliminate`
+                let dx = conditional.r.solve_upper_triangular(&rhs).unwrap();
+                let frontal = conditional.frontal;
+                if (&dx - &self.delta[frontal]).amax() > self.options.wildfire_threshold {
+                    changed.insert(frontal);
+                }
+                self.delta[frontal] = dx;
+            }
+            stack.extend(&clique.children);
+        }
+    }
+}
+
+/// Linearization point moved by the step over the free tangent coordinates.
+fn retract(param: &ParameterBlock, delta: &na::DVector<f64>) -> na::DVector<f64> {
+    let mut dx_full = na::DVector::zeros(param.tangent_size());
+    let mut reduced_idx = 0;
+    for i in 0..param.tangent_size() {
+        if !param.fixed_variables.contains(&i) {
+            dx_full[i] = delta[reduced_idx];
+            reduced_idx += 1;
+        }
+    }
+    let mut param = param.clone();
+    param.update_params(param.plus_f64(dx_full.as_view()));
+    param.params
+}
+
+/// Greedy minimum degree ordering of `variables`, the ones in `last` are
+/// eliminated after all the others so that they end up near the root.
+fn minimum_degree_ordering(
+    variables: &[usize],
+    factors: &[LinearFactor],
+    last: &HashSet<usize>,
+) -> Vec<usize> {
+    let mut neighbors: HashMap<usize, HashSet<usize>> =
+        variables.iter().map(|&v| (v, HashSet::new())).collect();
+    for factor in factors {
+        for &a in &factor.keys {
+            for &b in &factor.keys {
+                if a != b {
+                    neighbors.get_mut(&a).unwrap().insert(b);
+                }
+            }
+        }
+    }
+    let mut ordering = Vec::with_capacity(variables.len());
+    for constrained in [false, true] {
+        let mut group: Vec<usize> = variables
+            .iter()
+            .copied()
+            .filter(|v| last.contains(v) == constrained)
+            .collect();
+        while !group.is_empty() {
+            // ties are broken by the variable index
+            let (pos, _) = group
+                .iter()
+                .enumerate()
+                .min_by_key(|&(_, v)| (neighbors[v].len(), *v))
+                .unwrap();
+            let var_idx = group.swap_remove(pos);
+            let var_neighbors = neighbors.remove(&var_idx).unwrap();
+            for &a in &var_neighbors {
+                let a_neighbors = neighbors.get_mut(&a).unwrap();
+                a_neighbors.remove(&var_idx);
+                a_neighbors.extend(var_neighbors.iter().filter(|&&b| b != a));
+            }
+            ordering.push(var_idx);
+        }
+    }
+    ordering
+}
+
+/// Eliminates the variables in `ordering` one by one with a dense QR of the
+/// factors involving them. Returns the conditional of every variable and the
+/// factor left on its separator, or the variable that is not constrained.
+fn eliminate(
+    factors: Vec<LinearFactor>,
+    ordering: &[usize],
+    dim: impl Fn(usize) -> usize,
+) -> Result<Vec<(Conditional, Option<LinearFactor>)>, usize> {
+    let position: HashMap<usize, usize> = ordering
+        .iter()
+        .enumerate()
+        .map(|(pos, &var_idx)| (var_idx, pos))
+        .collect();
+    let mut pool: Vec<Option<LinearFactor>> = Vec::new();
+    let mut variable_factors: HashMap<usize, Vec<usize>> = HashMap::new();
+    fn add_factor(
+        pool: &mut Vec<Option<LinearFactor>>,
+        variable_factors: &mut HashMap<usize, Vec<usize>>,
+        factor: LinearFactor,
+    ) {
+        for &var_idx in &factor.keys {
+            variable_factors
+                .entry(var_idx)
+                .or_default()
+                .push(pool.len());
+        }
+        pool.push(Some(factor));
+    }
+    for factor in factors {
+        add_factor(&mut pool, &mut variable_factors, factor);
+    }
+
+    let mut eliminated = Vec::with_capacity(ordering.len());
+    for &var_idx in ordering {
+        let involved: Vec<LinearFactor> = variable_factors
+            .remove(&var_idx)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|i| pool[i].take())
+            .collect();
+        let mut separator: Vec<usize> = involved
+            .iter()
+            .flat_map(|f| f.keys.iter().copied())
+            .filter(|&k| k != var_idx)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        separator.sort_by_key(|k| position[k]);
+
+        let frontal_dim = dim(var_idx);
+        let mut col_offsets = HashMap::from([(var_idx, 0)]);
+        let mut num_cols = frontal_dim;
+        for &k in &separator {
+            col_offsets.insert(k, num_cols);
+            num_cols += dim(k);
+        }
+        let num_rows: usize = involved.iter().map(|f| f.b.len()).sum();
+        let mut stacked = na::DMatrix::zeros(num_rows.max(num_cols + 1), num_cols + 1);
+        let mut row = 0;
+        for factor in &involved {
+            let rows = factor.b.len();
+            for (k, block) in factor.keys.iter().zip(&factor.blocks) {
+                stacked
+                    .view_mut((row, col_offsets[k]), (rows, block.ncols()))
+                    .copy_from(block);
+            }
+            stacked
+                .view_mut((row, num_cols), (rows, 1))
+                .copy_from(&factor.b);
+            row += rows;
+        }
+        let r = stacked.qr().r();
+
+        let r_frontal = r.view((0, 0), (frontal_dim, frontal_dim)).into_owned();
+        let scale = r_frontal.amax();
+        if frontal_dim == 0
+            || r_frontal
+                .diagonal()
+                .iter()
+                .any(|v| v.abs() <= 1e-12 * scale.max(1.0))
+        {
+            return Err(var_idx);
+        }
+        let blocks_at = |rows: std::ops::Range<usize>| -> Vec<na::DMatrix<f64>> {
+            separator
+                .iter()
+                .map(|k| {
+                    r.view((rows.start, col_offsets[k]), (rows.len(), dim(*k)))
+                        .into_owned()
+                })
+                .collect()
+        };
+        let conditional = Conditional {
+            frontal: var_idx,
+            r: r_frontal,
+            parents: separator.clone(),
+            s: blocks_at(0..frontal_dim),
+            d: r.view((0, num_cols), (frontal_dim, 1))
+                .column(0)
+                .into_owned(),
+        };
+        let marginal = (!separator.is_empty()).then(|| LinearFactor {
+            keys: separator.clone(),
+            blocks: blocks_at(frontal_dim..num_cols),
+            b: r.view((frontal_dim, num_cols), (num_cols - frontal_dim, 1))
+                .column(0)
+                .into_owned(),
+        });
+        if let Some(marginal) = &marginal {
+            add_factor(&mut pool, &mut variable_factors, marginal.clone());
+        }
+        eliminated.push((conditional, marginal));
+    }
+    Ok(eliminated)
+}
@@ -5,12 +5,15 @@ use std::{collections::HashMap, time::Instant};
 use faer::sparse::Triplet;
 use faer_ext::IntoNalgebra;
 
-use crate::common::OptimizerOptions;
-use crate::linear;
+use crate::common::{
+    ForcingSequence, IterationSummary, OptimizerOptions, SolverStatus, SolverSummary,
+};
+use crate::error::TinySolverError;
 use crate::optimizer;
 use crate::parameter_block::ParameterBlock;
-use crate::sparse::LinearSolverType;
-use crate::sparse::SparseLinearSolver;
+use crate::solver_context::SolverContext;
+use crate::sparse::normal_matrix;
+use crate::variable_key::VariableKey;
 
 const DEFAULT_MIN_DIAGONAL: f64 = 1e-6;
 const DEFAULT_MAX_DIAGONAL: f64 = 1e32;
@@ -44,58 +47,78 @@ impl Default for LevenbergMarquardtOptimizer {
 }
 
 impl optimizer::Optimizer for LevenbergMarquardtOptimizer {
-    fn optimize(
+    fn optimize_with_context<K: VariableKey>(
         &self,
-        problem: &crate::problem::Problem,
-        initial_values: &std::collections::HashMap<String, nalgebra::DVector<f64>>,
-        optimizer_option: Option<OptimizerOptions>,
-    ) -> Option<HashMap<String, nalgebra::DVector<f64>>> {
-        let mut parameter_blocks: HashMap<String, ParameterBlock> =
-            problem.initialize_parameter_blocks(initial_values);
+        problem: &crate::problem::Problem<K>,
+        initial_values: &HashMap<K, nalgebra::DVector<f64>>,
+        optimizer_option: Option<OptimizerOptions<K>>,
+        context: &mut SolverContext<K>,
+    ) -> (
+        Result<HashMap<K, nalgebra::DVector<f64>>, TinySolverError>,
+        SolverSummary,
+    ) {
+        let total_start = Instant::now();
+        let mut summary = SolverSummary::default();
+        if let Err(err) = problem.validate(initial_values) {
+            return summary.failed(SolverStatus::InvalidProblem, err, total_start);
+        }
 
-        let variable_name_to_col_idx_dict =
-            problem.get_variable_name_to_col_idx_dict(&parameter_blocks);
-        let total_variable_dimension = parameter_blocks
-            .values()
-            .map(|p| {
-                if p.manifold.is_some() {
-                    p.tangent_size()
-                } else {
-                    p.tangent_size() - p.fixed_variables.len()
-                }
-            })
-            .sum();
+        let mut parameter_blocks: Vec<Option<ParameterBlock>> =
+            problem.initialize_parameter_blocks(initial_values);
 
         let opt_option = optimizer_option.unwrap_or_default();
-        let mut linear_solver: Box<dyn SparseLinearSolver> = match opt_option.linear_solver_type {
-            LinearSolverType::SparseCholesky => Box::new(linear::SparseCholeskySolver::new()),
-            LinearSolverType::SparseQR => Box::new(linear::SparseQRSolver::new()),
-        };
+        let (structure, linear_solver) = context.prepare(problem, &parameter_blocks, &opt_option);
+        let variable_idx_to_col_idx = &structure.variable_idx_to_col_idx;
+        let total_variable_dimension = structure.total_variable_dimension;
+        let symbolic_structure = &structure.symbolic_structure;
 
         // On the first iteration, we'll generate a diagonal matrix of the jacobian.
         // Its shape will be (total_variable_dimension, total_variable_dimension).
         // With LM, rather than solving A * dx = b for dx, we solve for (A + lambda * diag(A)) dx = b.
         let mut jacobi_scaling_diagonal: Option<faer::sparse::SparseColMat<usize, f64>> = None;
 
-        let symbolic_structure = problem.build_symbolic_structure(
-            &parameter_blocks,
-            total_variable_dimension,
-            &variable_name_to_col_idx_dict,
-        );
-
         // Damping parameter (a.k.a lambda / Marquardt parameter)
         let mut u = 1.0 / self.initial_trust_region_radius;
 
         let mut last_err;
-        let mut current_error = self.compute_error(problem, &parameter_blocks);
+        let mut start = Instant::now();
+        let mut current_error = match self.compute_error(problem, &parameter_blocks) {
+            Ok(error) => error,
+            Err(err) => {
+                return summary.failed(
+                    SolverStatus::FailedToEvaluateCostFunction,
+                    err,
+                    total_start,
+                );
+            }
+        };
+        summary.residual_evaluation_time += start.elapsed();
+        summary.initial_cost = current_error;
+        summary.final_cost = current_error;
+        summary.status = SolverStatus::HitMaxIterations;
+
+        let mut forcing_sequence = ForcingSequence::new(opt_option.eta);
         for i in 0..opt_option.max_iteration {
+            let iteration_start = Instant::now();
             last_err = current_error;
 
-            let (residuals, mut jac) = problem.compute_residual_and_jacobian(
+            start = Instant::now();
+            let (residuals, mut jac) = match problem.compute_residual_and_jacobian(
                 &parameter_blocks,
-                &variable_name_to_col_idx_dict,
-                &symbolic_structure,
-            );
+                variable_idx_to_col_idx,
+                symbolic_structure,
+            ) {
+                Ok(linearization) => linearization,
+                Err(err) => {
+                    return summary.failed(
+                        SolverStatus::FailedToEvaluateCostFunction,
+                        err,
+                        total_start,
+                    );
+                }
+            };
+            let jacobian_evaluation_time = start.elapsed();
+            let gradient_max_norm = (jac.as_ref().transpose() * &residuals).norm_max();
 
             if i == 0 {
                 // On the first iteration, generate the diagonal of the jacobian.
@@ -121,12 +144,7 @@ impl optimizer::Optimizer for LevenbergMarquardtOptimizer {
             jac = jac * jacobi_scaling_diagonal.as_ref().unwrap();
 
             // J^T * J = Matrix of shape (total_variable_dimension, total_variable_dimension)
-            let jtj = jac
-                .as_ref()
-                .transpose()
-                .to_col_major()
-                .unwrap()
-                .mul(jac.as_ref());
+            let jtj = normal_matrix(&jac);
 
             // J^T * -r = Matrix of shape (total_variable_dimension, 1)
             let jtr = jac.as_ref().transpose().mul(-&residuals);
@@ -138,77 +156,111 @@ impl optimizer::Optimizer for LevenbergMarquardtOptimizer {
                     u * (jtj[(i, i)].max(self.min_diagonal)).min(self.max_diagonal);
             }
 
-            let start = Instant::now();
-            if let Some(lm_step) = linear_solver.solve_jtj(&jtr, &jtj_regularized) {
-                let duration = start.elapsed();
-                let dx = jacobi_scaling_diagonal.as_ref().unwrap() * &lm_step;
+            forcing_sequence.update(linear_solver, gradient_max_norm);
+
+            let damping = u;
+            let mut residual_evaluation_time = std::time::Duration::ZERO;
+            start = Instant::now();
+            let lm_step = match linear_solver.solve_jtj(&jtr, &jtj_regularized) {
+                Ok(lm_step) => lm_step,
+                Err(err) => {
+                    return summary.failed(
+                        SolverStatus::FailedToSolveLinearSystem,
+                        err,
+                        total_start,
+                    );
+                }
+            };
+            let linear_solver_time = start.elapsed();
+            let dx = jacobi_scaling_diagonal.as_ref().unwrap() * &lm_step;
 
-                trace!("Time elapsed in solve Ax=b is: {:?}", duration);
+            trace!("Time elapsed in solve Ax=b is: {:?}", linear_solver_time);
 
-                let dx_na = dx.as_ref().into_nalgebra().column(0).clone_owned();
+            let dx_na = dx.as_ref().into_nalgebra().column(0).clone_owned();
+            let step_norm = dx_na.norm();
 
-                let mut new_param_blocks = parameter_blocks.clone();
+            let mut new_param_blocks = parameter_blocks.clone();
 
-                self.apply_dx2(
-                    &dx_na,
-                    &mut new_param_blocks,
-                    &variable_name_to_col_idx_dict,
-                );
+            self.apply_dx2(&dx_na, &mut new_param_blocks, variable_idx_to_col_idx);
 
-                // Compute residuals of (x + dx)
-                let new_residuals = problem.compute_residuals(&new_param_blocks, true);
-
-                // rho is the ratio between the actual reduction in error and the reduction
-                // in error if the problem were linear.
-                let actual_residual_change =
-                    residuals.as_ref().squared_norm_l2() - new_residuals.as_ref().squared_norm_l2();
-                trace!("actual_residual_change {}", actual_residual_change);
-                let linear_residual_change: faer::Mat<f64> =
-                    lm_step.transpose().mul(2.0 * &jtr - &jtj * &lm_step);
-                let rho = actual_residual_change / linear_residual_change[(0, 0)];
-
-                if rho > 0.0 {
-                    // The linear model appears to be fitting, so accept (x + dx) as the new x.
-                    parameter_blocks = new_param_blocks;
-
-                    // Increase the trust region by reducing u
-                    let tmp = 2.0 * rho - 1.0;
-                    u *= (1.0_f64 / 3.0).max(1.0 - tmp * tmp * tmp);
-                } else {
-                    // If there's too much divergence, reduce the trust region and try again with the same parameters.
-                    u *= 2.0;
-                    trace!("u {}", u);
+            // Compute the cost of (x + dx)
+            start = Instant::now();
+            let new_error = match self.compute_error(problem, &new_param_blocks) {
+                Ok(error) => error,
+                Err(err) => {
+                    return summary.failed(
+                        SolverStatus::FailedToEvaluateCostFunction,
+                        err,
+                        total_start,
+                    );
                 }
+            };
+            residual_evaluation_time += start.elapsed();
+
+            // rho is the ratio between the actual reduction in the robust cost and the
+            // reduction of the cost of the linearized, loss-corrected residuals.
+            let actual_cost_change = current_error - new_error;
+            trace!("actual_cost_change {}", actual_cost_change);
+            let linear_cost_change: faer::Mat<f64> =
+                0.5 * lm_step.transpose().mul(2.0 * &jtr - &jtj * &lm_step);
+            let rho = actual_cost_change / linear_cost_change[(0, 0)];
+
+            if rho > 0.0 {
+                // The linear model appears to be fitting, so accept (x + dx) as the new x.
+                parameter_blocks = new_param_blocks;
+                current_error = new_error;
+
+                // Increase the trust region by reducing u
+                let tmp = 2.0 * rho - 1.0;
+                u *= (1.0_f64 / 3.0).max(1.0 - tmp * tmp * tmp);
             } else {
-                log::debug!("solve ax=b failed");
-                return None;
+                // If there's too much divergence, reduce the trust region and try again with the same parameters.
+                u *= 2.0;
+                trace!("u {}", u);
             }
 
-            current_error = self.compute_error(problem, &parameter_blocks);
             trace!("iter:{} total err:{}", i, current_error);
+            summary.add_iteration(IterationSummary {
+                iteration: i,
+                cost: current_error,
+                cost_change: last_err - current_error,
+                gradient_max_norm,
+                step_norm,
+                damping,
+                trust_region_ratio: rho,
+                step_is_successful: rho > 0.0,
+                residual_evaluation_time,
+                jacobian_evaluation_time,
+                linear_solver_time,
+                iteration_time: iteration_start.elapsed(),
+            });
 
             if current_error < opt_option.min_error_threshold {
                 trace!("error too low");
+                summary.status = SolverStatus::ErrorTooSmall;
                 break;
-            } else if current_error.is_nan() {
-                log::debug!("solve ax=b failed, current error is nan");
-                return None;
+            } else if !current_error.is_finite() {
+                return summary.failed(
+                    SolverStatus::FailedToEvaluateCostFunction,
+                    TinySolverError::NonFiniteCost,
+                    total_start,
+                );
             }
 
             if (last_err - current_error).abs() < opt_option.min_abs_error_decrease_threshold {
                 trace!("absolute error decrease low");
+                summary.status = SolverStatus::AbsoluteErrorDecreaseTooSmall;
                 break;
             } else if (last_err - current_error).abs() / last_err
                 < opt_option.min_rel_error_decrease_threshold
             {
                 trace!("relative error decrease low");
+                summary.status = SolverStatus::RelativeErrorDecreaseTooSmall;
                 break;
             }
         }
-        let params = parameter_blocks
-            .iter()
-            .map(|(k, v)| (k.to_owned(), v.params.clone()))
-            .collect();
-        Some(params)
+        let params = problem.parameter_blocks_to_values(&parameter_blocks, initial_values);
+        summary.total_time = total_start.elapsed();
+        (Ok(params), summary)
     }
 }
@@ -1,7 +1,13 @@
 pub mod common;
+pub mod dogleg_optimizer;
 pub mod gauss_newton_optimizer;
+pub mod incremental_optimizer;
 pub mod levenberg_marquardt_optimizer;
+pub mod solver_context;
 
 pub use common::*;
+pub use dogleg_optimizer::*;
 pub use gauss_newton_optimizer::*;
+pub use incremental_optimizer::*;
 pub use levenberg_marquardt_optimizer::*;
+pub use solver_context::*;
@@ -0,0 +1,149 @@
+use crate::common::{OptimizerOptions, create_linear_solver};
+use crate::parameter_block::ParameterBlock;
+use crate::problem::{Problem, SymbolicStructure};
+use crate::sparse::{LinearSolverType, SparseLinearSolver};
+use crate::variable_key::VariableKey;
+
+/// Tangent size and sorted fixed tangent indexes of a variable, which
+/// decide its columns in the Jacobian. `None` for variables without a value.
+type ColumnLayout = Option<(usize, Vec<usize>)>;
+
+/// Solver state kept between solves, for re-optimizing the same graph as new
+/// measurements change its values but not its structure, e.g.
+/// `optimizer.optimize_with_context(&problem, &values, None, &mut context)`
+/// in a loop.
+///
+/// It caches the column of every variable, the sparsity pattern of the
+/// Jacobian and the linear solver with its symbolic factorization and
+/// fill-reducing ordering, so later solves only rerun the numeric
+/// factorization. The cache is rebuilt when residual blocks are added or
+/// removed, when the fixed coordinates or tangent sizes of the variables
+/// change, or when the linear solver options change.
+pub struct SolverContext<K = String> {
+    structure: Option<CachedStructure>,
+    linear_solver: Option<CachedLinearSolver<K>>,
+    num_symbolic_analyses: usize,
+}
+
+/// Columns and Jacobian pattern of a problem.
+pub(crate) struct CachedStructure {
+    structure_id: usize,
+    column_layouts: Vec<ColumnLayout>,
+    pub(crate) variable_idx_to_col_idx: Vec<usize>,
+    pub(crate) total_variable_dimension: usize,
+    pub(crate) symbolic_structure: SymbolicStructure,
+}
+
+struct CachedLinearSolver<K> {
+    linear_solver_type: LinearSolverType,
+    elimination_ordering: Option<Vec<K>>,
+    max_linear_solver_iteration: usize,
+    linear_solver: Box<dyn SparseLinearSolver + Send>,
+}
+
+impl<K> Default for SolverContext<K> {
+    fn default() -> Self {
+        SolverContext {
+            structure: None,
+            linear_solver: None,
+            num_symbolic_analyses: 0,
+        }
+    }
+}
+
+impl<K: VariableKey> SolverContext<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Number of times the structure of a problem was analyzed, one per
+    /// solve that could not reuse the cached state.
+    pub fn num_symbolic_analyses(&self) -> usize {
+        self.num_symbolic_analyses
+    }
+    /// Drops the cached state, the next solve analyzes the problem again.
+    pub fn invalidate(&mut self) {
+        self.structure = None;
+        self.linear_solver = None;
+    }
+
+    /// Cached columns and Jacobian pattern for `parameter_blocks`, rebuilt
+    /// if they are stale. The linear solver is dropped with them.
+    pub(crate) fn structure(
+        &mut self,
+        problem: &Problem<K>,
+        parameter_blocks: &[Option<ParameterBlock>],
+    ) -> &CachedStructure {
+        let column_layouts: Vec<ColumnLayout> = parameter_blocks
+            .iter()
+            .map(|param| {
+                let param = param.as_ref()?;
+                let mut fixed: Vec<usize> = param.fixed_variables.iter().copied().collect();
+                fixed.sort();
+                Some((param.tangent_size(), fixed))
+            })
+            .collect();
+        let is_valid = self.structure.as_ref().is_some_and(|cached| {
+            cached.structure_id == problem.structure_id() && cached.column_layouts == column_layouts
+        });
+        if !is_valid {
+            log::debug!("analyze the structure of the problem");
+            let variable_idx_to_col_idx = problem.get_variable_idx_to_col_idx(parameter_blocks);
+            let total_variable_dimension = parameter_blocks
+                .iter()
+                .flatten()
+                .map(|p| p.free_tangent_size())
+                .sum();
+            let symbolic_structure = problem.build_symbolic_structure(
+                parameter_blocks,
+                total_variable_dimension,
+                &variable_idx_to_col_idx,
+            );
+            self.num_symbolic_analyses += 1;
+            self.linear_solver = None;
+            self.structure = Some(CachedStructure {
+                structure_id: problem.structure_id(),
+                column_layouts,
+                variable_idx_to_col_idx,
+                total_variable_dimension,
+                symbolic_structure,
+            });
+        }
+        self.structure.as_ref().unwrap()
+    }
+
+    /// Cached structure and linear solver for `parameter_blocks`, rebuilt if
+    /// they are stale.
+    pub(crate) fn prepare(
+        &mut self,
+        problem: &Problem<K>,
+        parameter_blocks: &[Option<ParameterBlock>],
+        opt_option: &OptimizerOptions<K>,
+    ) -> (&CachedStructure, &mut (dyn SparseLinearSolver + Send)) {
+        let num_symbolic_analyses = self.num_symbolic_analyses;
+        self.structure(problem, parameter_blocks);
+        let is_valid = self.linear_solver.as_ref().is_some_and(|cached| {
+            cached.linear_solver_type == opt_option.linear_solver_type
+                && cached.elimination_ordering == opt_option.elimination_ordering
+                && cached.max_linear_solver_iteration == opt_option.max_linear_solver_iteration
+        });
+        let structure = self.structure.as_ref().unwrap();
+        if !is_valid {
+            if self.num_symbolic_analyses == num_symbolic_analyses {
+                self.num_symbolic_analyses += 1;
+            }
+            self.linear_solver = Some(CachedLinearSolver {
+                linear_solver_type: opt_option.linear_solver_type.clone(),
+                elimination_ordering: opt_option.elimination_ordering.clone(),
+                max_linear_solver_iteration: opt_option.max_linear_solver_iteration,
+                linear_solver: create_linear_solver(
+                    problem,
+                    parameter_blocks,
+                    &structure.variable_idx_to_col_idx,
+                    opt_option,
+                ),
+            });
+        }
+        let linear_solver = self.linear_solver.as_mut().unwrap();
+        (structure, linear_solver.linear_solver.as_mut())
+    }
+}
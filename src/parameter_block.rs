@@ -4,13 +4,15 @@ use std::{
 };
 
 use nalgebra as na;
-use num_dual::DualDVec64;
+use num_dual::{Derivative, DualDVec64, DualSVec64};
 
-use crate::manifold::Manifold;
+use crate::manifold::{AutoDiffManifold, Manifold, StaticDualManifold};
 
 #[derive(Clone)]
 pub struct ParameterBlock {
     pub params: na::DVector<f64>,
+    /// Fixed tangent indexes, which are also the parameter indexes when
+    /// there is no manifold.
     pub fixed_variables: HashSet<usize>,
     pub variable_bounds: HashMap<usize, (f64, f64)>,
     pub manifold: Option<Arc<dyn Manifold + Sync + Send>>,
@@ -38,6 +40,17 @@ impl ParameterBlock {
             self.ambient_size()
         }
     }
+    /// Number of columns of the variable in the Jacobian. Fixed indexes out
+    /// of the tangent space are ignored, `Problem::validate` reports them.
+    pub fn free_tangent_size(&self) -> usize {
+        let tangent_size = self.tangent_size();
+        tangent_size
+            - self
+                .fixed_variables
+                .iter()
+                .filter(|&&idx| idx < tangent_size)
+                .count()
+    }
     pub fn plus_f64(&self, dx: na::DVectorView<f64>) -> na::DVector<f64> {
         let mut new_param = na::DVector::zeros(self.ambient_size());
         if let Some(m) = &self.manifold {
@@ -56,6 +69,75 @@ impl ParameterBlock {
         }
         new_param
     }
+    /// `None` if the manifold does not support statically sized dual
+    /// numbers, see `Manifold::as_static_dual`.
+    pub fn plus_static<const N: usize>(
+        &self,
+        dx: na::DVectorView<DualSVec64<N>>,
+    ) -> Option<na::DVector<DualSVec64<N>>>
+    where
+        dyn StaticDualManifold: AutoDiffManifold<DualSVec64<N>>,
+    {
+        let params = self.params.map(DualSVec64::<N>::from_re);
+        match &self.manifold {
+            Some(m) => Some(m.as_static_dual()?.plus(params.as_view(), dx)),
+            None => Some(params + dx),
+        }
+    }
+    /// `d(x boxplus delta) / d(delta)` at zero, of size ambient x tangent.
+    /// Evaluated with statically sized dual numbers for small tangent spaces
+    /// if the manifold supports them.
+    pub fn plus_jacobian(&self) -> na::DMatrix<f64> {
+        let tangent_size = self.tangent_size();
+        if self.manifold.is_none() {
+            return na::DMatrix::identity(tangent_size, tangent_size);
+        }
+        let jacobian = match tangent_size {
+            0..=4 => self.plus_jacobian_static::<4>(),
+            5..=8 => self.plus_jacobian_static::<8>(),
+            9..=12 => self.plus_jacobian_static::<12>(),
+            13..=16 => self.plus_jacobian_static::<16>(),
+            _ => None,
+        };
+        jacobian.unwrap_or_else(|| {
+            let delta = na::DVector::from_fn(tangent_size, |j, _| {
+                DualDVec64::new(
+                    0.0,
+                    Derivative::some(na::DVector::from_fn(tangent_size, |i, _| {
+                        if i == j { 1.0 } else { 0.0 }
+                    })),
+                )
+            });
+            let plus = self.plus_dual(delta.as_view());
+            let mut jacobian = na::DMatrix::zeros(self.ambient_size(), tangent_size);
+            for (r, x) in plus.iter().enumerate() {
+                let eps = x
+                    .eps
+                    .clone()
+                    .unwrap_generic(na::Dyn(tangent_size), na::Const::<1>);
+                jacobian.row_mut(r).copy_from(&eps.transpose());
+            }
+            jacobian
+        })
+    }
+    fn plus_jacobian_static<const N: usize>(&self) -> Option<na::DMatrix<f64>>
+    where
+        dyn StaticDualManifold: AutoDiffManifold<DualSVec64<N>>,
+    {
+        let tangent_size = self.tangent_size();
+        let delta = na::DVector::from_fn(tangent_size, |j, _| {
+            DualSVec64::<N>::from_re(0.0).derivative(j)
+        });
+        let plus = self.plus_static(delta.as_view())?;
+        let mut jacobian = na::DMatrix::zeros(self.ambient_size(), tangent_size);
+        for (r, x) in plus.iter().enumerate() {
+            let eps = x.eps.unwrap_generic(na::Const::<N>, na::Const::<1>);
+            jacobian
+                .row_mut(r)
+                .copy_from(&eps.rows(0, tangent_size).transpose());
+        }
+        Some(jacobian)
+    }
     pub fn y_minus_f64(&self, y: na::DVectorView<f64>) -> na::DVector<f64> {
         let mut delta_x = na::DVector::zeros(self.tangent_size());
         if let Some(m) = &self.manifold {
@@ -80,9 +162,13 @@ impl ParameterBlock {
             new_param[idx] = new_param[idx].max(lower).min(upper);
         }
 
-        // fix
-        for &index_to_fix in &self.fixed_variables {
-            new_param[index_to_fix] = self.params[index_to_fix];
+        // fix, on a manifold the step is already zero at the fixed indexes
+        if self.manifold.is_none() {
+            for &index_to_fix in &self.fixed_variables {
+                if index_to_fix < new_param.len() {
+                    new_param[index_to_fix] = self.params[index_to_fix];
+                }
+            }
         }
         self.params = new_param;
     }
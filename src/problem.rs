@@ -1,28 +1,56 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 use faer::sparse::{Argsort, Pair, SparseColMat, SymbolicSparseColMat};
-use faer_ext::IntoFaer;
+use faer_ext::{IntoFaer, IntoNalgebra};
 use nalgebra as na;
 use rayon::prelude::*;
 
+use crate::error::TinySolverError;
 use crate::manifold::Manifold;
 use crate::parameter_block::ParameterBlock;
-use crate::{factors, loss_functions, residual_block};
+use crate::variable_key::{VariableKey, key_to_string};
+use crate::{factors, loss_functions, marginalization, residual_block};
 
-type ResidualBlockId = usize;
+pub type ResidualBlockId = usize;
 
-pub struct Problem {
+/// Source of `Problem::structure_id`, unique across all problems.
+static NEXT_STRUCTURE_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_structure_id() -> usize {
+    NEXT_STRUCTURE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Least squares problem over variables addressed by keys of type `K`.
+///
+/// The keys are interned when residual blocks are added and every variable
+/// gets an index, parameter blocks and columns are looked up by that index
+/// (see `variable_keys`) so no keys are hashed while solving.
+pub struct Problem<K = String> {
     pub total_residual_dimension: usize,
     residual_id_count: usize,
     residual_blocks: HashMap<ResidualBlockId, residual_block::ResidualBlock>,
-    pub fixed_variable_indexes: HashMap<String, HashSet<usize>>,
-    pub variable_bounds: HashMap<String, HashMap<usize, (f64, f64)>>,
-    pub variable_manifold: HashMap<String, Arc<dyn Manifold + Sync + Send>>,
+    pub fixed_variable_indexes: HashMap<K, HashSet<usize>>,
+    pub variable_bounds: HashMap<K, HashMap<usize, (f64, f64)>>,
+    pub variable_manifold: HashMap<K, Arc<dyn Manifold + Sync + Send>>,
+    variable_keys: Vec<K>,
+    variable_key_to_idx: HashMap<K, usize>,
+    structure_id: usize,
 }
-impl Default for Problem {
+impl<K: VariableKey> Default for Problem<K> {
     fn default() -> Self {
-        Self::new()
+        Problem {
+            total_residual_dimension: 0,
+            residual_id_count: 0,
+            residual_blocks: HashMap::new(),
+            fixed_variable_indexes: HashMap::new(),
+            variable_bounds: HashMap::new(),
+            variable_manifold: HashMap::new(),
+            variable_keys: Vec::new(),
+            variable_key_to_idx: HashMap::new(),
+            structure_id: next_structure_id(),
+        }
     }
 }
 
@@ -33,53 +61,102 @@ pub struct SymbolicStructure {
 
 type JacobianValue = f64;
 
-impl Problem {
+/// What `Problem::evaluate` computes, everything by default.
+#[derive(Debug, Clone, Copy)]
+pub struct EvaluateOptions {
+    /// Robustify the cost, the residuals and the Jacobian with the loss
+    /// functions. Without it the cost is `0.5 * |f(x)|^2`.
+    pub apply_loss_function: bool,
+    pub residuals: bool,
+    pub gradient: bool,
+    pub jacobian: bool,
+}
+impl Default for EvaluateOptions {
+    fn default() -> Self {
+        EvaluateOptions {
+            apply_loss_function: true,
+            residuals: true,
+            gradient: true,
+            jacobian: true,
+        }
+    }
+}
+
+/// Output of `Problem::evaluate`. The gradient and the Jacobian are taken
+/// with respect to the tangent space of the variables, fixed tangent
+/// coordinates have no column.
+pub struct Evaluation<K> {
+    pub cost: f64,
+    /// Residuals of every residual block.
+    pub residuals: Option<HashMap<ResidualBlockId, na::DVector<f64>>>,
+    pub gradient: Option<na::DVector<f64>>,
+    pub jacobian: Option<SparseColMat<usize, f64>>,
+    /// First row and number of rows of every residual block in `jacobian`.
+    pub residual_rows: HashMap<ResidualBlockId, (usize, usize)>,
+    /// First column and number of columns of every variable in `gradient`
+    /// and `jacobian`.
+    pub variable_columns: HashMap<K, (usize, usize)>,
+}
+
+impl Problem<String> {
+    /// Problem with `String` keys, use `Problem::<K>::default()` for other key types.
     pub fn new() -> Problem {
-        Problem {
-            total_residual_dimension: 0,
-            residual_id_count: 0,
-            residual_blocks: HashMap::new(),
-            fixed_variable_indexes: HashMap::new(),
-            variable_bounds: HashMap::new(),
-            variable_manifold: HashMap::new(),
+        Self::default()
+    }
+}
+
+impl<K: VariableKey> Problem<K> {
+    /// Keys of all variables in the problem, in the order of their indexes.
+    pub fn variable_keys(&self) -> &[K] {
+        &self.variable_keys
+    }
+    pub fn get_variable_idx(&self, key: &K) -> Option<usize> {
+        self.variable_key_to_idx.get(key).copied()
+    }
+    /// Changes whenever residual blocks or variables are added or removed,
+    /// so a `SolverContext` can tell that its symbolic analysis is stale.
+    pub(crate) fn structure_id(&self) -> usize {
+        self.structure_id
+    }
+    pub(crate) fn residual_blocks(
+        &self,
+    ) -> &HashMap<ResidualBlockId, residual_block::ResidualBlock> {
+        &self.residual_blocks
+    }
+    fn intern_variable(&mut self, key: K) -> usize {
+        if let Some(&idx) = self.variable_key_to_idx.get(&key) {
+            return idx;
         }
+        let idx = self.variable_keys.len();
+        self.variable_keys.push(key.clone());
+        self.variable_key_to_idx.insert(key, idx);
+        idx
     }
 
     pub fn build_symbolic_structure(
         &self,
-        parameter_blocks: &HashMap<String, ParameterBlock>,
+        parameter_blocks: &[Option<ParameterBlock>],
         total_variable_dimension: usize,
-        variable_name_to_col_idx_dict: &HashMap<String, usize>,
+        variable_idx_to_col_idx: &[usize],
     ) -> SymbolicStructure {
         let mut indices = Vec::<Pair<usize, usize>>::new();
 
         self.residual_blocks.iter().for_each(|(_, residual_block)| {
-            let mut variable_local_idx_size_list = Vec::<(usize, usize)>::new();
-            let mut count_variable_local_idx: usize = 0;
-            for var_key in &residual_block.variable_key_list {
-                if let Some(param) = parameter_blocks.get(var_key) {
-                    variable_local_idx_size_list
-                        .push((count_variable_local_idx, param.tangent_size()));
-                    count_variable_local_idx += param.tangent_size();
+            for &var_idx in &residual_block.variable_idx_list {
+                let Some(param) = parameter_blocks[var_idx].as_ref() else {
+                    continue;
                 };
-            }
-            for (i, var_key) in residual_block.variable_key_list.iter().enumerate() {
-                if let Some(variable_global_idx) = variable_name_to_col_idx_dict.get(var_key) {
-                    let (_, var_size) = variable_local_idx_size_list[i];
-                    for row_idx in 0..residual_block.dim_residual {
-                        let mut current_var_col_offset = 0;
-                        for col_idx in 0..var_size {
-                            if let Some(param) = parameter_blocks.get(var_key)
-                                && param.manifold.is_none()
-                                && param.fixed_variables.contains(&col_idx)
-                            {
-                                continue;
-                            }
-                            let global_row_idx = residual_block.residual_row_start_idx + row_idx;
-                            let global_col_idx = variable_global_idx + current_var_col_offset;
-                            indices.push(Pair::new(global_row_idx, global_col_idx));
-                            current_var_col_offset += 1;
+                let variable_global_idx = variable_idx_to_col_idx[var_idx];
+                for row_idx in 0..residual_block.dim_residual {
+                    let mut current_var_col_offset = 0;
+                    for col_idx in 0..param.tangent_size() {
+                        if param.fixed_variables.contains(&col_idx) {
+                            continue;
                         }
+                        let global_row_idx = residual_block.residual_row_start_idx + row_idx;
+                        let global_col_idx = variable_global_idx + current_var_col_offset;
+                        indices.push(Pair::new(global_row_idx, global_col_idx));
+                        current_var_col_offset += 1;
                     }
                 }
             }
@@ -98,39 +175,106 @@ impl Problem {
         }
     }
 
-    pub fn get_variable_name_to_col_idx_dict(
+    /// First column of every variable, indexed by the variable index.
+    /// Variables without a parameter block have no columns.
+    pub fn get_variable_idx_to_col_idx(
         &self,
-        parameter_blocks: &HashMap<String, ParameterBlock>,
-    ) -> HashMap<String, usize> {
+        parameter_blocks: &[Option<ParameterBlock>],
+    ) -> Vec<usize> {
         let mut count_col_idx = 0;
-        let mut variable_name_to_col_idx_dict = HashMap::new();
         parameter_blocks
             .iter()
-            .for_each(|(param_name, param_block)| {
-                variable_name_to_col_idx_dict.insert(param_name.to_owned(), count_col_idx);
-                let effective_size = if param_block.manifold.is_some() {
-                    param_block.tangent_size()
-                } else {
-                    param_block.tangent_size() - param_block.fixed_variables.len()
-                };
-                count_col_idx += effective_size;
-            });
-        variable_name_to_col_idx_dict
+            .map(|param_block| {
+                let col_idx = count_col_idx;
+                if let Some(param_block) = param_block {
+                    count_col_idx += param_block.free_tangent_size();
+                }
+                col_idx
+            })
+            .collect()
+    }
+    /// Greedily picks variables that never appear together in a residual block,
+    /// preferring the ones with the smallest tangent size (e.g. landmarks in
+    /// bundle adjustment). Used as the elimination ordering of the Schur solvers.
+    pub fn independent_variable_set(&self, parameter_blocks: &[Option<ParameterBlock>]) -> Vec<K> {
+        self.independent_variable_idx_set(parameter_blocks)
+            .into_iter()
+            .map(|idx| self.variable_keys[idx].clone())
+            .collect()
     }
-    pub fn add_residual_block(
+    pub(crate) fn independent_variable_idx_set(
+        &self,
+        parameter_blocks: &[Option<ParameterBlock>],
+    ) -> Vec<usize> {
+        let mut neighbors: Vec<HashSet<usize>> = vec![HashSet::new(); self.variable_keys.len()];
+        let mut in_residual_block = vec![false; self.variable_keys.len()];
+        for residual_block in self.residual_blocks.values() {
+            for &var_a in &residual_block.variable_idx_list {
+                in_residual_block[var_a] = true;
+                for &var_b in &residual_block.variable_idx_list {
+                    if var_a != var_b {
+                        neighbors[var_a].insert(var_b);
+                    }
+                }
+            }
+        }
+        // ties are broken by the order the variables were added
+        let mut candidates: Vec<(usize, usize)> = parameter_blocks
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| in_residual_block[*idx])
+            .filter_map(|(idx, param)| Some((idx, param.as_ref()?.tangent_size())))
+            .collect();
+        candidates.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        let mut excluded = HashSet::new();
+        let mut independent_set = Vec::new();
+        for (idx, _) in candidates {
+            if excluded.contains(&idx) {
+                continue;
+            }
+            independent_set.push(idx);
+            excluded.extend(neighbors[idx].iter().copied());
+        }
+        independent_set
+    }
+    pub fn add_residual_block<Q: Into<K> + Clone>(
         &mut self,
         dim_residual: usize,
-        variable_key_size_list: &[&str],
+        variable_keys: &[Q],
         factor: Box<dyn factors::FactorImpl + Send>,
         loss_func: Option<Box<dyn loss_functions::Loss + Send>>,
-    ) -> ResidualBlockId {
+    ) -> Result<ResidualBlockId, TinySolverError> {
+        if dim_residual == 0 {
+            return Err(TinySolverError::InvalidResidualBlock(
+                "dim_residual is zero".to_string(),
+            ));
+        }
+        if variable_keys.is_empty() {
+            return Err(TinySolverError::InvalidResidualBlock(
+                "no variables".to_string(),
+            ));
+        }
+        let keys: Vec<K> = variable_keys.iter().map(|k| k.clone().into()).collect();
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].contains(key) {
+                return Err(TinySolverError::InvalidResidualBlock(format!(
+                    "variable {} is used twice",
+                    key_to_string(key)
+                )));
+            }
+        }
+        let variable_idx_list: Vec<usize> = keys
+            .into_iter()
+            .map(|key| self.intern_variable(key))
+            .collect();
         self.residual_blocks.insert(
             self.residual_id_count,
             residual_block::ResidualBlock::new(
                 self.residual_id_count,
                 dim_residual,
                 self.total_residual_dimension,
-                variable_key_size_list,
+                &variable_idx_list,
                 factor,
                 loss_func,
             ),
@@ -139,8 +283,9 @@ impl Problem {
         self.residual_id_count += 1;
 
         self.total_residual_dimension += dim_residual;
+        self.structure_id = next_structure_id();
 
-        block_id
+        Ok(block_id)
     }
     pub fn remove_residual_block(
         &mut self,
@@ -148,125 +293,421 @@ impl Problem {
     ) -> Option<residual_block::ResidualBlock> {
         if let Some(residual_block) = self.residual_blocks.remove(&block_id) {
             self.total_residual_dimension -= residual_block.dim_residual;
+            self.structure_id = next_structure_id();
+            // keep the residual rows contiguous
+            for other in self.residual_blocks.values_mut() {
+                if other.residual_row_start_idx > residual_block.residual_row_start_idx {
+                    other.residual_row_start_idx -= residual_block.dim_residual;
+                }
+            }
             Some(residual_block)
         } else {
             None
         }
     }
-    pub fn fix_variable(&mut self, var_to_fix: &str, idx: usize) {
-        if let Some(var_mut) = self.fixed_variable_indexes.get_mut(var_to_fix) {
-            var_mut.insert(idx);
-        } else {
-            self.fixed_variable_indexes
-                .insert(var_to_fix.to_owned(), HashSet::from([idx]));
+    /// Marginalizes `variables` out of the problem at `values`, e.g. the
+    /// oldest states of a sliding window. The residual blocks connected to
+    /// them are linearized and replaced by a `MarginalizationFactor` on the
+    /// other variables of those blocks, which keeps their information. The
+    /// variables are removed with their fixed indexes, bounds and manifolds.
+    ///
+    /// Returns the id of the new residual block, or `None` if the
+    /// marginalized variables constrain nothing else. A key that is not in
+    /// the problem returns `UnknownVariable`. The problem is left unchanged
+    /// if an error is returned.
+    pub fn marginalize_variables<Q: Into<K> + Clone>(
+        &mut self,
+        variables: &[Q],
+        values: &HashMap<K, na::DVector<f64>>,
+    ) -> Result<Option<ResidualBlockId>, TinySolverError> {
+        let mut marginalized = HashSet::new();
+        for key in variables {
+            let key: K = key.clone().into();
+            match self.get_variable_idx(&key) {
+                Some(var_idx) => marginalized.insert(var_idx),
+                None => {
+                    return Err(TinySolverError::UnknownVariable {
+                        key: key_to_string(&key),
+                    });
+                }
+            };
+        }
+        let mut block_ids: Vec<ResidualBlockId> = self
+            .residual_blocks
+            .iter()
+            .filter(|(_, block)| {
+                block
+                    .variable_idx_list
+                    .iter()
+                    .any(|var_idx| marginalized.contains(var_idx))
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        block_ids.sort();
+        let mut kept: Vec<usize> = block_ids
+            .iter()
+            .flat_map(|id| self.residual_blocks[id].variable_idx_list.iter().copied())
+            .filter(|var_idx| !marginalized.contains(var_idx))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        kept.sort();
+        let mut marginalized_sorted: Vec<usize> = marginalized.iter().copied().collect();
+        marginalized_sorted.sort();
+
+        // dense Jacobian of the connected blocks, the free coordinates of the
+        // marginalized variables first and then the tangent coordinates of
+        // the kept ones, fixed coordinates have zero columns
+        let parameter_blocks = self.initialize_parameter_blocks(values);
+        let mut col_idx = vec![0; self.variable_keys.len()];
+        let mut num_cols = 0;
+        for &var_idx in &marginalized_sorted {
+            col_idx[var_idx] = num_cols;
+            if let Some(param) = &parameter_blocks[var_idx] {
+                num_cols += param.free_tangent_size();
+            }
+        }
+        let marginalized_size = num_cols;
+        for &var_idx in &kept {
+            col_idx[var_idx] = num_cols;
+            if let Some(param) = &parameter_blocks[var_idx] {
+                num_cols += param.tangent_size();
+            }
+        }
+        let num_rows = block_ids
+            .iter()
+            .map(|id| self.residual_blocks[id].dim_residual)
+            .sum();
+        let mut jacobian = na::DMatrix::zeros(num_rows, num_cols);
+        let mut residual = na::DVector::zeros(num_rows);
+        let mut row = 0;
+        for id in &block_ids {
+            let residual_block = &self.residual_blocks[id];
+            let params = self.residual_block_params(residual_block, &parameter_blocks)?;
+            let (res, jac) = residual_block.residual_and_jacobian(&params);
+            check_residual_dimension(residual_block, res.nrows())?;
+            residual.rows_mut(row, res.nrows()).copy_from(&res);
+            let mut local_col = 0;
+            for (param, &var_idx) in params.iter().zip(&residual_block.variable_idx_list) {
+                let mut col = col_idx[var_idx];
+                for i in 0..param.tangent_size() {
+                    let is_fixed = param.fixed_variables.contains(&i);
+                    if !is_fixed {
+                        jacobian
+                            .view_mut((row, col), (res.nrows(), 1))
+                            .copy_from(&jac.column(local_col + i));
+                    }
+                    if !is_fixed || !marginalized.contains(&var_idx) {
+                        col += 1;
+                    }
+                }
+                local_col += param.tangent_size();
+            }
+            row += res.nrows();
+        }
+        let prior =
+            marginalization::schur_complement_prior(&jacobian, &residual, marginalized_size);
+
+        let kept_keys: Vec<K> = kept
+            .iter()
+            .map(|&var_idx| self.variable_keys[var_idx].clone())
+            .collect();
+        let factor = prior.map(|(sqrt_information, residual)| {
+            let kept_params: Vec<&ParameterBlock> = kept
+                .iter()
+                .map(|&var_idx| parameter_blocks[var_idx].as_ref().unwrap())
+                .collect();
+            marginalization::MarginalizationFactor {
+                linearization_points: kept_params.iter().map(|p| p.params.clone()).collect(),
+                manifolds: kept_params.iter().map(|p| p.manifold.clone()).collect(),
+                sqrt_information,
+                residual,
+            }
+        });
+        for id in block_ids {
+            self.remove_residual_block(id);
+        }
+        self.remove_variables(&marginalized);
+        let Some(factor) = factor else {
+            return Ok(None);
+        };
+        self.add_residual_block(factor.residual.len(), &kept_keys, Box::new(factor), None)
+            .map(Some)
+    }
+    /// Removes variables that are not used by any residual block and
+    /// renumbers the others.
+    fn remove_variables(&mut self, var_indices: &HashSet<usize>) {
+        for &var_idx in var_indices {
+            let key = &self.variable_keys[var_idx];
+            self.fixed_variable_indexes.remove(key);
+            self.variable_bounds.remove(key);
+            self.variable_manifold.remove(key);
+        }
+        self.structure_id = next_structure_id();
+        let mut new_idx = vec![None; self.variable_keys.len()];
+        let variable_keys = std::mem::take(&mut self.variable_keys);
+        self.variable_key_to_idx.clear();
+        for (var_idx, key) in variable_keys.into_iter().enumerate() {
+            if !var_indices.contains(&var_idx) {
+                new_idx[var_idx] = Some(self.variable_keys.len());
+                self.variable_key_to_idx
+                    .insert(key.clone(), self.variable_keys.len());
+                self.variable_keys.push(key);
+            }
+        }
+        for residual_block in self.residual_blocks.values_mut() {
+            for var_idx in residual_block.variable_idx_list.iter_mut() {
+                *var_idx = new_idx[*var_idx].expect("removed variable is still in use");
+            }
         }
     }
-    pub fn unfix_variable(&mut self, var_to_unfix: &str) {
-        self.fixed_variable_indexes.remove(var_to_unfix);
+    /// Fixes the parameter at `idx`. On a manifold `idx` is a tangent index,
+    /// e.g. `5` fixes the z translation of an `SE3Manifold` pose.
+    pub fn fix_variable<Q: Into<K>>(&mut self, var_to_fix: Q, idx: usize) {
+        self.fixed_variable_indexes
+            .entry(var_to_fix.into())
+            .or_default()
+            .insert(idx);
     }
-    pub fn set_variable_bounds(
+    pub fn unfix_variable<Q: Into<K>>(&mut self, var_to_unfix: Q) {
+        self.fixed_variable_indexes.remove(&var_to_unfix.into());
+    }
+    pub fn set_variable_bounds<Q: Into<K>>(
         &mut self,
-        var_to_bound: &str,
+        var_to_bound: Q,
         idx: usize,
         lower_bound: f64,
         upper_bound: f64,
     ) {
         if lower_bound > upper_bound {
             log::error!("lower bound is larger than upper bound");
-        } else if let Some(var_mut) = self.variable_bounds.get_mut(var_to_bound) {
-            var_mut.insert(idx, (lower_bound, upper_bound));
         } else {
-            self.variable_bounds.insert(
-                var_to_bound.to_owned(),
-                HashMap::from([(idx, (lower_bound, upper_bound))]),
-            );
+            self.variable_bounds
+                .entry(var_to_bound.into())
+                .or_default()
+                .insert(idx, (lower_bound, upper_bound));
         }
     }
-    pub fn set_variable_manifold(
+    pub fn set_variable_manifold<Q: Into<K>>(
         &mut self,
-        var_name: &str,
+        var_name: Q,
         manifold: Arc<dyn Manifold + Sync + Send>,
     ) {
-        self.variable_manifold
-            .insert(var_name.to_string(), manifold);
+        self.variable_manifold.insert(var_name.into(), manifold);
     }
-    pub fn remove_variable_bounds(&mut self, var_to_unbound: &str) {
-        self.variable_bounds.remove(var_to_unbound);
+    pub fn remove_variable_bounds<Q: Into<K>>(&mut self, var_to_unbound: Q) {
+        self.variable_bounds.remove(&var_to_unbound.into());
     }
+    /// Parameter blocks indexed by the variable index, `None` for variables
+    /// without an initial value.
     pub fn initialize_parameter_blocks(
         &self,
-        initial_values: &HashMap<String, na::DVector<f64>>,
-    ) -> HashMap<String, ParameterBlock> {
-        let parameter_blocks: HashMap<String, ParameterBlock> = initial_values
+        initial_values: &HashMap<K, na::DVector<f64>>,
+    ) -> Vec<Option<ParameterBlock>> {
+        self.variable_keys
             .iter()
-            .map(|(k, v)| {
-                let mut p_block = ParameterBlock::from_vec(v.clone());
-                if let Some(indexes) = self.fixed_variable_indexes.get(k) {
-                    p_block.fixed_variables = indexes.clone();
-                }
-                if let Some(bounds) = self.variable_bounds.get(k) {
-                    p_block.variable_bounds = bounds.clone();
-                }
-                if let Some(manifold) = self.variable_manifold.get(k) {
-                    p_block.manifold = Some(manifold.clone())
-                }
-
-                (k.to_owned(), p_block)
-            })
-            .collect();
-        parameter_blocks
+            .map(|k| Some(self.parameter_block(k, initial_values.get(k)?.clone())))
+            .collect()
+    }
+    /// Parameter block of `key` with its fixed indexes, bounds and manifold.
+    pub(crate) fn parameter_block(&self, key: &K, value: na::DVector<f64>) -> ParameterBlock {
+        let mut p_block = ParameterBlock::from_vec(value);
+        if let Some(indexes) = self.fixed_variable_indexes.get(key) {
+            p_block.fixed_variables = indexes.clone();
+        }
+        if let Some(bounds) = self.variable_bounds.get(key) {
+            p_block.variable_bounds = bounds.clone();
+        }
+        if let Some(manifold) = self.variable_manifold.get(key) {
+            p_block.manifold = Some(manifold.clone())
+        }
+        p_block
+    }
+    /// Values of the parameter blocks keyed by variable. Initial values of
+    /// variables that are not in the problem are returned unchanged.
+    pub fn parameter_blocks_to_values(
+        &self,
+        parameter_blocks: &[Option<ParameterBlock>],
+        initial_values: &HashMap<K, na::DVector<f64>>,
+    ) -> HashMap<K, na::DVector<f64>> {
+        let mut values = initial_values.clone();
+        for (key, param_block) in self.variable_keys.iter().zip(parameter_blocks) {
+            if let Some(param_block) = param_block {
+                values.insert(key.clone(), param_block.params.clone());
+            }
+        }
+        values
     }
 
     pub fn compute_residuals(
         &self,
-        parameter_blocks: &HashMap<String, ParameterBlock>,
+        parameter_blocks: &[Option<ParameterBlock>],
         with_loss_fn: bool,
-    ) -> faer::Mat<f64> {
+    ) -> Result<faer::Mat<f64>, TinySolverError> {
         let total_residual = Arc::new(Mutex::new(na::DVector::<f64>::zeros(
             self.total_residual_dimension,
         )));
         self.residual_blocks
             .par_iter()
-            .for_each(|(_, residual_block)| {
+            .try_for_each(|(_, residual_block)| {
                 self.compute_residual_impl(
                     residual_block,
                     parameter_blocks,
                     &total_residual,
                     with_loss_fn,
                 )
-            });
+            })?;
         let total_residual = Arc::try_unwrap(total_residual)
             .unwrap()
             .into_inner()
             .unwrap();
 
-        total_residual.view_range(.., ..).into_faer().to_owned()
+        Ok(total_residual.view_range(.., ..).into_faer().to_owned())
+    }
+
+    /// Total robustified cost `sum_i 0.5 * rho_i(|f_i(x)|^2)` of the
+    /// parameter blocks, the objective minimized by the optimizers.
+    pub fn compute_cost(
+        &self,
+        parameter_blocks: &[Option<ParameterBlock>],
+    ) -> Result<f64, TinySolverError> {
+        let costs: Vec<f64> = self
+            .residual_blocks
+            .par_iter()
+            .map(|(_, residual_block)| {
+                let params = self.residual_block_params(residual_block, parameter_blocks)?;
+                let res = residual_block.residual(&params, false);
+                check_residual_dimension(residual_block, res.nrows())?;
+                Ok(residual_block.cost_from_squared_norm(res.norm_squared()))
+            })
+            .collect::<Result<_, TinySolverError>>()?;
+        Ok(costs.iter().sum())
+    }
+
+    /// Total robustified cost at `values`, as `Problem::Evaluate` in Ceres.
+    pub fn evaluate_cost(
+        &self,
+        values: &HashMap<K, na::DVector<f64>>,
+    ) -> Result<f64, TinySolverError> {
+        self.compute_cost(&self.initialize_parameter_blocks(values))
+    }
+
+    /// Cost, residuals, gradient and Jacobian at `values`, as
+    /// `Problem::Evaluate` in Ceres. Useful to debug factors or to hand the
+    /// problem to another optimizer.
+    pub fn evaluate(
+        &self,
+        values: &HashMap<K, na::DVector<f64>>,
+        options: EvaluateOptions,
+    ) -> Result<Evaluation<K>, TinySolverError> {
+        let parameter_blocks = self.initialize_parameter_blocks(values);
+        let variable_idx_to_col_idx = self.get_variable_idx_to_col_idx(&parameter_blocks);
+        let variable_columns: HashMap<K, (usize, usize)> = self
+            .variable_keys
+            .iter()
+            .zip(&parameter_blocks)
+            .zip(&variable_idx_to_col_idx)
+            .filter_map(|((key, param), &col_idx)| {
+                Some((key.clone(), (col_idx, param.as_ref()?.free_tangent_size())))
+            })
+            .collect();
+        let residual_rows: HashMap<ResidualBlockId, (usize, usize)> = self
+            .residual_blocks
+            .iter()
+            .map(|(&id, block)| (id, (block.residual_row_start_idx, block.dim_residual)))
+            .collect();
+
+        let (total_residual, jacobian) = if options.gradient || options.jacobian {
+            let total_variable_dimension = variable_columns.values().map(|(_, n)| n).sum();
+            let symbolic_structure = self.build_symbolic_structure(
+                &parameter_blocks,
+                total_variable_dimension,
+                &variable_idx_to_col_idx,
+            );
+            let (residual, jacobian) = self.compute_residual_and_jacobian_with_loss(
+                &parameter_blocks,
+                &variable_idx_to_col_idx,
+                &symbolic_structure,
+                options.apply_loss_function,
+            )?;
+            (residual, Some(jacobian))
+        } else {
+            let residual =
+                self.compute_residuals(&parameter_blocks, options.apply_loss_function)?;
+            (residual, None)
+        };
+        let gradient = if options.gradient {
+            let gradient = jacobian.as_ref().unwrap().as_ref().transpose() * &total_residual;
+            Some(gradient.as_ref().into_nalgebra().column(0).into_owned())
+        } else {
+            None
+        };
+        let total_residual = total_residual
+            .as_ref()
+            .into_nalgebra()
+            .column(0)
+            .into_owned();
+
+        let cost = if options.apply_loss_function {
+            self.compute_cost(&parameter_blocks)?
+        } else {
+            0.5 * total_residual.norm_squared()
+        };
+        let residuals = options.residuals.then(|| {
+            residual_rows
+                .iter()
+                .map(|(&id, &(start, size))| (id, total_residual.rows(start, size).into_owned()))
+                .collect()
+        });
+        Ok(Evaluation {
+            cost,
+            residuals,
+            gradient,
+            jacobian: jacobian.filter(|_| options.jacobian),
+            residual_rows,
+            variable_columns,
+        })
     }
 
     pub fn compute_residual_and_jacobian(
         &self,
-        parameter_blocks: &HashMap<String, ParameterBlock>,
-        variable_name_to_col_idx_dict: &HashMap<String, usize>,
+        parameter_blocks: &[Option<ParameterBlock>],
+        variable_idx_to_col_idx: &[usize],
         symbolic_structure: &SymbolicStructure,
-    ) -> (faer::Mat<f64>, SparseColMat<usize, f64>) {
+    ) -> Result<(faer::Mat<f64>, SparseColMat<usize, f64>), TinySolverError> {
+        self.compute_residual_and_jacobian_with_loss(
+            parameter_blocks,
+            variable_idx_to_col_idx,
+            symbolic_structure,
+            true,
+        )
+    }
+
+    fn compute_residual_and_jacobian_with_loss(
+        &self,
+        parameter_blocks: &[Option<ParameterBlock>],
+        variable_idx_to_col_idx: &[usize],
+        symbolic_structure: &SymbolicStructure,
+        with_loss_fn: bool,
+    ) -> Result<(faer::Mat<f64>, SparseColMat<usize, f64>), TinySolverError> {
         // multi
         let total_residual = Arc::new(Mutex::new(na::DVector::<f64>::zeros(
             self.total_residual_dimension,
         )));
 
-        let jacobian_lists: Vec<JacobianValue> = self
+        let jacobian_lists: Vec<Vec<JacobianValue>> = self
             .residual_blocks
             .par_iter()
             .map(|(_, residual_block)| {
                 self.compute_residual_and_jacobian_impl(
                     residual_block,
                     parameter_blocks,
-                    variable_name_to_col_idx_dict,
+                    variable_idx_to_col_idx,
                     &total_residual,
+                    with_loss_fn,
                 )
             })
-            .flatten()
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         let total_residual = Arc::try_unwrap(total_residual)
             .unwrap()
@@ -277,26 +718,22 @@ impl Problem {
         let jacobian_faer = SparseColMat::new_from_argsort(
             symbolic_structure.pattern.clone(),
             &symbolic_structure.order,
-            jacobian_lists.as_slice(),
+            jacobian_lists.concat().as_slice(),
         )
         .unwrap();
-        (residual_faer, jacobian_faer)
+        Ok((residual_faer, jacobian_faer))
     }
 
     fn compute_residual_impl(
         &self,
         residual_block: &crate::ResidualBlock,
-        parameter_blocks: &HashMap<String, ParameterBlock>,
+        parameter_blocks: &[Option<ParameterBlock>],
         total_residual: &Arc<Mutex<na::DVector<f64>>>,
         with_loss_fn: bool,
-    ) {
-        let mut params = Vec::new();
-        for var_key in &residual_block.variable_key_list {
-            if let Some(param) = parameter_blocks.get(var_key) {
-                params.push(param);
-            };
-        }
+    ) -> Result<(), TinySolverError> {
+        let params = self.residual_block_params(residual_block, parameter_blocks)?;
         let res = residual_block.residual(&params, with_loss_fn);
+        check_residual_dimension(residual_block, res.nrows())?;
 
         {
             let mut total_residual = total_residual.lock().unwrap();
@@ -307,26 +744,27 @@ impl Problem {
                 )
                 .copy_from(&res);
         }
+        Ok(())
     }
 
     fn compute_residual_and_jacobian_impl(
         &self,
         residual_block: &crate::ResidualBlock,
-        parameter_blocks: &HashMap<String, ParameterBlock>,
-        variable_name_to_col_idx_dict: &HashMap<String, usize>,
+        parameter_blocks: &[Option<ParameterBlock>],
+        variable_idx_to_col_idx: &[usize],
         total_residual: &Arc<Mutex<na::DVector<f64>>>,
-    ) -> Vec<JacobianValue> {
-        let mut params = Vec::new();
+        with_loss_fn: bool,
+    ) -> Result<Vec<JacobianValue>, TinySolverError> {
+        let params = self.residual_block_params(residual_block, parameter_blocks)?;
         let mut variable_local_idx_size_list = Vec::<(usize, usize)>::new();
         let mut count_variable_local_idx: usize = 0;
-        for var_key in &residual_block.variable_key_list {
-            if let Some(param) = parameter_blocks.get(var_key) {
-                params.push(param);
-                variable_local_idx_size_list.push((count_variable_local_idx, param.tangent_size()));
-                count_variable_local_idx += param.tangent_size();
-            };
+        for param in &params {
+            variable_local_idx_size_list.push((count_variable_local_idx, param.tangent_size()));
+            count_variable_local_idx += param.tangent_size();
         }
-        let (res, jac) = residual_block.residual_and_jacobian(&params);
+        debug_assert_eq!(variable_idx_to_col_idx.len(), parameter_blocks.len());
+        let (res, jac) = residual_block.residual_and_jacobian_impl(&params, with_loss_fn);
+        check_residual_dimension(residual_block, res.nrows())?;
         {
             let mut total_residual = total_residual.lock().unwrap();
             total_residual
@@ -339,39 +777,158 @@ impl Problem {
 
         let mut local_jacobian_list = Vec::new();
 
-        for (i, var_key) in residual_block.variable_key_list.iter().enumerate() {
-            if variable_name_to_col_idx_dict.contains_key(var_key) {
-                let (variable_local_idx, var_size) = variable_local_idx_size_list[i];
-                let variable_jac = jac.view((0, variable_local_idx), (jac.shape().0, var_size));
-                let param = &params[i];
-                for row_idx in 0..jac.shape().0 {
-                    for col_idx in 0..var_size {
-                        if param.manifold.is_none() && param.fixed_variables.contains(&col_idx) {
-                            continue;
-                        }
-                        let j_value = variable_jac[(row_idx, col_idx)];
-                        if j_value.is_finite() {
-                            local_jacobian_list.push(j_value);
-                        } else {
-                            log::warn!(
-                                "Non-finite Jacobian value detected at residual block {}, variable {}, row {}, col {}. Setting to 0.0",
-                                residual_block.residual_block_id,
-                                var_key,
-                                row_idx,
-                                col_idx
-                            );
-                            local_jacobian_list.push(0.0);
+        for (i, &var_idx) in residual_block.variable_idx_list.iter().enumerate() {
+            let (variable_local_idx, var_size) = variable_local_idx_size_list[i];
+            let variable_jac = jac.view((0, variable_local_idx), (jac.shape().0, var_size));
+            let param = &params[i];
+            for row_idx in 0..jac.shape().0 {
+                for col_idx in 0..var_size {
+                    if param.fixed_variables.contains(&col_idx) {
+                        continue;
+                    }
+                    let j_value = variable_jac[(row_idx, col_idx)];
+                    if j_value.is_finite() {
+                        local_jacobian_list.push(j_value);
+                    } else {
+                        log::warn!(
+                            "Non-finite Jacobian value detected at residual block {}, variable {}, row {}, col {}. Setting to 0.0",
+                            residual_block.residual_block_id,
+                            key_to_string(&self.variable_keys[var_idx]),
+                            row_idx,
+                            col_idx
+                        );
+                        local_jacobian_list.push(0.0);
+                    }
+                }
+            }
+        }
+
+        Ok(local_jacobian_list)
+    }
+
+    /// Checks the problem against `initial_values` before solving. Every
+    /// factor is evaluated once to check its output dimension, and every
+    /// variable needs an initial value matching the ambient size of its
+    /// manifold with fixed indexes inside its tangent space. All problems
+    /// are reported together in `TinySolverError::InvalidProblem`.
+    pub fn validate(
+        &self,
+        initial_values: &HashMap<K, na::DVector<f64>>,
+    ) -> Result<(), TinySolverError> {
+        let mut errors = Vec::new();
+        let mut variable_is_valid = vec![true; self.variable_keys.len()];
+        for (var_idx, key) in self.variable_keys.iter().enumerate() {
+            let (Some(value), Some(manifold)) =
+                (initial_values.get(key), self.variable_manifold.get(key))
+            else {
+                continue;
+            };
+            let expected = manifold.ambient_size().get();
+            if value.len() != expected {
+                variable_is_valid[var_idx] = false;
+                errors.push(TinySolverError::VariableSizeMismatch {
+                    key: key_to_string(&key),
+                    expected,
+                    actual: value.len(),
+                });
+            }
+        }
+
+        let parameter_blocks = self.initialize_parameter_blocks(initial_values);
+        for ((key, param), &is_valid) in self
+            .variable_keys
+            .iter()
+            .zip(&parameter_blocks)
+            .zip(&variable_is_valid)
+        {
+            let Some(param) = param.as_ref().filter(|_| is_valid) else {
+                continue;
+            };
+            let tangent_size = param.tangent_size();
+            let mut out_of_range: Vec<usize> = param
+                .fixed_variables
+                .iter()
+                .copied()
+                .filter(|&idx| idx >= tangent_size)
+                .collect();
+            out_of_range.sort();
+            for index in out_of_range {
+                errors.push(TinySolverError::FixedIndexOutOfRange {
+                    key: key_to_string(&key),
+                    index,
+                    tangent_size,
+                });
+            }
+        }
+        let mut residual_block_ids: Vec<&ResidualBlockId> = self.residual_blocks.keys().collect();
+        residual_block_ids.sort();
+        for residual_block_id in residual_block_ids {
+            let residual_block = &self.residual_blocks[residual_block_id];
+            let params = match self.residual_block_params(residual_block, &parameter_blocks) {
+                Ok(params) => params,
+                Err(_) => {
+                    for &var_idx in &residual_block.variable_idx_list {
+                        if parameter_blocks[var_idx].is_none() {
+                            errors.push(TinySolverError::MissingVariable {
+                                key: key_to_string(&self.variable_keys[var_idx]),
+                                residual_block_id: *residual_block_id,
+                            });
                         }
                     }
+                    continue;
                 }
-            } else {
-                panic!(
-                    "Missing key {} in variable-to-column-index mapping",
-                    var_key
-                );
+            };
+            // the factor can not be evaluated with values of the wrong size
+            if residual_block
+                .variable_idx_list
+                .iter()
+                .any(|&var_idx| !variable_is_valid[var_idx])
+            {
+                continue;
             }
+            let res = residual_block.residual(&params, false);
+            if let Err(err) = check_residual_dimension(residual_block, res.nrows()) {
+                errors.push(err);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(TinySolverError::InvalidProblem(errors))
         }
+    }
 
-        local_jacobian_list
+    pub(crate) fn residual_block_params<'a>(
+        &self,
+        residual_block: &crate::ResidualBlock,
+        parameter_blocks: &'a [Option<ParameterBlock>],
+    ) -> Result<Vec<&'a ParameterBlock>, TinySolverError> {
+        residual_block
+            .variable_idx_list
+            .iter()
+            .map(|&var_idx| {
+                parameter_blocks[var_idx]
+                    .as_ref()
+                    .ok_or_else(|| TinySolverError::MissingVariable {
+                        key: key_to_string(&self.variable_keys[var_idx]),
+                        residual_block_id: residual_block.residual_block_id,
+                    })
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn check_residual_dimension(
+    residual_block: &crate::ResidualBlock,
+    actual: usize,
+) -> Result<(), TinySolverError> {
+    if actual != residual_block.dim_residual {
+        return Err(TinySolverError::DimensionMismatch {
+            residual_block_id: residual_block.residual_block_id,
+            expected: residual_block.dim_residual,
+            actual,
+        });
     }
+    Ok(())
 }
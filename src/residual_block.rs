@@ -10,7 +10,8 @@ pub struct ResidualBlock {
     pub residual_block_id: usize,
     pub dim_residual: usize,
     pub residual_row_start_idx: usize,
-    pub variable_key_list: Vec<String>,
+    /// Indexes of the variables in `Problem::variable_keys`.
+    pub variable_idx_list: Vec<usize>,
     pub factor: Box<dyn FactorImpl + Send>,
     pub loss_func: Option<Box<dyn Loss + Send>>,
 }
@@ -19,7 +20,7 @@ impl ResidualBlock {
         residual_block_id: usize,
         dim_residual: usize,
         residual_row_start_idx: usize,
-        variable_key_size_list: &[&str],
+        variable_idx_list: &[usize],
         factor: Box<dyn FactorImpl + Send>,
         loss_func: Option<Box<dyn Loss + Send>>,
     ) -> Self {
@@ -27,10 +28,7 @@ impl ResidualBlock {
             residual_block_id,
             dim_residual,
             residual_row_start_idx,
-            variable_key_list: variable_key_size_list
-                .iter()
-                .map(|s| s.to_string())
-                .collect(),
+            variable_idx_list: variable_idx_list.to_vec(),
             factor,
             loss_func,
         }
@@ -40,21 +38,61 @@ impl ResidualBlock {
         let param_vec: Vec<_> = params.iter().map(|p| p.params.clone()).collect();
         let mut residual = self.factor.residual_func_f64(&param_vec);
         let squared_norm = residual.norm_squared();
-        if with_loss_fn {
-            if let Some(loss_func) = self.loss_func.as_ref() {
-                let rho = loss_func.evaluate(squared_norm);
-                // let cost = 0.5 * rho[0];
-                let corrector = Corrector::new(squared_norm, &rho);
-                corrector.correct_residuals(&mut residual);
-            }
-        } else {
-            // let cost = 0.5 * squared_norm;
+        if with_loss_fn && let Some(loss_func) = self.loss_func.as_ref() {
+            let rho = loss_func.evaluate(squared_norm);
+            let corrector = Corrector::new(squared_norm, &rho);
+            corrector.correct_residuals(&mut residual);
         }
         residual
     }
+    /// Robustified cost of the block, `0.5 * rho(|f(x)|^2)`, or
+    /// `0.5 * |f(x)|^2` without a loss function.
+    pub fn cost(&self, params: &[&ParameterBlock]) -> f64 {
+        self.cost_from_squared_norm(self.residual(params, false).norm_squared())
+    }
+    pub(crate) fn cost_from_squared_norm(&self, squared_norm: f64) -> f64 {
+        match self.loss_func.as_ref() {
+            Some(loss_func) => 0.5 * loss_func.evaluate(squared_norm)[0],
+            None => 0.5 * squared_norm,
+        }
+    }
+    /// Residual and Jacobian with respect to the tangent space of the
+    /// parameters, corrected by the loss function. Analytic factors skip the
+    /// dual numbers and small factors use statically sized ones.
     pub fn residual_and_jacobian(
         &self,
         params: &[&ParameterBlock],
+    ) -> (na::DVector<f64>, na::DMatrix<f64>) {
+        self.residual_and_jacobian_impl(params, true)
+    }
+    pub(crate) fn residual_and_jacobian_impl(
+        &self,
+        params: &[&ParameterBlock],
+        with_loss_fn: bool,
+    ) -> (na::DVector<f64>, na::DMatrix<f64>) {
+        let param_vec: Vec<_> = params.iter().map(|p| p.params.clone()).collect();
+        let (mut residual, mut jacobian) =
+            match self.factor.analytic_residual_and_jacobian(&param_vec) {
+                Some((residual, ambient_jacobian)) => {
+                    (residual, tangent_jacobian(params, &ambient_jacobian))
+                }
+                None => self
+                    .factor
+                    .tangent_residual_and_jacobian(params)
+                    .unwrap_or_else(|| self.autodiff_residual_and_jacobian(params)),
+            };
+        let squared_norm = residual.norm_squared();
+        if with_loss_fn && let Some(loss_func) = self.loss_func.as_ref() {
+            let rho = loss_func.evaluate(squared_norm);
+            let corrector = Corrector::new(squared_norm, &rho);
+            corrector.correct_jacobian(&residual, &mut jacobian);
+            corrector.correct_residuals(&mut residual);
+        }
+        (residual, jacobian)
+    }
+    fn autodiff_residual_and_jacobian(
+        &self,
+        params: &[&ParameterBlock],
     ) -> (na::DVector<f64>, na::DMatrix<f64>) {
         let variable_rows: Vec<usize> = params.iter().map(|x| x.tangent_size()).collect();
         let dim_variable = variable_rows.iter().sum::<usize>();
@@ -83,25 +121,40 @@ impl ResidualBlock {
 
         // tangent size
         let residual_with_jacobian = self.factor.residual_func_dual(&params_plus_tangent_dual);
-        let mut residual = residual_with_jacobian.map(|x| x.re);
+        let residual = residual_with_jacobian.map(|x| x.re);
         let jacobian = residual_with_jacobian
             .map(|x| x.eps.unwrap_generic(na::Dyn(dim_variable), na::Const::<1>));
-        let mut jacobian =
+        let jacobian =
             na::DMatrix::<f64>::from_fn(residual_with_jacobian.nrows(), dim_variable, |r, c| {
                 jacobian[r][c]
             });
-        let squared_norm = residual.norm_squared();
-        if let Some(loss_func) = self.loss_func.as_ref() {
-            let rho = loss_func.evaluate(squared_norm);
-            // let cost = 0.5 * rho[0];
-            let corrector = Corrector::new(squared_norm, &rho);
-            corrector.correct_jacobian(&residual, &mut jacobian);
-            corrector.correct_residuals(&mut residual);
+        (residual, jacobian)
+    }
+}
+
+/// Applies the chain rule through the manifold of each parameter block.
+fn tangent_jacobian(
+    params: &[&ParameterBlock],
+    ambient_jacobian: &na::DMatrix<f64>,
+) -> na::DMatrix<f64> {
+    let dim_variable = params.iter().map(|p| p.tangent_size()).sum();
+    let mut jacobian = na::DMatrix::zeros(ambient_jacobian.nrows(), dim_variable);
+    let mut ambient_col = 0;
+    let mut tangent_col = 0;
+    for param in params {
+        let ambient_size = param.ambient_size();
+        let tangent_size = param.tangent_size();
+        let ambient_block = ambient_jacobian.columns(ambient_col, ambient_size);
+        let mut tangent_block = jacobian.columns_mut(tangent_col, tangent_size);
+        if param.manifold.is_some() {
+            ambient_block.mul_to(&param.plus_jacobian(), &mut tangent_block);
         } else {
-            // let cost = 0.5 * squared_norm;
+            tangent_block.copy_from(&ambient_block);
         }
-        (residual, jacobian)
+        ambient_col += ambient_size;
+        tangent_col += tangent_size;
     }
+    jacobian
 }
 
 fn get_variable_rows(variable_rows: &[usize]) -> Vec<Vec<usize>> {
@@ -0,0 +1,46 @@
+use std::fmt;
+use std::hash::Hash;
+
+/// Key type of the variables in a `Problem`, e.g. `String` or `Symbol`.
+/// Keys are interned when a residual block is added, so the solver only
+/// deals with integer indexes internally.
+pub trait VariableKey: Hash + Eq + Clone + fmt::Debug + Send + Sync {}
+
+impl<T: Hash + Eq + Clone + fmt::Debug + Send + Sync> VariableKey for T {}
+
+/// Name of a key in errors and logs, its `Debug` output without the quotes
+/// of string keys.
+pub(crate) fn key_to_string<K: fmt::Debug>(key: &K) -> String {
+    let key = format!("{:?}", key);
+    match key.strip_prefix('"').and_then(|k| k.strip_suffix('"')) {
+        Some(k) => k.to_string(),
+        None => key,
+    }
+}
+
+/// GTSAM style key made of a character and an index, e.g. `Symbol::new('x', 1)`
+/// for the pose x1 or `Symbol::new('l', 3)` for the landmark l3. Both
+/// `Debug` and `Display` print it as `x1`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol {
+    pub chr: char,
+    pub index: u64,
+}
+
+impl Symbol {
+    pub const fn new(chr: char, index: u64) -> Self {
+        Symbol { chr, index }
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.chr, self.index)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.chr, self.index)
+    }
+}
@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use nalgebra as na;
+    use tiny_solver::factors::{Factor, PriorFactor, WeightedFactor};
+    use tiny_solver::{
+        Covariance, CovarianceAlgorithmType, CovarianceOptions, Problem, TinySolverError,
+    };
+
+    struct DiffFactor {
+        d: na::DVector<f64>,
+    }
+    impl<T: na::RealField> Factor<T> for DiffFactor {
+        fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+            params[1].clone() - params[0].clone() - self.d.clone().cast()
+        }
+    }
+
+    fn linear_problem() -> (Problem, HashMap<String, na::DVector<f64>>) {
+        let mut problem = Problem::new();
+        let prior = WeightedFactor::new(
+            PriorFactor {
+                v: na::dvector![1.0, 2.0],
+            },
+            na::DMatrix::from_diagonal(&na::dvector![2.0, 3.0]),
+        );
+        problem
+            .add_residual_block(2, &["x"], Box::new(prior), None)
+            .unwrap();
+        problem
+            .add_residual_block(
+                2,
+                &["x", "y"],
+                Box::new(DiffFactor {
+                    d: na::dvector![0.5, 0.5],
+                }),
+                None,
+            )
+            .unwrap();
+        let values = HashMap::from([
+            ("x".to_string(), na::dvector![1.0, 2.0]),
+            ("y".to_string(), na::dvector![1.5, 2.5]),
+        ]);
+        (problem, values)
+    }
+
+    fn expected_covariance() -> na::DMatrix<f64> {
+        // J = [[W, 0], [-I, I]] with W = diag(2, 3)
+        let mut jac = na::DMatrix::zeros(4, 4);
+        jac[(0, 0)] = 2.0;
+        jac[(1, 1)] = 3.0;
+        for i in 0..2 {
+            jac[(2 + i, i)] = -1.0;
+            jac[(2 + i, 2 + i)] = 1.0;
+        }
+        (jac.transpose() * jac).try_inverse().unwrap()
+    }
+
+    #[test]
+    fn covariance_blocks() {
+        let (problem, values) = linear_problem();
+        let expected = expected_covariance();
+        for algorithm_type in [
+            CovarianceAlgorithmType::SparseCholesky,
+            CovarianceAlgorithmType::SparseQR,
+            CovarianceAlgorithmType::DenseSvd,
+        ] {
+            let mut covariance = Covariance::new(CovarianceOptions {
+                algorithm_type,
+                ..Default::default()
+            });
+            covariance
+                .compute(&[("x", "x"), ("x", "y"), ("y", "y")], &problem, &values)
+                .unwrap();
+            let cov_xy = covariance.get_covariance_block("x", "y").unwrap();
+            assert!((cov_xy - expected.view((0, 2), (2, 2))).norm() < 1e-9);
+            let cov_yx = covariance.get_covariance_block("y", "x").unwrap();
+            assert!((cov_yx - expected.view((2, 0), (2, 2))).norm() < 1e-9);
+            let full = covariance.get_covariance_matrix(&["x", "y"]).unwrap();
+            assert!((full - &expected).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn covariance_with_fixed_variable() {
+        let (mut problem, values) = linear_problem();
+        problem.fix_variable("x", 0);
+        let mut covariance = Covariance::default();
+        covariance
+            .compute(&[("x", "x"), ("y", "y")], &problem, &values)
+            .unwrap();
+        let cov_x = covariance.get_covariance_block("x", "x").unwrap();
+        assert_eq!(cov_x[(0, 0)], 0.0);
+        assert_eq!(cov_x[(0, 1)], 0.0);
+        assert!(cov_x[(1, 1)] > 0.0);
+    }
+
+    #[test]
+    fn covariance_rank_deficient() {
+        // without the prior only x - y is observed
+        let mut problem = Problem::new();
+        problem
+            .add_residual_block(
+                2,
+                &["x", "y"],
+                Box::new(DiffFactor {
+                    d: na::dvector![0.5, 0.5],
+                }),
+                None,
+            )
+            .unwrap();
+        let (_, values) = linear_problem();
+        for algorithm_type in [
+            CovarianceAlgorithmType::SparseCholesky,
+            CovarianceAlgorithmType::SparseQR,
+        ] {
+            let mut covariance = Covariance::new(CovarianceOptions {
+                algorithm_type,
+                ..Default::default()
+            });
+            assert!(
+                covariance
+                    .compute(&[("x", "x")], &problem, &values)
+                    .is_err()
+            );
+        }
+
+        // the pseudo-inverse of J^T * J = [[I, -I], [-I, I]] is J^T * J / 4
+        let mut covariance = Covariance::new(CovarianceOptions {
+            algorithm_type: CovarianceAlgorithmType::DenseSvd,
+            ..Default::default()
+        });
+        covariance
+            .compute(&[("x", "x"), ("x", "y")], &problem, &values)
+            .unwrap();
+        let cov_x = covariance.get_covariance_block("x", "x").unwrap();
+        assert!((cov_x - na::DMatrix::identity(2, 2) * 0.25).norm() < 1e-9);
+        let cov_xy = covariance.get_covariance_block("x", "y").unwrap();
+        assert!((cov_xy + na::DMatrix::identity(2, 2) * 0.25).norm() < 1e-9);
+    }
+
+    #[test]
+    fn covariance_missing_variable() {
+        let (problem, values) = linear_problem();
+        let mut covariance = Covariance::default();
+        assert!(matches!(
+            covariance.compute(&[("x", "z")], &problem, &values),
+            Err(TinySolverError::UnknownVariable { .. })
+        ));
+        assert!(covariance.get_covariance_block("x", "z").is_none());
+    }
+}
@@ -1,7 +1,15 @@
 #[cfg(test)]
 mod tests {
+    use std::num::NonZero;
+    use std::sync::Arc;
+
     use nalgebra as na;
+    use num_dual::DualDVec64;
+    use tiny_solver::ResidualBlock;
     use tiny_solver::factors::*;
+    use tiny_solver::manifold::se3::SE3Manifold;
+    use tiny_solver::manifold::{AutoDiffManifold, Manifold};
+    use tiny_solver::parameter_block::ParameterBlock;
 
     #[test]
     fn prior_factor() {
@@ -56,7 +64,308 @@ mod tests {
 
         let params = [na::dvector![1.0, 2.0, 3.0], na::dvector![1.0, 2.0, 3.0]];
 
-        let residual = factor.residual_func(&params);
-        assert_eq!(residual, na::dvector![2.0, 3.0, 1.0]);
+        // the poses are equal, so the error is the measurement
+        let residual: na::DVector<f64> = factor.residual_func(&params);
+        assert!((residual - na::dvector![2.0, 3.0, 1.0]).norm() < 1e-12);
+
+        // the angle error wraps around pi
+        let factor = BetweenFactorSE2 {
+            dtheta: 0.0,
+            dx: 0.0,
+            dy: 0.0,
+        };
+        let params = [na::dvector![3.1, 0.0, 0.0], na::dvector![-3.1, 0.0, 0.0]];
+        let residual: na::DVector<f64> = factor.residual_func(&params);
+        assert!((residual[2] - (6.2 - 2.0 * std::f64::consts::PI)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn weighted_factor() {
+        let information = na::dmatrix![4.0, 0.0; 0.0, 9.0];
+        let factor = WeightedFactor::from_information(
+            PriorFactor {
+                v: na::dvector![3.0, 1.0],
+            },
+            information.clone(),
+        )
+        .unwrap();
+
+        let params = [na::dvector![1.0, 2.0]];
+        let residual: na::DVector<f64> = factor.residual_func(&params);
+        assert_eq!(residual, na::dvector![-4.0, 3.0]);
+
+        // |sqrt_info * r|^2 == r^T * info * r
+        let r = na::dvector![-2.0, 1.0];
+        let expected = (r.transpose() * information * &r)[(0, 0)];
+        assert!((residual.norm_squared() - expected).abs() < 1e-12);
+
+        let not_pd = na::dmatrix![1.0, 2.0; 2.0, 1.0];
+        assert!(WeightedFactor::from_information(PriorFactor { v: r }, not_pd).is_none());
+    }
+
+    #[test]
+    fn angle_axis_rotation() {
+        let point = na::Vector3::new(1.0, -2.0, 0.5);
+        for angle_axis in [
+            na::Vector3::new(0.3, -0.2, 0.9),
+            na::Vector3::new(1e-10, 0.0, -1e-10),
+        ] {
+            let expected = na::Rotation3::new(angle_axis) * point;
+            let rotated = angle_axis_rotate_point(&angle_axis, &point);
+            assert!((rotated - expected).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn reprojection_factor_bal() {
+        // identity rotation, the point is 2 in front of the camera (-z)
+        let camera = na::dvector![0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 100.0, 0.1, 0.01];
+        let point = na::dvector![0.4, -0.2, -1.0];
+        let factor = ReprojectionFactorBAL {
+            observed: na::Vector2::new(20.0, -10.0),
+        };
+        // p = [0.2, -0.1], r2 = 0.05, f * distortion = 100 * 1.005025
+        let residual: na::DVector<f64> = factor.residual_func(&[camera, point]);
+        assert!((residual[0] - 0.1005).abs() < 1e-10);
+        assert!((residual[1] + 0.05025).abs() < 1e-10);
+    }
+
+    #[test]
+    fn camera_models() {
+        let p_camera = na::Vector3::new(0.3, -0.4, 2.0);
+        let pinhole = na::dvector![400.0, 410.0, 320.0, 240.0];
+        let expected = CameraModel::Pinhole.project(&pinhole, &p_camera);
+        assert!((expected - na::Vector2::new(380.0, 158.0)).norm() < 1e-10);
+
+        // zero distortion reduces to the pinhole model
+        let mut brown_conrady = na::DVector::zeros(9);
+        brown_conrady.rows_mut(0, 4).copy_from(&pinhole);
+        let projected = CameraModel::BrownConrady.project(&brown_conrady, &p_camera);
+        assert!((projected - expected).norm() < 1e-10);
+        let mut unified = na::DVector::zeros(5);
+        unified.rows_mut(0, 4).copy_from(&pinhole);
+        let projected = CameraModel::UnifiedCamera.project(&unified, &p_camera);
+        assert!((projected - expected).norm() < 1e-10);
+
+        // Kannala-Brandt is equidistant, the radius on the normalized plane is theta
+        let mut kannala_brandt = na::DVector::zeros(8);
+        kannala_brandt.rows_mut(0, 4).copy_from(&pinhole);
+        let projected: na::Vector2<f64> =
+            CameraModel::KannalaBrandt.project(&kannala_brandt, &p_camera);
+        let r: f64 = 0.5;
+        let theta = r.atan2(2.0);
+        let normalized = na::Vector2::new(
+            (projected[0] - 320.0) / 400.0,
+            (projected[1] - 240.0) / 410.0,
+        );
+        assert!((normalized.norm() - theta).abs() < 1e-10);
+        let on_axis = CameraModel::KannalaBrandt.project(&kannala_brandt, &na::Vector3::z());
+        assert!((on_axis - na::Vector2::new(320.0, 240.0)).norm() < 1e-10);
+
+        assert_eq!(CameraModel::Pinhole.num_intrinsics(), pinhole.len());
+        assert_eq!(CameraModel::BrownConrady.num_intrinsics(), 9);
+        assert_eq!(CameraModel::KannalaBrandt.num_intrinsics(), 8);
+        assert_eq!(CameraModel::UnifiedCamera.num_intrinsics(), 5);
+    }
+
+    #[test]
+    fn reprojection_factor() {
+        let factor = ReprojectionFactor {
+            camera_model: CameraModel::Pinhole,
+            observed: na::Vector2::new(380.0, 158.0),
+        };
+        let intrinsics = na::dvector![400.0, 410.0, 320.0, 240.0];
+        // camera translated by [0, 0, 1] so the world point [0.3, -0.4, 1] is at z = 2
+        let pose = na::dvector![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let point = na::dvector![0.3, -0.4, 1.0];
+        let residual: na::DVector<f64> = factor.residual_func(&[intrinsics, pose, point]);
+        assert!(residual.norm() < 1e-10);
+    }
+
+    #[test]
+    fn analytic_jacobians() {
+        let factor = ReprojectionFactorBAL {
+            observed: na::Vector2::new(20.0, -10.0),
+        };
+        let params = [
+            na::dvector![0.1, -0.2, 0.3, 0.05, -0.1, -1.0, 100.0, 0.1, 0.01],
+            na::dvector![0.4, -0.2, -1.0],
+        ];
+        let comparison = compare_jacobians(&factor, &params);
+        assert_eq!(comparison.analytic.shape(), (2, 12));
+        assert!(
+            comparison.max_abs_error < 1e-9,
+            "{}",
+            comparison.max_abs_error
+        );
+        let residual: na::DVector<f64> = factor.residual_func(&params);
+        assert!((AnalyticFactor::residual(&factor, &params) - residual).norm() < 1e-12);
+
+        // small angle branch
+        let params = [
+            na::dvector![0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 100.0, 0.1, 0.01],
+            na::dvector![0.4, -0.2, -1.0],
+        ];
+        assert!(compare_jacobians(&factor, &params).max_abs_error < 1e-9);
+    }
+
+    #[test]
+    fn analytic_residual_block() {
+        let v = na::dvector![0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 3.0];
+        let axis = na::Vector3::new(0.2, -0.4, 0.6);
+        let q = na::UnitQuaternion::from_scaled_axis(axis);
+        let mut param = ParameterBlock::from_vec(na::dvector![q.i, q.j, q.k, q.w, 0.5, 0.1, -0.2]);
+        param.set_manifold(Arc::new(SE3Manifold));
+
+        let autodiff =
+            ResidualBlock::new(0, 7, 0, &[0], Box::new(PriorFactor { v: v.clone() }), None);
+        let analytic =
+            ResidualBlock::new(0, 7, 0, &[0], Box::new(Analytic(PriorFactor { v })), None);
+        let (expected_residual, expected_jacobian) = autodiff.residual_and_jacobian(&[&param]);
+        let (residual, jacobian) = analytic.residual_and_jacobian(&[&param]);
+        assert_eq!(jacobian.shape(), (7, 6));
+        assert!((residual - expected_residual).norm() < 1e-12);
+        assert!((jacobian - expected_jacobian).amax() < 1e-12);
+
+        // the first order expansion used for dual numbers
+        let dual_params = [param.params.map(|x| {
+            num_dual::DualDVec64::new(x, num_dual::Derivative::some(na::dvector![2.0 * x]))
+        })];
+        let dual = Analytic(PriorFactor {
+            v: na::DVector::zeros(7),
+        })
+        .residual_func_dual(&dual_params);
+        for (r, x) in dual.iter().zip(param.params.iter()) {
+            assert!((r.re - x).abs() < 1e-12);
+            assert!(
+                (r.eps.clone().unwrap_generic(na::Dyn(1), na::Const::<1>)[0] - 2.0 * x).abs()
+                    < 1e-12
+            );
+        }
+    }
+
+    #[test]
+    fn static_dual_jacobians() {
+        let factor = ReprojectionFactorBAL {
+            observed: na::Vector2::new(20.0, -10.0),
+        };
+        let camera = ParameterBlock::from_vec(na::dvector![
+            0.1, -0.2, 0.3, 0.05, -0.1, -1.0, 100.0, 0.1, 0.01
+        ]);
+        let point = ParameterBlock::from_vec(na::dvector![0.4, -0.2, -1.0]);
+        // opt-in, plain factors use DualDVec64
+        assert!(
+            factor
+                .tangent_residual_and_jacobian(&[&camera, &point])
+                .is_none()
+        );
+        let (residual, jacobian) = StaticDual(factor.clone())
+            .tangent_residual_and_jacobian(&[&camera, &point])
+            .unwrap();
+        let params = [camera.params.clone(), point.params.clone()];
+        let comparison = compare_jacobians(&factor, &params);
+        assert!((residual - AnalyticFactor::residual(&factor, &params)).norm() < 1e-12);
+        assert!((jacobian - comparison.analytic).amax() < 1e-9);
+
+        // on a manifold, compared to the chain rule of the analytic factor
+        let q = na::UnitQuaternion::from_scaled_axis(na::Vector3::new(0.2, -0.4, 0.6));
+        let mut pose = ParameterBlock::from_vec(na::dvector![q.i, q.j, q.k, q.w, 0.5, 0.1, -0.2]);
+        pose.set_manifold(Arc::new(SE3Manifold));
+        let prior = PriorFactor {
+            v: na::dvector![0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 3.0],
+        };
+        let (_, jacobian) = StaticDual(prior.clone())
+            .tangent_residual_and_jacobian(&[&pose])
+            .unwrap();
+        let analytic = ResidualBlock::new(0, 7, 0, &[0], Box::new(Analytic(prior.clone())), None);
+        let (_, expected) = analytic.residual_and_jacobian(&[&pose]);
+        assert_eq!(jacobian.shape(), (7, 6));
+        assert!((jacobian - expected).amax() < 1e-12);
+
+        // too large for the static dual numbers
+        let large = ParameterBlock::from_vec(na::DVector::zeros(17));
+        let prior = PriorFactor {
+            v: na::DVector::zeros(17),
+        };
+        let prior = StaticDual(prior);
+        assert!(prior.tangent_residual_and_jacobian(&[&large]).is_none());
+        let block = ResidualBlock::new(0, 17, 0, &[0], Box::new(prior), None);
+        let (_, jacobian) = block.residual_and_jacobian(&[&large]);
+        assert_eq!(jacobian, na::DMatrix::identity(17, 17));
+    }
+
+    /// A manifold and a factor implemented only for `f64` and `DualDVec64`.
+    struct OffsetManifold;
+    impl<T: na::RealField> AutoDiffManifold<T> for OffsetManifold
+    where
+        T: From<f64>,
+    {
+        fn plus(&self, x: na::DVectorView<T>, delta: na::DVectorView<T>) -> na::DVector<T> {
+            x + delta * T::from(2.0)
+        }
+        fn minus(&self, y: na::DVectorView<T>, x: na::DVectorView<T>) -> na::DVector<T> {
+            (y - x) / T::from(2.0)
+        }
+    }
+    impl Manifold for OffsetManifold {
+        fn tangent_size(&self) -> NonZero<usize> {
+            NonZero::new(2).unwrap()
+        }
+        fn ambient_size(&self) -> NonZero<usize> {
+            NonZero::new(2).unwrap()
+        }
+    }
+    struct DynamicOnlyPrior;
+    impl Factor<f64> for DynamicOnlyPrior {
+        fn residual_func(&self, params: &[na::DVector<f64>]) -> na::DVector<f64> {
+            params[0].clone()
+        }
+    }
+    impl Factor<DualDVec64> for DynamicOnlyPrior {
+        fn residual_func(&self, params: &[na::DVector<DualDVec64>]) -> na::DVector<DualDVec64> {
+            params[0].clone()
+        }
+    }
+
+    #[test]
+    fn static_dual_is_opt_in() {
+        let mut param = ParameterBlock::from_vec(na::dvector![1.0, 2.0]);
+        param.set_manifold(Arc::new(OffsetManifold));
+        let expected = na::DMatrix::identity(2, 2) * 2.0;
+
+        let block = ResidualBlock::new(0, 2, 0, &[0], Box::new(DynamicOnlyPrior), None);
+        let (_, jacobian) = block.residual_and_jacobian(&[&param]);
+        assert_eq!(jacobian, expected);
+
+        // the manifold does not support the static dual numbers
+        let prior = StaticDual(PriorFactor {
+            v: na::dvector![0.0, 0.0],
+        });
+        assert!(prior.tangent_residual_and_jacobian(&[&param]).is_none());
+        let block = ResidualBlock::new(0, 2, 0, &[0], Box::new(prior), None);
+        let (_, jacobian) = block.residual_and_jacobian(&[&param]);
+        assert_eq!(jacobian, expected);
+        assert_eq!(param.plus_jacobian(), expected);
+    }
+
+    #[test]
+    fn factor_macro() {
+        let custom = tiny_solver::factor!(|params| {
+            na::dvector![params[0][0].clone() * params[1][1].clone()]
+        });
+        let params = [na::dvector![2.0], na::dvector![0.0, 3.0]];
+        let residual: na::DVector<f64> = custom.residual_func(&params);
+        assert_eq!(residual, na::dvector![6.0]);
+
+        let observed = na::dvector![1.0, 2.0];
+        let scale = 2.0;
+        let prior = tiny_solver::factor!([v: na::DVector<f64> = observed.clone(), scale: f64 = scale] |params| {
+            (params[0].clone() - v.clone().cast::<T>()) * T::from_f64(*scale).unwrap()
+        });
+        let block = ResidualBlock::new(0, 2, 0, &[0], Box::new(prior), None);
+        let param = ParameterBlock::from_vec(na::dvector![2.0, 4.0]);
+        let (residual, jacobian) = block.residual_and_jacobian(&[&param]);
+        assert_eq!(residual, na::dvector![2.0, 4.0]);
+        assert_eq!(jacobian, na::DMatrix::identity(2, 2) * 2.0);
     }
 }
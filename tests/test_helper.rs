@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use nalgebra as na;
+    use tiny_solver::helper::*;
+    use tiny_solver::sparse::LinearSolverType;
+    use tiny_solver::{LevenbergMarquardtOptimizer, Optimizer, OptimizerOptions, TinySolverError};
+
+    #[test]
+    fn upper_triangular_information() {
+        let info = upper_triangular_to_symmetric(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3);
+        assert_eq!(
+            info,
+            na::dmatrix![
+                1.0, 2.0, 3.0;
+                2.0, 4.0, 5.0;
+                3.0, 5.0, 6.0
+            ]
+        );
+    }
+
+    #[test]
+    fn read_g2o_se2() {
+        let (problem, init_values) = read_g2o("tests/data/input_M3500_g2o.g2o").unwrap();
+        assert_eq!(init_values.len(), 3500);
+        // every edge is 3 dimensional, x0 is fixed instead of a prior
+        let num_edges = std::fs::read_to_string("tests/data/input_M3500_g2o.g2o")
+            .unwrap()
+            .lines()
+            .filter(|l| l.starts_with("EDGE_SE2"))
+            .count();
+        assert_eq!(problem.total_residual_dimension, 3 * num_edges);
+        assert_eq!(problem.fixed_variable_indexes["x0"].len(), 3);
+    }
+
+    #[test]
+    fn read_bal_and_optimize() {
+        let (mut problem, init_values) = read_bal("tests/data/bal_synthetic.txt").unwrap();
+        assert_eq!(init_values.len(), 4 + 30);
+        assert_eq!(init_values["c0"].len(), 9);
+        assert_eq!(init_values["p0"].len(), 3);
+        assert_eq!(problem.total_residual_dimension, 2 * 120);
+
+        // the first camera is the unperturbed reference
+        for i in 0..9 {
+            problem.fix_variable("c0", i);
+        }
+        let options = OptimizerOptions {
+            linear_solver_type: LinearSolverType::SparseSchur,
+            ..Default::default()
+        };
+        let optimizer = LevenbergMarquardtOptimizer::default();
+        let (result, summary) =
+            optimizer.optimize_with_summary(&problem, &init_values, Some(options));
+        assert!(summary.initial_cost > 1.0);
+        assert!(summary.final_cost < 1e-8, "{}", summary);
+        let result = result.unwrap();
+        assert_eq!(result["c0"], init_values["c0"]);
+    }
+
+    #[test]
+    fn read_g2o_fix_and_unknown_tags() {
+        let path = std::env::temp_dir().join("tiny_solver_fix.g2o");
+        std::fs::write(
+            &path,
+            "PARAMS_SE2OFFSET 0 0.0 0.0 0.0\n\
+             VERTEX_SE2 0 0.0 0.0 0.0\n\
+             VERTEX_SE2 1 1.0 0.0 0.0\n\
+             VERTEX_XY 2 1.0 1.0\n\
+             EDGE_SE2 0 1 1.0 0.0 0.0 1.0 0.0 0.0 1.0 0.0 1.0\n\
+             FIX 1\n",
+        )
+        .unwrap();
+        let result = read_g2o(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let (problem, init_values) = result.unwrap();
+        assert_eq!(init_values.len(), 2);
+        // x0 is not fixed since x1 is
+        assert_eq!(problem.total_residual_dimension, 3);
+        assert_eq!(
+            problem.fixed_variable_indexes["x1"],
+            [0, 1, 2].into_iter().collect()
+        );
+        assert!(!problem.fixed_variable_indexes.contains_key("x0"));
+    }
+
+    #[test]
+    fn read_g2o_parse_error() {
+        let path = std::env::temp_dir().join("tiny_solver_parse_error.g2o");
+        std::fs::write(
+            &path,
+            "VERTEX_SE2 0 0.0 0.0 0.0\n\nVERTEX_SE2 1 1.0 abc 0.0\n",
+        )
+        .unwrap();
+        let result = read_g2o(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(TinySolverError::ParseError { line, .. }) => assert_eq!(line, 3),
+            _ => panic!("expected a parse error"),
+        }
+
+        // the information matrix of an SE2 edge has 6 values
+        std::fs::write(
+            &path,
+            "VERTEX_SE2 0 0.0 0.0 0.0\nVERTEX_SE2 1 1.0 0.0 0.0\nEDGE_SE2 0 1 1.0 0.0 0.0 1.0 0.0 0.0 1.0\n",
+        )
+        .unwrap();
+        let result = read_g2o(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(TinySolverError::ParseError { line: 3, .. })
+        ));
+        assert!(matches!(
+            read_g2o("tests/data/does_not_exist.g2o"),
+            Err(TinySolverError::Io(_))
+        ));
+    }
+}
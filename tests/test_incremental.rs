@@ -0,0 +1,299 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use nalgebra as na;
+    use tiny_solver::factors::{BetweenFactorSE2, Factor, PriorFactor};
+    use tiny_solver::manifold::se2::SE2Manifold;
+    use tiny_solver::{
+        GaussNewtonOptimizer, IncrementalOptimizer, IncrementalOptions, Optimizer, Problem,
+        TinySolverError,
+    };
+
+    struct OdometryFactor {
+        d: na::DVector<f64>,
+    }
+    impl<T: na::RealField> Factor<T> for OdometryFactor {
+        fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+            &params[1] - &params[0] - self.d.clone().cast()
+        }
+    }
+
+    struct PriorOnFirst {
+        v: f64,
+    }
+    impl<T: na::RealField> Factor<T> for PriorOnFirst {
+        fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+            na::dvector![params[0][0].clone() - T::from_f64(self.v).unwrap()]
+        }
+    }
+
+    fn max_difference(
+        a: &HashMap<String, na::DVector<f64>>,
+        b: &HashMap<String, na::DVector<f64>>,
+    ) -> f64 {
+        assert_eq!(a.len(), b.len());
+        a.iter()
+            .map(|(key, value)| (value - &b[key]).amax())
+            .fold(0.0, f64::max)
+    }
+
+    fn batch_solution(
+        problem: &Problem,
+        values: &HashMap<String, na::DVector<f64>>,
+    ) -> HashMap<String, na::DVector<f64>> {
+        GaussNewtonOptimizer::new()
+            .optimize(problem, values, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn linear_chain_matches_batch() {
+        let options = IncrementalOptions {
+            wildfire_threshold: 0.0,
+            ..Default::default()
+        };
+        let mut isam = IncrementalOptimizer::new(Problem::new(), options);
+        isam.add_residual_block(
+            2,
+            &["x0"],
+            Box::new(PriorFactor {
+                v: na::dvector![0.0, 0.0],
+            }),
+            None,
+        )
+        .unwrap();
+        let mut values = HashMap::from([("x0".to_string(), na::dvector![0.1, -0.2])]);
+        isam.update(&values).unwrap();
+
+        for i in 1..20 {
+            let key = format!("x{}", i);
+            let d = na::dvector![1.0, 0.1 * i as f64];
+            isam.add_residual_block(
+                2,
+                &[format!("x{}", i - 1), key.clone()],
+                Box::new(OdometryFactor { d: d.clone() }),
+                None,
+            )
+            .unwrap();
+            // loop closures to the start every few steps
+            if i % 5 == 0 {
+                isam.add_residual_block(
+                    2,
+                    &["x0".to_string(), key.clone()],
+                    Box::new(OdometryFactor {
+                        d: na::dvector![i as f64 + 0.3, 0.0],
+                    }),
+                    None,
+                )
+                .unwrap();
+            }
+            // predicted from the odometry as a front end would
+            let prediction = &isam.estimate()[&format!("x{}", i - 1)] + &d;
+            let new_values = HashMap::from([(key, prediction)]);
+            values.extend(new_values.clone());
+            let summary = isam.update(&new_values).unwrap();
+            assert_eq!(summary.num_new_residual_blocks, 1 + usize::from(i % 5 == 0));
+            if i % 5 > 1 {
+                // odometry only touches the top of the tree, away from the
+                // relinearization after a loop closure
+                assert_eq!(summary.num_relinearized_variables, 0);
+                assert!(summary.num_reeliminated_variables < 4, "{:?}", summary);
+            }
+
+            let expected = batch_solution(isam.problem(), &values);
+            assert!(max_difference(&isam.estimate(), &expected) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn se2_pose_graph() {
+        let options = IncrementalOptions {
+            relinearize_threshold: 1e-3,
+            wildfire_threshold: 0.0,
+        };
+        let mut isam = IncrementalOptimizer::new(Problem::new(), options);
+        let mut values = HashMap::new();
+        let (step, turn) = (1.0, 2.0 * std::f64::consts::PI / 12.0);
+        for i in 0..24 {
+            let key = format!("x{}", i);
+            isam.set_variable_manifold(key.as_str(), Arc::new(SE2Manifold));
+            if i == 0 {
+                isam.add_residual_block(
+                    3,
+                    &["x0"],
+                    Box::new(PriorFactor {
+                        v: na::dvector![0.0, 0.0, 0.0],
+                    }),
+                    None,
+                )
+                .unwrap();
+            } else {
+                isam.add_residual_block(
+                    3,
+                    &[format!("x{}", i - 1), key.clone()],
+                    Box::new(BetweenFactorSE2 {
+                        dx: step,
+                        dy: 0.0,
+                        dtheta: turn,
+                    }),
+                    None,
+                )
+                .unwrap();
+            }
+            if i >= 12 {
+                // loop closure to the same place one round earlier
+                isam.add_residual_block(
+                    3,
+                    &[format!("x{}", i - 12), key.clone()],
+                    Box::new(BetweenFactorSE2 {
+                        dx: 0.01,
+                        dy: 0.0,
+                        dtheta: 0.0,
+                    }),
+                    None,
+                )
+                .unwrap();
+            }
+            // dead reckoning with a drifting heading
+            let theta = 1.05 * turn * i as f64;
+            let value = na::dvector![theta, theta.cos() * i as f64 * 0.2, theta.sin()];
+            let new_values = HashMap::from([(key, value)]);
+            values.extend(new_values.clone());
+            isam.update(&new_values).unwrap();
+        }
+        for _ in 0..5 {
+            let summary = isam.update(&HashMap::new()).unwrap();
+            assert_eq!(summary.num_new_residual_blocks, 0);
+        }
+        let expected = batch_solution(isam.problem(), &values);
+        // the steps below the relinearization threshold are only linear
+        assert!(max_difference(&isam.estimate(), &expected) < 1e-5);
+    }
+
+    #[test]
+    fn remove_residual_block() {
+        let options = IncrementalOptions {
+            wildfire_threshold: 0.0,
+            ..Default::default()
+        };
+        let mut problem = Problem::new();
+        problem
+            .add_residual_block(
+                1,
+                &["x0"],
+                Box::new(PriorFactor {
+                    v: na::dvector![0.0],
+                }),
+                None,
+            )
+            .unwrap();
+        let mut values = HashMap::from([("x0".to_string(), na::dvector![0.0])]);
+        let mut outlier = None;
+        for i in 1..6 {
+            let key = format!("x{}", i);
+            problem
+                .add_residual_block(
+                    1,
+                    &[format!("x{}", i - 1), key.clone()],
+                    Box::new(OdometryFactor {
+                        d: na::dvector![1.0],
+                    }),
+                    None,
+                )
+                .unwrap();
+            if i == 3 {
+                outlier = Some(
+                    problem
+                        .add_residual_block(
+                            1,
+                            &["x0", "x3"],
+                            Box::new(OdometryFactor {
+                                d: na::dvector![10.0],
+                            }),
+                            None,
+                        )
+                        .unwrap(),
+                );
+            }
+            values.insert(key, na::dvector![i as f64]);
+        }
+        let mut isam = IncrementalOptimizer::new(problem, options);
+        isam.update(&values).unwrap();
+        assert!((isam.estimate()["x5"][0] - 5.0).abs() > 0.5);
+
+        assert!(isam.remove_residual_block(outlier.unwrap()));
+        assert!(!isam.remove_residual_block(outlier.unwrap()));
+        let summary = isam.update(&HashMap::new()).unwrap();
+        assert_eq!(summary.num_removed_residual_blocks, 1);
+        let expected = batch_solution(isam.problem(), &values);
+        assert!(max_difference(&isam.estimate(), &expected) < 1e-6);
+        assert!((isam.estimate()["x5"][0] - 5.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn failed_update_changes_nothing() {
+        let mut isam = IncrementalOptimizer::new(Problem::new(), IncrementalOptions::default());
+        let prior_id = isam
+            .add_residual_block(
+                1,
+                &["x0"],
+                Box::new(PriorFactor {
+                    v: na::dvector![1.0],
+                }),
+                None,
+            )
+            .unwrap();
+        isam.add_residual_block(
+            1,
+            &["x0", "x1"],
+            Box::new(OdometryFactor {
+                d: na::dvector![1.0],
+            }),
+            None,
+        )
+        .unwrap();
+        let x0 = HashMap::from([("x0".to_string(), na::dvector![0.0])]);
+        assert!(matches!(
+            isam.update(&x0),
+            Err(TinySolverError::MissingVariable { .. })
+        ));
+        assert!(isam.estimate().is_empty());
+
+        let mut values = x0.clone();
+        values.insert("x1".to_string(), na::dvector![0.0]);
+        let summary = isam.update(&values).unwrap();
+        assert_eq!(summary.num_new_residual_blocks, 2);
+        assert!((isam.estimate()["x1"][0] - 2.0).abs() < 1e-9);
+
+        // the residual does not depend on x3
+        let block_id = isam
+            .add_residual_block(1, &["x1", "x3"], Box::new(PriorOnFirst { v: 2.0 }), None)
+            .unwrap();
+        let x3 = HashMap::from([("x3".to_string(), na::dvector![0.0])]);
+        assert!(matches!(
+            isam.update(&x3),
+            Err(TinySolverError::LinearSolverFailed(_))
+        ));
+        assert_eq!(isam.estimate().len(), 2);
+
+        assert!(isam.remove_residual_block(block_id));
+        let summary = isam.update(&HashMap::new()).unwrap();
+        assert_eq!(summary.num_new_residual_blocks, 0);
+        assert!((isam.estimate()["x1"][0] - 2.0).abs() < 1e-9);
+
+        // removing the only prior leaves the chain unconstrained, restoring
+        // it gets the optimizer going again
+        assert!(isam.remove_residual_block(prior_id));
+        assert!(matches!(
+            isam.update(&HashMap::new()),
+            Err(TinySolverError::LinearSolverFailed(_))
+        ));
+        assert!(isam.restore_residual_block(prior_id));
+        assert!(!isam.restore_residual_block(prior_id));
+        assert!(isam.update(&HashMap::new()).is_ok());
+        assert_eq!(isam.problem().total_residual_dimension, 2);
+        assert!((isam.estimate()["x1"][0] - 2.0).abs() < 1e-9);
+    }
+}
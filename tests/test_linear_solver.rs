@@ -0,0 +1,159 @@
+#[cfg(test)]
+mod tests {
+    use faer::sparse::{SparseColMat, Triplet};
+    use tiny_solver::linear::*;
+    use tiny_solver::sparse::SparseLinearSolver;
+
+    const NUM_CAMERAS: usize = 3;
+    const CAMERA_SIZE: usize = 4;
+    const NUM_POINTS: usize = 10;
+    const POINT_SIZE: usize = 2;
+
+    type BaLikeProblem = (
+        faer::Mat<f64>,
+        SparseColMat<usize, f64>,
+        Vec<(usize, usize)>,
+    );
+
+    /// Bundle adjustment like jacobian, every point is seen by two cameras and
+    /// the point columns are interleaved with the camera columns.
+    fn ba_like_problem() -> BaLikeProblem {
+        let mut col = 0;
+        let mut camera_cols = Vec::new();
+        let mut point_cols = Vec::new();
+        for i in 0..NUM_CAMERAS.max(NUM_POINTS) {
+            if i < NUM_CAMERAS {
+                camera_cols.push(col);
+                col += CAMERA_SIZE;
+            }
+            if i < NUM_POINTS {
+                point_cols.push(col);
+                col += POINT_SIZE;
+            }
+        }
+        let num_cols = col;
+        let mut triplets = Vec::new();
+        let mut row = 0;
+        let value = |i: usize, j: usize| ((i * 7 + j * 13) % 17) as f64 / 17.0 - 0.4;
+        for (p, &point_col) in point_cols.iter().enumerate() {
+            for c in [p % NUM_CAMERAS, (p + 1) % NUM_CAMERAS] {
+                for r in 0..2 {
+                    for k in 0..POINT_SIZE {
+                        triplets.push(Triplet::new(
+                            row + r,
+                            point_col + k,
+                            value(row + r, k) + 1.0,
+                        ));
+                    }
+                    for k in 0..CAMERA_SIZE {
+                        let v = value(row + r, k + 5);
+                        triplets.push(Triplet::new(row + r, camera_cols[c] + k, v));
+                    }
+                }
+                row += 2;
+            }
+        }
+        // priors to make the system full rank
+        for i in 0..num_cols {
+            triplets.push(Triplet::new(row, i, 0.5));
+            row += 1;
+        }
+        let jac = SparseColMat::try_new_from_triplets(row, num_cols, &triplets).unwrap();
+        let residuals = faer::Mat::from_fn(row, 1, |r, _| value(r, 3));
+        let eliminate_blocks = point_cols.iter().map(|&c| (c, POINT_SIZE)).collect();
+        (residuals, jac, eliminate_blocks)
+    }
+
+    fn assert_close(a: &faer::Mat<f64>, b: &faer::Mat<f64>) {
+        assert_eq!(a.nrows(), b.nrows());
+        for r in 0..a.nrows() {
+            assert!((a[(r, 0)] - b[(r, 0)]).abs() < 1e-8, "row {}", r);
+        }
+    }
+
+    #[test]
+    fn schur_complement_matches_cholesky() {
+        let (residuals, jac, eliminate_blocks) = ba_like_problem();
+        let expected = SparseCholeskySolver::new().solve(&residuals, &jac).unwrap();
+
+        let mut dense = SchurComplementSolver::new_dense(eliminate_blocks.clone());
+        assert_close(&dense.solve(&residuals, &jac).unwrap(), &expected);
+
+        let mut sparse = SchurComplementSolver::new_sparse(eliminate_blocks);
+        assert_close(&sparse.solve(&residuals, &jac).unwrap(), &expected);
+        // the symbolic factorization is reused
+        assert_close(&sparse.solve(&residuals, &jac).unwrap(), &expected);
+    }
+
+    #[test]
+    fn schur_complement_rejects_coupled_blocks() {
+        let (residuals, jac, _) = ba_like_problem();
+        // the first camera and the first point share residuals
+        let mut solver = SchurComplementSolver::new_sparse(vec![(0, CAMERA_SIZE + POINT_SIZE)]);
+        assert!(solver.solve(&residuals, &jac).is_ok());
+        let mut solver =
+            SchurComplementSolver::new_sparse(vec![(0, CAMERA_SIZE), (CAMERA_SIZE, POINT_SIZE)]);
+        assert!(solver.solve(&residuals, &jac).is_err());
+    }
+
+    /// Every column block of `ba_like_problem`, the cameras fill the gaps between the points.
+    fn parameter_blocks(eliminate_blocks: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let mut blocks = Vec::new();
+        let mut col = 0;
+        for &(start, size) in eliminate_blocks {
+            if col < start {
+                blocks.push((col, start - col));
+            }
+            blocks.push((start, size));
+            col = start + size;
+        }
+        blocks
+    }
+
+    #[test]
+    fn conjugate_gradient_matches_cholesky() {
+        let (residuals, jac, eliminate_blocks) = ba_like_problem();
+        let expected = SparseCholeskySolver::new().solve(&residuals, &jac).unwrap();
+        let blocks = parameter_blocks(&eliminate_blocks);
+        assert_eq!(
+            blocks.iter().map(|(_, size)| size).sum::<usize>(),
+            jac.ncols()
+        );
+
+        for preconditioner_type in [
+            PreconditionerType::Identity,
+            PreconditionerType::Jacobi,
+            PreconditionerType::BlockJacobi,
+            PreconditionerType::SchurJacobi,
+        ] {
+            let mut solver = ConjugateGradientSolver::new(
+                preconditioner_type,
+                blocks.clone(),
+                eliminate_blocks.clone(),
+            );
+            solver.set_relative_tolerance(1e-14);
+            assert_close(&solver.solve(&residuals, &jac).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn conjugate_gradient_max_iterations() {
+        let (residuals, jac, eliminate_blocks) = ba_like_problem();
+        let expected = SparseCholeskySolver::new().solve(&residuals, &jac).unwrap();
+        let mut solver = ConjugateGradientSolver::new(
+            PreconditionerType::Identity,
+            parameter_blocks(&eliminate_blocks),
+            eliminate_blocks,
+        )
+        .with_max_iterations(1);
+        solver.set_relative_tolerance(1e-14);
+        let dx = solver.solve(&residuals, &jac).unwrap();
+        // a single steepest descent step is not the solution
+        assert!(
+            (0..dx.nrows())
+                .map(|r| (dx[(r, 0)] - expected[(r, 0)]).abs())
+                .fold(0.0, f64::max)
+                > 1e-6
+        );
+    }
+}
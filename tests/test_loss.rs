@@ -8,7 +8,7 @@ mod tests {
         let tolerance = 100.0;
         let asymptote = tolerance * f64::consts::PI / 2.0;
 
-        let arctan_loss = ArctanLoss::new(tolerance);
+        let arctan_loss = ArctanLoss::new(tolerance).unwrap();
 
         let rho1 = arctan_loss.evaluate(1.0);
         let rho2 = arctan_loss.evaluate(30.0);
@@ -22,4 +22,148 @@ mod tests {
         // Test that scales largely above the tolerance are asymptotically bounded
         assert!(arctan_loss.evaluate(tolerance * tolerance * tolerance)[0] < asymptote);
     }
+
+    #[test]
+    fn invalid_loss_parameter() {
+        assert!(HuberLoss::new(1.0).is_ok());
+        assert!(matches!(
+            HuberLoss::new(0.0),
+            Err(tiny_solver::TinySolverError::InvalidLossParameter(_))
+        ));
+        assert!(CauchyLoss::new(-1.0).is_err());
+        assert!(ArctanLoss::new(0.0).is_err());
+    }
+
+    #[test]
+    fn cauchy_loss_values() {
+        // rho(s) = a^2 * ln(1 + s / a^2), it used log2 before 0.19
+        let cauchy = CauchyLoss::new(2.0).unwrap();
+        let [rho, rho1, rho2] = cauchy.evaluate(4.0);
+        assert!((rho - 4.0 * 2.0_f64.ln()).abs() < 1e-12);
+        assert!((rho1 - 0.5).abs() < 1e-12);
+        assert!((rho2 + 1.0 / 16.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn loss_derivative_stays_positive() {
+        // rho' is clamped to the smallest positive double, not f64::MIN
+        let losses: Vec<Box<dyn Loss>> = vec![
+            Box::new(HuberLoss::new(1.0).unwrap()),
+            Box::new(CauchyLoss::new(1.0).unwrap()),
+            Box::new(ArctanLoss::new(1.0).unwrap()),
+        ];
+        for loss in &losses {
+            assert_eq!(loss.evaluate(f64::INFINITY)[1], f64::MIN_POSITIVE);
+        }
+    }
+
+    /// Checks rho' and rho'' against central differences of rho and rho'.
+    fn check_derivatives(loss: &dyn Loss, samples: &[f64]) {
+        for &s in samples {
+            let h = 1e-6 * s.max(1.0);
+            let [rho, rho1, rho2] = loss.evaluate(s);
+            let plus = loss.evaluate(s + h);
+            let minus = loss.evaluate(s - h);
+            assert!(rho.is_finite());
+            let numeric_rho1 = (plus[0] - minus[0]) / (2.0 * h);
+            let numeric_rho2 = (plus[1] - minus[1]) / (2.0 * h);
+            assert!(
+                (numeric_rho1 - rho1).abs() < 1e-6 * rho1.abs().max(1.0),
+                "rho' at {s}: {rho1} vs {numeric_rho1}"
+            );
+            assert!(
+                (numeric_rho2 - rho2).abs() < 1e-5 * rho2.abs().max(1.0),
+                "rho'' at {s}: {rho2} vs {numeric_rho2}"
+            );
+        }
+    }
+
+    /// Small residuals are not affected by a robust loss.
+    fn check_quadratic_at_zero(loss: &dyn Loss) {
+        let [rho, rho1, _] = loss.evaluate(0.0);
+        assert!(rho.abs() < 1e-12);
+        assert!((rho1 - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn loss_derivatives() {
+        // away from the kinks of Huber, Tukey and DCS at s = 4
+        let samples = [0.01, 0.5, 1.0, 3.0, 5.0, 10.0, 100.0];
+        let losses: Vec<Box<dyn Loss>> = vec![
+            Box::new(HuberLoss::new(2.0).unwrap()),
+            Box::new(CauchyLoss::new(2.0).unwrap()),
+            Box::new(ArctanLoss::new(2.0).unwrap()),
+            Box::new(SoftLOneLoss::new(2.0).unwrap()),
+            Box::new(TukeyLoss::new(2.0).unwrap()),
+            Box::new(GemanMcClureLoss::new(2.0).unwrap()),
+            Box::new(WelschLoss::new(2.0).unwrap()),
+            Box::new(FairLoss::new(2.0).unwrap()),
+            Box::new(DcsLoss::new(4.0).unwrap()),
+            Box::new(BarronLoss::new(1.5, 2.0).unwrap()),
+            Box::new(BarronLoss::new(-3.0, 2.0).unwrap()),
+            Box::new(BarronLoss::new(0.0, 2.0).unwrap()),
+            Box::new(BarronLoss::new(f64::NEG_INFINITY, 2.0).unwrap()),
+            Box::new(ScaledLoss::new(Some(Box::new(CauchyLoss::new(2.0).unwrap())), 3.0).unwrap()),
+            Box::new(ScaledLoss::new(None, 3.0).unwrap()),
+            Box::new(ComposedLoss::new(
+                Box::new(CauchyLoss::new(3.0).unwrap()),
+                Box::new(SoftLOneLoss::new(2.0).unwrap()),
+            )),
+            Box::new(TolerantLoss::new(3.0, 0.5).unwrap()),
+        ];
+        for loss in &losses {
+            check_derivatives(loss.as_ref(), &samples);
+        }
+        // all but the scaled and the tolerant losses
+        for loss in losses[..13].iter().chain(&losses[15..16]) {
+            check_quadratic_at_zero(loss.as_ref());
+        }
+        // the tolerant loss is zero at zero but flat
+        assert!(TolerantLoss::new(3.0, 0.5).unwrap().evaluate(0.0)[0].abs() < 1e-12);
+        assert_eq!(TolerantLoss::new(3.0, 0.5).unwrap().evaluate(1e3)[1], 1.0);
+    }
+
+    #[test]
+    fn bounded_losses() {
+        let tukey = TukeyLoss::new(2.0).unwrap();
+        assert_eq!(tukey.evaluate(100.0), [4.0 / 3.0, 0.0, 0.0]);
+        assert!(GemanMcClureLoss::new(2.0).unwrap().evaluate(1e12)[0] < 4.0);
+        assert!(WelschLoss::new(2.0).unwrap().evaluate(1e12)[0] <= 4.0);
+        // DCS scales the residual by min(1, 2 * phi / (phi + s))
+        let dcs = DcsLoss::new(1.0).unwrap();
+        assert_eq!(dcs.evaluate(0.5), [0.5, 1.0, 0.0]);
+        let scaling = 2.0 / (1.0 + 3.0);
+        assert!((dcs.evaluate(3.0)[1] - scaling * scaling).abs() < 1e-12);
+    }
+
+    #[test]
+    fn barron_special_cases() {
+        let c = 1.5_f64;
+        let cases: Vec<(f64, Box<dyn Loss>)> = vec![
+            (1.0, Box::new(SoftLOneLoss::new(c).unwrap())),
+            (0.0, Box::new(CauchyLoss::new(2.0_f64.sqrt() * c).unwrap())),
+            (-2.0, Box::new(GemanMcClureLoss::new(2.0 * c).unwrap())),
+            (
+                f64::NEG_INFINITY,
+                Box::new(WelschLoss::new(2.0_f64.sqrt() * c).unwrap()),
+            ),
+        ];
+        for (alpha, loss) in cases {
+            let barron = BarronLoss::new(alpha, c).unwrap();
+            for s in [0.1, 1.0, 7.0, 50.0] {
+                let expected = loss.evaluate(s);
+                let actual = barron.evaluate(s);
+                for i in 0..3 {
+                    assert!((expected[i] - actual[i]).abs() < 1e-9 * expected[i].abs().max(1.0));
+                }
+            }
+        }
+        assert_eq!(
+            BarronLoss::new(2.0, c).unwrap().evaluate(3.0),
+            [3.0, 1.0, 0.0]
+        );
+        assert!(BarronLoss::new(f64::NAN, c).is_err());
+        assert!(TolerantLoss::new(-1.0, 1.0).is_err());
+        assert!(ScaledLoss::new(None, 0.0).is_err());
+    }
 }
@@ -1,9 +1,25 @@
 #[cfg(test)]
 mod tests {
     use nalgebra as na;
+    use std::collections::HashMap;
     use std::f64::consts::PI;
+    use std::sync::Arc;
 
-    use tiny_solver::manifold::so3::SO3;
+    use tiny_solver::factors::PriorFactor;
+    use tiny_solver::manifold::euclidean::EuclideanManifold;
+    use tiny_solver::manifold::line::LineManifold;
+    use tiny_solver::manifold::product::ProductManifold;
+    use tiny_solver::manifold::se2::{SE2, SE2Manifold};
+    use tiny_solver::manifold::se3::{SE3, SE3Manifold};
+    use tiny_solver::manifold::se23::{SE23, SE23Manifold};
+    use tiny_solver::manifold::sim3::{Sim3, Sim3Manifold};
+    use tiny_solver::manifold::so2::{SO2, SO2Manifold};
+    use tiny_solver::manifold::so3::{QuaternionManifold, SO3};
+    use tiny_solver::manifold::sphere::SphereManifold;
+    use tiny_solver::manifold::subset::SubsetManifold;
+    use tiny_solver::manifold::{AutoDiffManifold, Manifold};
+    use tiny_solver::parameter_block::ParameterBlock;
+    use tiny_solver::{LevenbergMarquardtOptimizer, Optimizer, Problem};
 
     fn equal_to_na(so3: &SO3<f64>) -> bool {
         let q = so3.to_vec();
@@ -23,4 +39,419 @@ mod tests {
             assert!(equal_to_na(&r));
         }
     }
+
+    #[test]
+    fn test_se2() {
+        for _ in 0..1000 {
+            let xi = na::dvector![
+                (rand::random::<f64>() * 2.0 - 1.0) * PI,
+                rand::random::<f64>() * 10.0 - 5.0,
+                rand::random::<f64>() * 10.0 - 5.0
+            ];
+            let pose = SE2::exp(xi.as_view());
+            assert!((pose.log() - &xi).norm() < 1e-9);
+
+            let na_pose = na::Isometry2::new(pose.xy, pose.theta);
+            let p = na::Vector2::new(0.3, -1.2);
+            assert!((&pose * p.as_view() - (na_pose * na::Point2::from(p)).coords).norm() < 1e-9);
+            let identity = &pose * &pose.inverse();
+            assert!(identity.log().norm() < 1e-9);
+        }
+        // small angles
+        let xi = na::dvector![1e-8, 1.0, 2.0];
+        assert!((SE2::exp(xi.as_view()).log() - &xi).norm() < 1e-12);
+    }
+
+    #[test]
+    fn se2_manifold_wraps_angle() {
+        let x = na::dvector![PI - 0.1, 1.0, 2.0];
+        let delta = na::dvector![0.2, 0.0, 0.0];
+        let y = SE2Manifold.plus(x.as_view(), delta.as_view());
+        assert!((y[0] - (-PI + 0.1)).abs() < 1e-12);
+        assert!((y.rows(1, 2) - x.rows(1, 2)).norm() < 1e-12);
+        let minus = SE2Manifold.minus(y.as_view(), x.as_view());
+        assert!((minus - delta).norm() < 1e-12);
+    }
+
+    fn random_tangent(size: usize) -> na::DVector<f64> {
+        let mut xi = na::DVector::from_fn(size, |_, _| rand::random::<f64>() * 2.0 - 1.0);
+        if size < 3 {
+            return xi;
+        }
+        // keep the rotation angle below pi
+        let theta = xi.rows(0, 3).norm();
+        if theta > 0.0 {
+            let angle = rand::random::<f64>() * (PI - 0.1);
+            xi.rows_mut(0, 3).scale_mut(angle / theta);
+        }
+        xi
+    }
+
+    /// Checks `exp(xi + d) = exp(xi) * exp(right_jacobian * d)` with central
+    /// differences of `log(exp(xi)^-1 * exp(xi + d))`.
+    fn check_right_jacobian(
+        xi: &na::DVector<f64>,
+        right_jacobian: na::DMatrix<f64>,
+        minus_exp: impl Fn(&na::DVector<f64>, &na::DVector<f64>) -> na::DVector<f64>,
+    ) {
+        let h = 1e-6;
+        for j in 0..xi.len() {
+            let mut d = na::DVector::zeros(xi.len());
+            d[j] = h;
+            let column = (minus_exp(&(xi + &d), xi) - minus_exp(&(xi - &d), xi)) / (2.0 * h);
+            assert!((column - right_jacobian.column(j)).norm() < 1e-6);
+        }
+    }
+
+    fn check_manifold<M: Manifold>(manifold: &M, x: &na::DVector<f64>) {
+        let size = manifold.tangent_size().get();
+        assert_eq!(x.len(), manifold.ambient_size().get());
+        let delta = random_tangent(size) * 0.5;
+        let y = manifold.plus(x.as_view(), delta.as_view());
+        let minus = manifold.minus(y.as_view(), x.as_view());
+        assert!((minus - delta).norm() < 1e-9);
+        let zero = manifold.plus(x.as_view(), na::DVector::zeros(size).as_view());
+        assert!((zero - x).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_so2() {
+        for _ in 0..100 {
+            let theta = (rand::random::<f64>() * 2.0 - 1.0) * PI;
+            let r = SO2::exp(na::dvector![theta].as_view());
+            assert!((r.log()[0] - theta).abs() < 1e-12);
+            let na_r = na::Rotation2::new(theta);
+            assert!((r.rotation_matrix() - na_r.matrix()).norm() < 1e-12);
+            assert!((SO2::hat(theta).exp() - na_r.matrix()).norm() < 1e-9);
+            assert!((&r * &r.inverse()).log().norm() < 1e-12);
+            check_manifold(&SO2Manifold, &r.to_dvec());
+        }
+        let x = na::dvector![PI - 0.1];
+        let y = SO2Manifold.plus(x.as_view(), na::dvector![0.2].as_view());
+        assert!((y[0] - (-PI + 0.1)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_sim3() {
+        for _ in 0..200 {
+            let xi = random_tangent(7);
+            let x = Sim3::exp(xi.as_view());
+            assert!((x.log() - &xi).norm() < 1e-9);
+            assert!((Sim3::hat(xi.as_view()).exp() - x.to_matrix()).norm() < 1e-9);
+
+            let p = na::Vector3::new(0.3, -1.2, 2.0);
+            let p_h = x.to_matrix() * p.push(1.0);
+            assert!((&x * p.as_view() - p_h.xyz()).norm() < 1e-9);
+            assert!((&x * &x.inverse()).log().norm() < 1e-9);
+
+            // x * exp(eta) * x^-1 = exp(Adj(x) * eta)
+            let eta = random_tangent(7);
+            let conjugated = (&(&x * &Sim3::exp(eta.as_view())) * &x.inverse()).log();
+            assert!((conjugated - x.adjoint() * &eta).norm() < 1e-9);
+
+            let jr = Sim3::right_jacobian(xi.as_view());
+            let jl = Sim3::left_jacobian(xi.as_view());
+            assert!((jl - x.adjoint() * jr).norm() < 1e-9);
+            check_right_jacobian(
+                &xi,
+                na::DMatrix::from_column_slice(7, 7, jr.as_slice()),
+                |a, b| (Sim3::exp(b.as_view()).inverse() * Sim3::exp(a.as_view())).log(),
+            );
+            check_manifold(&Sim3Manifold, &x.to_dvec());
+        }
+        // small rotation and scale
+        let xi = na::dvector![1e-12, 0.0, 0.0, 1.0, 2.0, 3.0, 1e-12];
+        assert!((Sim3::exp(xi.as_view()).log() - &xi).norm() < 1e-12);
+        let xi = na::dvector![0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.5];
+        assert!((Sim3::exp(xi.as_view()).log() - &xi).norm() < 1e-12);
+        // large rotation, with the closed-form inverse of W
+        let xi = na::dvector![0.0, 3.0, 0.0, 1.0, 2.0, 3.0, -0.7];
+        assert!((Sim3::exp(xi.as_view()).log() - &xi).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_se23() {
+        for _ in 0..200 {
+            let xi = random_tangent(9);
+            let x = SE23::exp(xi.as_view());
+            assert!((x.log() - &xi).norm() < 1e-9);
+            assert!((SE23::hat(xi.as_view()).exp() - x.to_matrix()).norm() < 1e-9);
+            assert!((&x * &x.inverse()).log().norm() < 1e-9);
+
+            let eta = random_tangent(9);
+            let conjugated = (&(&x * &SE23::exp(eta.as_view())) * &x.inverse()).log();
+            assert!((conjugated - x.adjoint() * &eta).norm() < 1e-9);
+
+            let jr = SE23::right_jacobian(xi.as_view());
+            let jl = SE23::left_jacobian(xi.as_view());
+            assert!((jl - x.adjoint() * jr).norm() < 1e-9);
+            check_right_jacobian(
+                &xi,
+                na::DMatrix::from_column_slice(9, 9, jr.as_slice()),
+                |a, b| (SE23::exp(b.as_view()).inverse() * SE23::exp(a.as_view())).log(),
+            );
+            check_manifold(&SE23Manifold, &x.to_dvec());
+        }
+        let xi = na::dvector![1e-9, -1e-9, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert!((SE23::exp(xi.as_view()).log() - &xi).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_sphere() {
+        for size in [2, 3, 4] {
+            let manifold = SphereManifold::new(size);
+            for _ in 0..100 {
+                let x = na::DVector::<f64>::new_random(size).add_scalar(-0.5) * 4.0;
+                check_manifold(&manifold, &x);
+                let delta = random_tangent(size - 1);
+                let y = manifold.plus(x.as_view(), delta.as_view());
+                assert!((y.norm() - x.norm()).abs() < 1e-12);
+                // the geodesic distance is the norm of the step
+                let angle = (x.dot(&y) / (x.norm() * y.norm())).clamp(-1.0, 1.0).acos();
+                assert!((angle - delta.norm()).abs() < 1e-9);
+            }
+        }
+        // the Householder reflection degenerates on the last axis
+        let manifold = SphereManifold::new(3);
+        for x in [na::dvector![0.0, 0.0, 1.0], na::dvector![0.0, 0.0, -2.0]] {
+            check_manifold(&manifold, &x);
+        }
+    }
+
+    #[test]
+    fn test_line() {
+        for _ in 0..100 {
+            let d = na::Vector3::<f64>::new_random()
+                .add_scalar(-0.5)
+                .normalize();
+            let o = na::Vector3::<f64>::new_random().add_scalar(-0.5) * 10.0;
+            let o = o - d * d.dot(&o);
+            let m = o.cross(&d);
+            let x = na::dvector![d[0], d[1], d[2], m[0], m[1], m[2]];
+            check_manifold(&LineManifold, &x);
+
+            let delta = random_tangent(4);
+            let y = LineManifold.plus(x.as_view(), delta.as_view());
+            let (d_y, m_y) = (y.fixed_rows::<3>(0), y.fixed_rows::<3>(3));
+            assert!((d_y.norm() - 1.0).abs() < 1e-12);
+            assert!(d_y.dot(&m_y).abs() < 1e-9);
+            // moving only the origin keeps the direction
+            let delta = na::dvector![0.0, 0.0, 0.3, -0.2];
+            let y = LineManifold.plus(x.as_view(), delta.as_view());
+            assert!((y.fixed_rows::<3>(0) - d).norm() < 1e-12);
+            let o_y = d.cross(&y.fixed_rows::<3>(3));
+            assert!(((o_y - o).norm() - delta.norm()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_product() {
+        let manifold = ProductManifold::new(vec![
+            Arc::new(QuaternionManifold),
+            Arc::new(EuclideanManifold::new(3)),
+            Arc::new(SphereManifold::new(3)),
+        ]);
+        assert_eq!(manifold.ambient_size().get(), 10);
+        assert_eq!(manifold.tangent_size().get(), 8);
+        for _ in 0..100 {
+            let q = SO3::exp(random_tangent(3).as_view()).to_dvec();
+            let t = random_tangent(3);
+            let n = random_tangent(3).normalize();
+            let x =
+                na::DVector::from_iterator(10, q.iter().chain(t.iter()).chain(n.iter()).cloned());
+            check_manifold(&manifold, &x);
+
+            let delta = random_tangent(8) * 0.5;
+            let y = manifold.plus(x.as_view(), delta.as_view());
+            let q_y = QuaternionManifold.plus(q.as_view(), delta.rows(0, 3));
+            let n_y = SphereManifold::new(3).plus(n.as_view(), delta.rows(6, 2));
+            assert!((y.rows(0, 4) - q_y).norm() < 1e-12);
+            assert!((y.rows(4, 3) - (&t + delta.rows(3, 3))).norm() < 1e-12);
+            assert!((y.rows(7, 3) - n_y).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn plus_jacobian() {
+        // tangent sizes 3, 6 and 21, with static and dynamic dual numbers
+        let small: Arc<dyn Manifold + Sync + Send> = Arc::new(QuaternionManifold);
+        let medium: Arc<dyn Manifold + Sync + Send> =
+            Arc::new(ProductManifold::new(vec![small.clone(), small.clone()]));
+        let large: Arc<dyn Manifold + Sync + Send> = Arc::new(ProductManifold::new(
+            (0..7).map(|_| small.clone()).collect(),
+        ));
+        assert!(medium.as_static_dual().is_some());
+        for manifold in [small, medium, large] {
+            let x = na::DVector::from_iterator(
+                manifold.ambient_size().get(),
+                (0..manifold.tangent_size().get() / 3).flat_map(|_| {
+                    SO3::exp(random_tangent(3).as_view())
+                        .to_dvec()
+                        .data
+                        .as_vec()
+                        .clone()
+                }),
+            );
+            let mut param = ParameterBlock::from_vec(x.clone());
+            param.set_manifold(manifold.clone());
+            let jacobian = param.plus_jacobian();
+
+            let tangent_size = manifold.tangent_size().get();
+            let eps = 1e-6;
+            for c in 0..tangent_size {
+                let mut delta = na::DVector::zeros(tangent_size);
+                delta[c] = eps;
+                let forward = manifold.plus_f64(x.as_view(), delta.as_view());
+                delta[c] = -eps;
+                let backward = manifold.plus_f64(x.as_view(), delta.as_view());
+                let numeric = (forward - backward) / (2.0 * eps);
+                assert!((jacobian.column(c) - numeric).norm() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn optimize_on_sphere_and_product() {
+        let target = na::dvector![1.0, 2.0, -2.0] / 3.0;
+        let q = SO3::exp(na::dvector![0.1, -0.2, 0.3].as_view()).to_dvec();
+        let pose = na::DVector::from_iterator(7, q.iter().chain([1.0, 2.0, 3.0].iter()).cloned());
+
+        let mut problem = Problem::new();
+        problem
+            .add_residual_block(3, &["n"], Box::new(PriorFactor { v: target.clone() }), None)
+            .unwrap();
+        problem
+            .add_residual_block(
+                7,
+                &["pose"],
+                Box::new(PriorFactor { v: pose.clone() }),
+                None,
+            )
+            .unwrap();
+        problem.set_variable_manifold("n", Arc::new(SphereManifold::new(3)));
+        problem.set_variable_manifold(
+            "pose",
+            Arc::new(ProductManifold::new(vec![
+                Arc::new(QuaternionManifold),
+                Arc::new(EuclideanManifold::new(3)),
+            ])),
+        );
+        let initial_values = HashMap::from([
+            ("n".to_string(), na::dvector![0.0, 0.0, 1.0]),
+            (
+                "pose".to_string(),
+                na::dvector![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            ),
+        ]);
+        let result = LevenbergMarquardtOptimizer::default()
+            .optimize(&problem, &initial_values, None)
+            .unwrap();
+        assert!((result["n"].norm() - 1.0).abs() < 1e-12);
+        assert!((&result["n"] - &target).norm() < 1e-4);
+        assert!((&result["pose"] - &pose).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_subset() {
+        let manifold = SubsetManifold::new(Arc::new(SE3Manifold), &[2, 5]);
+        assert_eq!(manifold.ambient_size().get(), 7);
+        assert_eq!(manifold.tangent_size().get(), 4);
+        for _ in 0..100 {
+            let q = SO3::exp(random_tangent(3).as_view()).to_dvec();
+            let x =
+                na::DVector::from_iterator(7, q.iter().chain(random_tangent(3).iter()).cloned());
+            check_manifold(&manifold, &x);
+
+            let delta = random_tangent(4) * 0.5;
+            let y = manifold.plus(x.as_view(), delta.as_view());
+            let delta_full = SE3Manifold.minus(y.as_view(), x.as_view());
+            let expected = na::dvector![delta[0], delta[1], 0.0, delta[2], delta[3], 0.0];
+            assert!((delta_full - expected).norm() < 1e-9);
+        }
+    }
+
+    fn seed_dual(xi: &na::DVector<f64>) -> na::DVector<num_dual::DualDVec64> {
+        let n = xi.len();
+        na::DVector::from_fn(n, |i, _| {
+            let mut eps = na::DVector::zeros(n);
+            eps[i] = 1.0;
+            num_dual::DualDVec64::new(xi[i], num_dual::Derivative::some(eps))
+        })
+    }
+
+    fn dual_jacobian(values: &na::DVector<num_dual::DualDVec64>, cols: usize) -> na::DMatrix<f64> {
+        na::DMatrix::from_fn(values.len(), cols, |r, c| {
+            values[r]
+                .eps
+                .clone()
+                .unwrap_generic(na::Dyn(cols), na::Const::<1>)[c]
+        })
+    }
+
+    #[test]
+    fn test_so3_jacobians() {
+        for _ in 0..200 {
+            let omega = random_tangent(3);
+            let omega3 = na::Vector3::from_column_slice(omega.as_slice());
+            let r = SO3::exp(omega.as_view());
+            let jr = SO3::right_jacobian(omega3.as_view());
+            let jl = SO3::left_jacobian(omega3.as_view());
+            assert!((jl - r.adjoint() * jr).norm() < 1e-9);
+            assert!((SO3::left_jacobian_inverse(omega3.as_view()) * jl).is_identity(1e-9));
+            assert!((SO3::right_jacobian_inverse(omega3.as_view()) * jr).is_identity(1e-9));
+            check_right_jacobian(
+                &omega,
+                na::DMatrix::from_column_slice(3, 3, jr.as_slice()),
+                |a, b| (SO3::exp(b.as_view()).inverse() * SO3::exp(a.as_view())).log(),
+            );
+
+            // r * p against automatic derivatives of r * exp(omega) * p
+            let p = na::Vector3::new(0.3, -1.2, 2.0);
+            let (j_rot, j_p) = r.action_jacobians(p.as_view());
+            let omega_dual = seed_dual(&na::DVector::zeros(3));
+            let r_dual: SO3<num_dual::DualDVec64> = r.cast();
+            let rotated = &(r_dual * SO3::exp(omega_dual.as_view())) * p.cast().as_view();
+            let rotated = na::DVector::from_column_slice(rotated.as_slice());
+            assert!((dual_jacobian(&rotated, 3) - j_rot).norm() < 1e-9);
+            assert!((j_p - r.to_rotation_matrix()).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_se3() {
+        for _ in 0..200 {
+            let xi = random_tangent(6);
+            let x = SE3::exp(xi.as_view());
+            assert!((x.log() - &xi).norm() < 1e-9);
+            assert!((SE3::hat(xi.as_view()).exp() - x.to_matrix()).norm() < 1e-9);
+            assert!((&x * &x.inverse()).log().norm() < 1e-9);
+
+            let eta = random_tangent(6);
+            let conjugated = (&(&x * &SE3::exp(eta.as_view())) * &x.inverse()).log();
+            assert!((conjugated - x.adjoint() * &eta).norm() < 1e-9);
+
+            let jr = SE3::right_jacobian(xi.as_view());
+            let jl = SE3::left_jacobian(xi.as_view());
+            assert!((jl - x.adjoint() * jr).norm() < 1e-9);
+            assert!((SE3::left_jacobian_inverse(xi.as_view()) * jl).is_identity(1e-9));
+            assert!((SE3::right_jacobian_inverse(xi.as_view()) * jr).is_identity(1e-9));
+            check_right_jacobian(
+                &xi,
+                na::DMatrix::from_column_slice(6, 6, jr.as_slice()),
+                |a, b| (SE3::exp(b.as_view()).inverse() * SE3::exp(a.as_view())).log(),
+            );
+
+            let p = na::Vector3::new(0.3, -1.2, 2.0);
+            let (j_xi, j_p) = x.action_jacobians(p.as_view());
+            let xi_dual = seed_dual(&na::DVector::zeros(6));
+            let x_dual: SE3<num_dual::DualDVec64> = x.cast();
+            let moved = (x_dual * SE3::exp(xi_dual.as_view())) * p.cast().as_view();
+            let moved = na::DVector::from_column_slice(moved.as_slice());
+            assert!((dual_jacobian(&moved, 6) - j_xi).norm() < 1e-9);
+            assert!((j_p - x.rot.to_rotation_matrix()).norm() < 1e-12);
+        }
+        // small rotations
+        let xi = na::dvector![1e-9, -1e-9, 0.0, 1.0, 2.0, 3.0];
+        assert!((SE3::exp(xi.as_view()).log() - &xi).norm() < 1e-12);
+    }
 }
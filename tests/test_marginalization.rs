@@ -0,0 +1,215 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use nalgebra as na;
+    use tiny_solver::factors::{BetweenFactorSE2, Factor, PriorFactor};
+    use tiny_solver::manifold::se2::SE2Manifold;
+    use tiny_solver::{
+        Covariance, GaussNewtonOptimizer, Optimizer, OptimizerOptions, Problem, TinySolverError,
+    };
+
+    struct OdometryFactor {
+        d: na::DVector<f64>,
+    }
+    impl<T: na::RealField> Factor<T> for OdometryFactor {
+        fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+            &params[1] - &params[0] - self.d.clone().cast()
+        }
+    }
+
+    fn chain_problem() -> (Problem, HashMap<String, na::DVector<f64>>) {
+        let mut problem = Problem::new();
+        problem
+            .add_residual_block(
+                2,
+                &["x0"],
+                Box::new(PriorFactor {
+                    v: na::dvector![0.0, 0.0],
+                }),
+                None,
+            )
+            .unwrap();
+        let mut values = HashMap::from([("x0".to_string(), na::dvector![0.0, 0.0])]);
+        for i in 1..5 {
+            problem
+                .add_residual_block(
+                    2,
+                    &[format!("x{}", i - 1), format!("x{}", i)],
+                    Box::new(OdometryFactor {
+                        d: na::dvector![1.0, 0.1 * i as f64],
+                    }),
+                    None,
+                )
+                .unwrap();
+            values.insert(format!("x{}", i), na::dvector![i as f64, 0.0]);
+        }
+        // a loop closure keeps x0 connected to two variables
+        problem
+            .add_residual_block(
+                2,
+                &["x0", "x2"],
+                Box::new(OdometryFactor {
+                    d: na::dvector![2.2, 0.0],
+                }),
+                None,
+            )
+            .unwrap();
+        (problem, values)
+    }
+
+    #[test]
+    fn linear_marginal_keeps_the_solution() {
+        let (mut problem, mut values) = chain_problem();
+        let optimizer = GaussNewtonOptimizer::new();
+        let full = optimizer.optimize(&problem, &values, None).unwrap();
+        let mut full_covariance = Covariance::default();
+        full_covariance
+            .compute(&[("x4", "x4")], &problem, &full)
+            .unwrap();
+
+        // the linearization point does not matter for a linear problem
+        let block_id = problem.marginalize_variables(&["x0"], &values).unwrap();
+        assert!(block_id.is_some());
+        assert_eq!(problem.variable_keys().len(), 4);
+        assert!(problem.get_variable_idx(&"x0".to_string()).is_none());
+        values.remove("x0");
+
+        let reduced = optimizer.optimize(&problem, &values, None).unwrap();
+        for (key, value) in &reduced {
+            assert!((value - &full[key]).amax() < 1e-9, "{}", key);
+        }
+        let mut reduced_covariance = Covariance::default();
+        reduced_covariance
+            .compute(&[("x4", "x4")], &problem, &reduced)
+            .unwrap();
+        let difference = full_covariance.get_covariance_block("x4", "x4").unwrap()
+            - reduced_covariance.get_covariance_block("x4", "x4").unwrap();
+        assert!(difference.amax() < 1e-9);
+    }
+
+    #[test]
+    fn sliding_window_on_manifold() {
+        let mut problem = Problem::new();
+        let mut values = HashMap::new();
+        let turn = 0.3;
+        for i in 0..6 {
+            let key = format!("x{}", i);
+            problem.set_variable_manifold(key.as_str(), Arc::new(SE2Manifold));
+            if i == 0 {
+                problem
+                    .add_residual_block(
+                        3,
+                        &["x0"],
+                        Box::new(PriorFactor {
+                            v: na::dvector![0.0, 0.0, 0.0],
+                        }),
+                        None,
+                    )
+                    .unwrap();
+            } else {
+                problem
+                    .add_residual_block(
+                        3,
+                        &[format!("x{}", i - 1), key.clone()],
+                        Box::new(BetweenFactorSE2 {
+                            dx: 1.0,
+                            dy: 0.0,
+                            dtheta: turn,
+                        }),
+                        None,
+                    )
+                    .unwrap();
+            }
+            if i >= 3 {
+                problem
+                    .add_residual_block(
+                        3,
+                        &[format!("x{}", i - 3), key.clone()],
+                        Box::new(BetweenFactorSE2 {
+                            dx: 2.5,
+                            dy: 1.0,
+                            dtheta: 3.0 * turn,
+                        }),
+                        None,
+                    )
+                    .unwrap();
+            }
+            let theta = 1.1 * turn * i as f64;
+            values.insert(key, na::dvector![theta, i as f64, 0.3 * i as f64]);
+        }
+        let optimizer = GaussNewtonOptimizer::new();
+        let options = OptimizerOptions {
+            min_abs_error_decrease_threshold: 0.0,
+            min_rel_error_decrease_threshold: 0.0,
+            max_iteration: 20,
+            ..Default::default()
+        };
+        let full = optimizer
+            .optimize(&problem, &values, Some(options.clone()))
+            .unwrap();
+
+        // marginalize the two oldest poses at the optimum
+        problem.marginalize_variables(&["x0", "x1"], &full).unwrap();
+        assert_eq!(problem.variable_keys().len(), 4);
+        assert_eq!(problem.variable_manifold.len(), 4);
+        let mut window = full.clone();
+        window.remove("x0");
+        window.remove("x1");
+        let initial: HashMap<String, na::DVector<f64>> = window
+            .iter()
+            .map(|(key, value)| (key.clone(), value + na::dvector![0.05, -0.1, 0.1]))
+            .collect();
+        let reduced = optimizer
+            .optimize(&problem, &initial, Some(options))
+            .unwrap();
+        for (key, value) in &reduced {
+            assert!((value - &full[key]).amax() < 1e-6, "{}", key);
+        }
+    }
+
+    #[test]
+    fn marginalize_errors() {
+        let (mut problem, mut values) = chain_problem();
+        values.remove("x2");
+        let num_blocks = problem.total_residual_dimension;
+        assert!(matches!(
+            problem.marginalize_variables(&["x0"], &values),
+            Err(TinySolverError::MissingVariable { .. })
+        ));
+        assert_eq!(problem.total_residual_dimension, num_blocks);
+        assert_eq!(problem.variable_keys().len(), 5);
+
+        // a typo in one key leaves the whole set in the problem
+        let (mut problem, values) = chain_problem();
+        assert!(matches!(
+            problem.marginalize_variables(&["x0", "x_1"], &values),
+            Err(TinySolverError::UnknownVariable { .. })
+        ));
+        assert_eq!(problem.total_residual_dimension, num_blocks);
+        assert_eq!(problem.variable_keys().len(), 5);
+
+        // the only variable of its blocks leaves nothing behind
+        let mut problem = Problem::new();
+        problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(PriorFactor {
+                    v: na::dvector![1.0],
+                }),
+                None,
+            )
+            .unwrap();
+        let values = HashMap::from([("x".to_string(), na::dvector![0.0])]);
+        assert!(
+            problem
+                .marginalize_variables(&["x"], &values)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(problem.total_residual_dimension, 0);
+        assert!(problem.variable_keys().is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use nalgebra as na;
+    use tiny_solver::factors::PriorFactor;
+    use tiny_solver::manifold::se3::SE3Manifold;
+    use tiny_solver::numeric_diff::*;
+    use tiny_solver::parameter_block::ParameterBlock;
+    use tiny_solver::{LevenbergMarquardtOptimizer, Optimizer, ResidualBlock};
+
+    fn residual_func(params: &[na::DVector<f64>]) -> na::DVector<f64> {
+        let x = &params[0];
+        let y = &params[1];
+        na::dvector![x[0].exp() * y[0].sin(), x[0] * y[0] * y[0]]
+    }
+
+    #[test]
+    fn numeric_diff_methods() {
+        let (x, y) = (0.5_f64, 1.2_f64);
+        let expected = na::dmatrix![
+            x.exp() * y.sin(), x.exp() * y.cos();
+            y * y, 2.0 * x * y
+        ];
+        let x_block = ParameterBlock::from_vec(na::dvector![x]);
+        let y_block = ParameterBlock::from_vec(na::dvector![y]);
+        for (method, tolerance) in [
+            (NumericDiffMethod::Forward, 1e-5),
+            (NumericDiffMethod::Central, 1e-8),
+            (NumericDiffMethod::Ridders, 1e-11),
+        ] {
+            let factor = NumericDiffFactor::with_options(
+                residual_func,
+                NumericDiffOptions {
+                    method,
+                    ..Default::default()
+                },
+            );
+            let (residual, jacobian) = factor.residual_and_jacobian(&[&x_block, &y_block]);
+            assert_eq!(residual, residual_func(&[na::dvector![x], na::dvector![y]]));
+            let error = (jacobian - &expected).amax();
+            assert!(error < tolerance, "{:?} {}", method, error);
+        }
+    }
+
+    #[test]
+    fn numeric_diff_on_manifold() {
+        let v = na::dvector![0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 3.0];
+        let q = na::UnitQuaternion::from_scaled_axis(na::Vector3::new(0.2, -0.4, 0.6));
+        let mut pose = ParameterBlock::from_vec(na::dvector![q.i, q.j, q.k, q.w, 0.5, 0.1, -0.2]);
+        pose.set_manifold(Arc::new(SE3Manifold));
+
+        let prior = v.clone();
+        let numeric = ResidualBlock::new(
+            0,
+            7,
+            0,
+            &[0],
+            Box::new(NumericDiffFactor::new(
+                move |params: &[na::DVector<f64>]| &params[0] - &prior,
+            )),
+            None,
+        );
+        let autodiff = ResidualBlock::new(0, 7, 0, &[0], Box::new(PriorFactor { v }), None);
+        let (residual, jacobian) = numeric.residual_and_jacobian(&[&pose]);
+        let (expected_residual, expected_jacobian) = autodiff.residual_and_jacobian(&[&pose]);
+        assert_eq!(jacobian.shape(), (7, 6));
+        assert!((residual - expected_residual).norm() < 1e-12);
+        assert!((jacobian - expected_jacobian).amax() < 1e-8);
+    }
+
+    #[test]
+    fn optimize_numeric_diff_factor() {
+        let mut problem = tiny_solver::Problem::new();
+        problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(NumericDiffFactor::new(|params: &[na::DVector<f64>]| {
+                    na::dvector![params[0][0].exp() - 2.0]
+                })),
+                None,
+            )
+            .unwrap();
+        let initial_values = HashMap::from([("x".to_string(), na::dvector![0.0])]);
+        let result = LevenbergMarquardtOptimizer::default()
+            .optimize(&problem, &initial_values, None)
+            .unwrap();
+        assert!((result["x"][0] - 2.0_f64.ln()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,330 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use nalgebra as na;
+    use tiny_solver::factors::{Factor, PriorFactor};
+    use tiny_solver::linear::PreconditionerType;
+    use tiny_solver::loss_functions::CauchyLoss;
+    use tiny_solver::sparse::LinearSolverType;
+    use tiny_solver::{
+        DoglegOptimizer, DoglegType, GaussNewtonOptimizer, LevenbergMarquardtOptimizer, Optimizer,
+        Problem, SolverContext, SolverStatus, Symbol,
+    };
+
+    struct CustomFactor;
+    impl<T: na::RealField> Factor<T> for CustomFactor {
+        fn residual_func(&self, params: &[na::DVector<T>]) -> na::DVector<T> {
+            let x = &params[0][0];
+            let y = &params[1][0];
+            let z = &params[1][1];
+
+            na::dvector![
+                x.clone()
+                    + y.clone() * T::from_f64(2.0).unwrap()
+                    + z.clone() * T::from_f64(4.0).unwrap(),
+                y.clone() * z.clone()
+            ]
+        }
+    }
+
+    fn small_problem() -> (Problem, HashMap<String, na::DVector<f64>>) {
+        let mut problem = Problem::new();
+        problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
+        problem
+            .add_residual_block(2, &["x", "yz"], Box::new(CustomFactor), None)
+            .unwrap();
+        let initial_values = HashMap::<String, na::DVector<f64>>::from([
+            ("x".to_string(), na::dvector![0.7]),
+            ("yz".to_string(), na::dvector![-30.2, 123.4]),
+        ]);
+        (problem, initial_values)
+    }
+
+    fn check_solution(result: &HashMap<String, na::DVector<f64>>) {
+        assert!((result["x"][0] - 3.0).abs() < 1e-3);
+        let yz = &result["yz"];
+        assert!((3.0 + 2.0 * yz[0] + 4.0 * yz[1]).abs() < 1e-3);
+        assert!((yz[0] * yz[1]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn gauss_newton_summary() {
+        let (problem, initial_values) = small_problem();
+        let optimizer = GaussNewtonOptimizer::new();
+        let (result, summary) = optimizer.optimize_with_summary(&problem, &initial_values, None);
+        check_solution(&result.unwrap());
+
+        assert!(summary.is_solution_usable());
+        assert_ne!(summary.status, SolverStatus::Running);
+        assert!(summary.final_cost < summary.initial_cost);
+        assert_eq!(summary.num_successful_steps, summary.iterations.len());
+        assert_eq!(summary.num_unsuccessful_steps, 0);
+        assert_eq!(summary.final_cost, summary.iterations.last().unwrap().cost);
+    }
+
+    #[test]
+    fn levenberg_marquardt_summary() {
+        let (problem, initial_values) = small_problem();
+        let optimizer = LevenbergMarquardtOptimizer::default();
+        let (result, summary) = optimizer.optimize_with_summary(&problem, &initial_values, None);
+        check_solution(&result.unwrap());
+
+        assert!(summary.is_solution_usable());
+        assert!(summary.final_cost < summary.initial_cost);
+        assert_eq!(
+            summary.num_successful_steps + summary.num_unsuccessful_steps,
+            summary.iterations.len()
+        );
+        assert!(summary.iterations.iter().all(|it| it.damping > 0.0));
+        assert!(summary.total_time >= summary.linear_solver_time);
+    }
+
+    #[test]
+    fn max_iteration_status() {
+        let (problem, initial_values) = small_problem();
+        let optimizer = LevenbergMarquardtOptimizer::default();
+        let options = tiny_solver::OptimizerOptions {
+            max_iteration: 1,
+            ..Default::default()
+        };
+        let (_, summary) =
+            optimizer.optimize_with_summary(&problem, &initial_values, Some(options));
+        assert_eq!(summary.iterations.len(), 1);
+        assert_eq!(summary.status, SolverStatus::HitMaxIterations);
+    }
+
+    #[test]
+    fn dogleg() {
+        for dogleg_type in [DoglegType::Traditional, DoglegType::Subspace] {
+            let (problem, initial_values) = small_problem();
+            let optimizer = DoglegOptimizer::with_type(dogleg_type);
+            let (result, summary) =
+                optimizer.optimize_with_summary(&problem, &initial_values, None);
+            check_solution(&result.unwrap());
+            assert!(summary.is_solution_usable());
+            assert!(summary.final_cost < summary.initial_cost);
+        }
+    }
+
+    #[test]
+    fn dogleg_small_trust_region() {
+        // a tiny initial radius forces steps on the trust region boundary
+        for dogleg_type in [DoglegType::Traditional, DoglegType::Subspace] {
+            let (problem, initial_values) = small_problem();
+            let optimizer = DoglegOptimizer::new(dogleg_type, 1e-2, 1e16, 1e-32);
+            let options = tiny_solver::OptimizerOptions {
+                max_iteration: 500,
+                ..Default::default()
+            };
+            let (result, summary) =
+                optimizer.optimize_with_summary(&problem, &initial_values, Some(options));
+            check_solution(&result.unwrap());
+            let first = &summary.iterations[0];
+            assert!((first.step_norm - 1e-2).abs() < 1e-9);
+            assert!(first.step_is_successful);
+        }
+    }
+
+    #[test]
+    fn schur_linear_solvers() {
+        for linear_solver_type in [LinearSolverType::DenseSchur, LinearSolverType::SparseSchur] {
+            let (problem, initial_values) = small_problem();
+            let options = tiny_solver::OptimizerOptions {
+                linear_solver_type,
+                ..Default::default()
+            };
+            let optimizer = LevenbergMarquardtOptimizer::default();
+            let result = optimizer.optimize(&problem, &initial_values, Some(options.clone()));
+            check_solution(&result.unwrap());
+            let optimizer = GaussNewtonOptimizer::new();
+            let result = optimizer.optimize(
+                &problem,
+                &initial_values,
+                Some(tiny_solver::OptimizerOptions {
+                    elimination_ordering: Some(vec!["yz".to_string()]),
+                    ..options
+                }),
+            );
+            check_solution(&result.unwrap());
+        }
+    }
+
+    #[test]
+    fn conjugate_gradient_linear_solver() {
+        for preconditioner_type in [
+            PreconditionerType::Jacobi,
+            PreconditionerType::BlockJacobi,
+            PreconditionerType::SchurJacobi,
+        ] {
+            let (problem, initial_values) = small_problem();
+            let options = tiny_solver::OptimizerOptions {
+                linear_solver_type: LinearSolverType::ConjugateGradient(preconditioner_type),
+                elimination_ordering: Some(vec!["yz".to_string()]),
+                ..Default::default()
+            };
+            let optimizer = LevenbergMarquardtOptimizer::default();
+            let (result, summary) =
+                optimizer.optimize_with_summary(&problem, &initial_values, Some(options));
+            assert!(summary.status.is_solution_usable());
+            check_solution(&result.unwrap());
+        }
+    }
+
+    #[test]
+    fn typed_variable_keys() {
+        let x = Symbol::new('x', 0);
+        let yz = Symbol::new('y', 0);
+        let mut problem = Problem::<Symbol>::default();
+        problem
+            .add_residual_block(
+                1,
+                &[x],
+                Box::new(PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
+        problem
+            .add_residual_block(2, &[x, yz], Box::new(CustomFactor), None)
+            .unwrap();
+        let initial_values =
+            HashMap::from([(x, na::dvector![0.7]), (yz, na::dvector![-30.2, 123.4])]);
+        let options = tiny_solver::OptimizerOptions {
+            linear_solver_type: LinearSolverType::SparseSchur,
+            elimination_ordering: Some(vec![yz]),
+            ..Default::default()
+        };
+        let optimizer = LevenbergMarquardtOptimizer::default();
+        let result = optimizer
+            .optimize(&problem, &initial_values, Some(options))
+            .unwrap();
+        let result = HashMap::from([
+            ("x".to_string(), result[&x].clone()),
+            ("yz".to_string(), result[&yz].clone()),
+        ]);
+        check_solution(&result);
+    }
+
+    #[test]
+    fn levenberg_marquardt_robust_cost() {
+        let (mut problem, initial_values) = small_problem();
+        // an outlier prior on x, down-weighted by the loss
+        problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(PriorFactor {
+                    v: na::dvector![100.0],
+                }),
+                Some(Box::new(CauchyLoss::new(0.1).unwrap())),
+            )
+            .unwrap();
+        let optimizer = LevenbergMarquardtOptimizer::default();
+        let (result, summary) = optimizer.optimize_with_summary(&problem, &initial_values, None);
+        let result = result.unwrap();
+        assert!((result["x"][0] - 3.0).abs() < 1e-2);
+
+        assert_eq!(
+            summary.initial_cost,
+            problem.evaluate_cost(&initial_values).unwrap()
+        );
+        assert!(
+            (summary.final_cost - problem.evaluate_cost(&result).unwrap()).abs()
+                < 1e-9 * summary.final_cost
+        );
+        let mut last_cost = summary.initial_cost;
+        for iteration in &summary.iterations {
+            assert!(iteration.cost <= last_cost);
+            last_cost = iteration.cost;
+        }
+    }
+
+    #[test]
+    fn solver_context_reuses_the_structure() {
+        let (mut problem, initial_values) = small_problem();
+        let mut context = SolverContext::new();
+        let optimizer = LevenbergMarquardtOptimizer::default();
+        let (result, _) =
+            optimizer.optimize_with_context(&problem, &initial_values, None, &mut context);
+        check_solution(&result.unwrap());
+        assert_eq!(context.num_symbolic_analyses(), 1);
+
+        // new values, same structure, any optimizer
+        let new_values = HashMap::<String, na::DVector<f64>>::from([
+            ("x".to_string(), na::dvector![-1.5]),
+            ("yz".to_string(), na::dvector![10.0, -20.0]),
+        ]);
+        let expected = optimizer.optimize(&problem, &new_values, None).unwrap();
+        let (result, _) =
+            optimizer.optimize_with_context(&problem, &new_values, None, &mut context);
+        let result = result.unwrap();
+        for (key, value) in &expected {
+            assert!((value - &result[key]).amax() < 1e-12);
+        }
+        let (result, _) = DoglegOptimizer::default().optimize_with_context(
+            &problem,
+            &new_values,
+            None,
+            &mut context,
+        );
+        check_solution(&result.unwrap());
+        assert_eq!(context.num_symbolic_analyses(), 1);
+
+        let options = tiny_solver::OptimizerOptions {
+            linear_solver_type: LinearSolverType::SparseQR,
+            ..Default::default()
+        };
+        let (result, _) = GaussNewtonOptimizer::new().optimize_with_context(
+            &problem,
+            &new_values,
+            Some(options),
+            &mut context,
+        );
+        check_solution(&result.unwrap());
+        assert_eq!(context.num_symbolic_analyses(), 2);
+
+        // adding a residual block invalidates the cached structure
+        problem
+            .add_residual_block(
+                2,
+                &["yz"],
+                Box::new(PriorFactor {
+                    v: na::dvector![1.0, -1.0],
+                }),
+                None,
+            )
+            .unwrap();
+        let (result, _) =
+            optimizer.optimize_with_context(&problem, &new_values, None, &mut context);
+        let expected = optimizer.optimize(&problem, &new_values, None).unwrap();
+        let result = result.unwrap();
+        for (key, value) in &expected {
+            assert!((value - &result[key]).amax() < 1e-12);
+        }
+        assert_eq!(context.num_symbolic_analyses(), 3);
+
+        // and so does fixing a coordinate
+        problem.fix_variable("yz", 1);
+        let (result, _) =
+            optimizer.optimize_with_context(&problem, &new_values, None, &mut context);
+        assert_eq!(result.unwrap()["yz"][1], -20.0);
+        assert_eq!(context.num_symbolic_analyses(), 4);
+
+        context.invalidate();
+        let (result, _) =
+            optimizer.optimize_with_context(&problem, &new_values, None, &mut context);
+        assert!(result.is_ok());
+        assert_eq!(context.num_symbolic_analyses(), 5);
+    }
+}
@@ -1,9 +1,11 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::sync::Arc;
 
     use nalgebra as na;
-    use tiny_solver;
+    use tiny_solver::manifold::se3::SE3Manifold;
+    use tiny_solver::{Optimizer, TinySolverError};
 
     #[test]
     fn new_problem() {
@@ -16,25 +18,29 @@ mod tests {
     #[test]
     fn add_residual_block() {
         let mut problem = tiny_solver::Problem::new();
-        let block_id1 = problem.add_residual_block(
-            1,
-            &["x"],
-            Box::new(tiny_solver::factors::PriorFactor {
-                v: na::dvector![3.0],
-            }),
-            None,
-        );
+        let block_id1 = problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
 
         assert_eq!(problem.total_residual_dimension, 1);
 
-        let block_id2 = problem.add_residual_block(
-            1,
-            &["y"],
-            Box::new(tiny_solver::factors::PriorFactor {
-                v: na::dvector![3.0],
-            }),
-            None,
-        );
+        let block_id2 = problem
+            .add_residual_block(
+                1,
+                &["y"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
 
         assert!(block_id1 != block_id2);
         assert_eq!(problem.total_residual_dimension, 2);
@@ -43,14 +49,16 @@ mod tests {
     #[test]
     fn remove_residual_block() {
         let mut problem = tiny_solver::Problem::new();
-        let block_id = problem.add_residual_block(
-            1,
-            &["x"],
-            Box::new(tiny_solver::factors::PriorFactor {
-                v: na::dvector![3.0],
-            }),
-            None,
-        );
+        let block_id = problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
 
         assert_eq!(problem.total_residual_dimension, 1);
 
@@ -118,14 +126,16 @@ mod tests {
     #[test]
     fn compute_residual_and_jacobian() {
         let mut problem = tiny_solver::Problem::new();
-        problem.add_residual_block(
-            1,
-            &["x"],
-            Box::new(tiny_solver::factors::PriorFactor {
-                v: na::dvector![3.0],
-            }),
-            None,
-        );
+        problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
 
         struct CustomFactor {}
         impl<T: na::RealField> tiny_solver::factors::Factor<T> for CustomFactor {
@@ -144,7 +154,9 @@ mod tests {
             }
         }
 
-        problem.add_residual_block(2, &["x", "yz"], Box::new(CustomFactor {}), None);
+        problem
+            .add_residual_block(2, &["x", "yz"], Box::new(CustomFactor {}), None)
+            .unwrap();
 
         // the initial values for x is 0.7 and yz is [-30.2, 123.4]
         let initial_values = HashMap::<String, na::DVector<f64>>::from([
@@ -152,20 +164,25 @@ mod tests {
             ("yz".to_string(), na::dvector![-30.2, 123.4]),
         ]);
         let parameter_blocks = problem.initialize_parameter_blocks(&initial_values);
-        let variable_name_to_col_idx_dict =
-            problem.get_variable_name_to_col_idx_dict(&parameter_blocks);
-        let total_variable_dimension = parameter_blocks.values().map(|p| p.tangent_size()).sum();
+        let variable_idx_to_col_idx = problem.get_variable_idx_to_col_idx(&parameter_blocks);
+        let total_variable_dimension = parameter_blocks
+            .iter()
+            .flatten()
+            .map(|p| p.tangent_size())
+            .sum();
         let symbolic_structure = problem.build_symbolic_structure(
             &parameter_blocks,
             total_variable_dimension,
-            &variable_name_to_col_idx_dict,
+            &variable_idx_to_col_idx,
         );
 
-        let (residuals, jac) = problem.compute_residual_and_jacobian(
-            &parameter_blocks,
-            &variable_name_to_col_idx_dict,
-            &symbolic_structure,
-        );
+        let (residuals, jac) = problem
+            .compute_residual_and_jacobian(
+                &parameter_blocks,
+                &variable_idx_to_col_idx,
+                &symbolic_structure,
+            )
+            .unwrap();
 
         assert_eq!(residuals.nrows(), 3);
         assert_eq!(residuals.ncols(), 1);
@@ -176,14 +193,16 @@ mod tests {
     #[test]
     fn compute_residual_and_jacobian_with_fixed_variable() {
         let mut problem = tiny_solver::Problem::new();
-        problem.add_residual_block(
-            1,
-            &["x"],
-            Box::new(tiny_solver::factors::PriorFactor {
-                v: na::dvector![3.0],
-            }),
-            None,
-        );
+        problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
 
         struct CustomFactor {}
         impl<T: na::RealField> tiny_solver::factors::Factor<T> for CustomFactor {
@@ -201,7 +220,9 @@ mod tests {
             }
         }
 
-        problem.add_residual_block(2, &["x", "yz"], Box::new(CustomFactor {}), None);
+        problem
+            .add_residual_block(2, &["x", "yz"], Box::new(CustomFactor {}), None)
+            .unwrap();
         problem.fix_variable("x", 0);
 
         // the initial values for x is 0.7 and yz is [-30.2, 123.4]
@@ -210,10 +231,10 @@ mod tests {
             ("yz".to_string(), na::dvector![-30.2, 123.4]),
         ]);
         let parameter_blocks = problem.initialize_parameter_blocks(&initial_values);
-        let variable_name_to_col_idx_dict =
-            problem.get_variable_name_to_col_idx_dict(&parameter_blocks);
+        let variable_idx_to_col_idx = problem.get_variable_idx_to_col_idx(&parameter_blocks);
         let total_variable_dimension = parameter_blocks
-            .values()
+            .iter()
+            .flatten()
             .map(|p| {
                 if p.manifold.is_some() {
                     p.tangent_size()
@@ -225,18 +246,466 @@ mod tests {
         let symbolic_structure = problem.build_symbolic_structure(
             &parameter_blocks,
             total_variable_dimension,
-            &variable_name_to_col_idx_dict,
+            &variable_idx_to_col_idx,
         );
 
-        let (residuals, jac) = problem.compute_residual_and_jacobian(
-            &parameter_blocks,
-            &variable_name_to_col_idx_dict,
-            &symbolic_structure,
-        );
+        let (residuals, jac) = problem
+            .compute_residual_and_jacobian(
+                &parameter_blocks,
+                &variable_idx_to_col_idx,
+                &symbolic_structure,
+            )
+            .unwrap();
 
         assert_eq!(residuals.nrows(), 3);
         assert_eq!(residuals.ncols(), 1);
         assert_eq!(jac.nrows(), 3);
         assert_eq!(jac.ncols(), 2); // x is fixed, so 3 - 1 = 2
     }
+
+    #[test]
+    fn independent_variable_set() {
+        let mut problem = tiny_solver::Problem::new();
+        struct SumFactor {}
+        impl<T: na::RealField> tiny_solver::factors::Factor<T> for SumFactor {
+            fn residual_func(&self, params: &[nalgebra::DVector<T>]) -> nalgebra::DVector<T> {
+                na::dvector![params[0][0].clone() + params[1][0].clone()]
+            }
+        }
+        // cameras c0, c1 observe points p0, p1, p2
+        for (c, p) in [("c0", "p0"), ("c0", "p1"), ("c1", "p1"), ("c1", "p2")] {
+            problem
+                .add_residual_block(1, &[c, p], Box::new(SumFactor {}), None)
+                .unwrap();
+        }
+        let initial_values = HashMap::<String, na::DVector<f64>>::from([
+            ("c0".to_string(), na::dvector![0.0, 0.0, 0.0]),
+            ("c1".to_string(), na::dvector![0.0, 0.0, 0.0]),
+            ("p0".to_string(), na::dvector![0.0]),
+            ("p1".to_string(), na::dvector![0.0]),
+            ("p2".to_string(), na::dvector![0.0]),
+        ]);
+        let parameter_blocks = problem.initialize_parameter_blocks(&initial_values);
+        let independent_set = problem.independent_variable_set(&parameter_blocks);
+        assert_eq!(independent_set, vec!["p0", "p1", "p2"]);
+    }
+
+    #[test]
+    fn interned_variable_keys() {
+        let mut problem = tiny_solver::Problem::<tiny_solver::Symbol>::default();
+        let x0 = tiny_solver::Symbol::new('x', 0);
+        let x1 = tiny_solver::Symbol::new('x', 1);
+        for keys in [[x1].as_slice(), &[x1, x0], &[x0]] {
+            problem
+                .add_residual_block(
+                    1,
+                    keys,
+                    Box::new(tiny_solver::factors::PriorFactor {
+                        v: na::dvector![0.0],
+                    }),
+                    None,
+                )
+                .unwrap();
+        }
+        assert_eq!(problem.variable_keys(), &[x1, x0]);
+        assert_eq!(problem.get_variable_idx(&x0), Some(1));
+        assert_eq!(
+            problem.get_variable_idx(&tiny_solver::Symbol::new('l', 0)),
+            None
+        );
+        assert_eq!(x1.to_string(), "x1");
+        assert_eq!(format!("{:?}", x1), "x1");
+        // errors name the key as x1
+        let error = problem
+            .add_residual_block(
+                2,
+                &[x1, x1],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![0.0, 0.0],
+                }),
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            tiny_solver::TinySolverError::InvalidResidualBlock(
+                "variable x1 is used twice".to_string()
+            )
+            .to_string()
+        );
+
+        problem.fix_variable(x0, 0);
+        let initial_values = HashMap::from([
+            (x0, na::dvector![1.0]),
+            (x1, na::dvector![2.0]),
+            (tiny_solver::Symbol::new('l', 0), na::dvector![3.0]),
+        ]);
+        let parameter_blocks = problem.initialize_parameter_blocks(&initial_values);
+        assert_eq!(parameter_blocks.len(), 2);
+        assert_eq!(parameter_blocks[0].as_ref().unwrap().params[0], 2.0);
+        assert!(
+            parameter_blocks[1]
+                .as_ref()
+                .unwrap()
+                .fixed_variables
+                .contains(&0)
+        );
+        // x0 is fixed and has no columns
+        assert_eq!(
+            problem.get_variable_idx_to_col_idx(&parameter_blocks),
+            vec![0, 1]
+        );
+        // values of variables outside of the problem are passed through
+        let values = problem.parameter_blocks_to_values(&parameter_blocks, &initial_values);
+        assert_eq!(values, initial_values);
+    }
+
+    #[test]
+    fn invalid_residual_blocks() {
+        let mut problem = tiny_solver::Problem::new();
+        let prior = || {
+            Box::new(tiny_solver::factors::PriorFactor {
+                v: na::dvector![3.0],
+            })
+        };
+        let keys: [&str; 0] = [];
+        assert!(matches!(
+            problem.add_residual_block(1, &keys, prior(), None),
+            Err(TinySolverError::InvalidResidualBlock(_))
+        ));
+        assert!(matches!(
+            problem.add_residual_block(0, &["x"], prior(), None),
+            Err(TinySolverError::InvalidResidualBlock(_))
+        ));
+        assert!(matches!(
+            problem.add_residual_block(1, &["x", "x"], prior(), None),
+            Err(TinySolverError::InvalidResidualBlock(_))
+        ));
+        assert_eq!(problem.total_residual_dimension, 0);
+    }
+
+    #[test]
+    fn optimize_errors() {
+        let optimizer = tiny_solver::GaussNewtonOptimizer::new();
+        let mut problem = tiny_solver::Problem::new();
+        // the prior is 1 dimensional
+        problem
+            .add_residual_block(
+                2,
+                &["x"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
+        let initial_values = HashMap::from([("x".to_string(), na::dvector![0.7])]);
+        match optimizer.optimize(&problem, &initial_values, None) {
+            Err(TinySolverError::InvalidProblem(errors)) => assert!(matches!(
+                errors[..],
+                [TinySolverError::DimensionMismatch {
+                    residual_block_id: 0,
+                    expected: 2,
+                    actual: 1
+                }]
+            )),
+            _ => panic!("expected an invalid problem"),
+        }
+
+        let mut problem = tiny_solver::Problem::new();
+        problem
+            .add_residual_block(
+                1,
+                &["y"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
+        let (result, summary) = optimizer.optimize_with_summary(&problem, &initial_values, None);
+        match result {
+            Err(TinySolverError::InvalidProblem(errors)) => match &errors[..] {
+                [
+                    TinySolverError::MissingVariable {
+                        key,
+                        residual_block_id,
+                    },
+                ] => {
+                    assert_eq!(key, "y");
+                    assert_eq!(*residual_block_id, 0);
+                }
+                _ => panic!("expected a missing variable error"),
+            },
+            _ => panic!("expected an invalid problem"),
+        }
+        assert_eq!(summary.status, tiny_solver::SolverStatus::InvalidProblem);
+    }
+
+    #[test]
+    fn validate() {
+        let mut problem = tiny_solver::Problem::new();
+        let prior = |v: na::DVector<f64>| Box::new(tiny_solver::factors::PriorFactor { v });
+        problem
+            .add_residual_block(1, &["x"], prior(na::dvector![1.0]), None)
+            .unwrap();
+        problem
+            .add_residual_block(1, &["y"], prior(na::dvector![1.0, 2.0]), None)
+            .unwrap();
+        problem
+            .add_residual_block(1, &["z"], prior(na::dvector![1.0]), None)
+            .unwrap();
+        problem
+            .add_residual_block(7, &["pose"], prior(na::dvector![0.0, 0.0, 0.0, 1.0]), None)
+            .unwrap();
+        problem.set_variable_manifold("pose", Arc::new(SE3Manifold));
+
+        let mut initial_values = HashMap::from([
+            ("x".to_string(), na::dvector![0.0]),
+            ("y".to_string(), na::dvector![0.0, 0.0]),
+            ("pose".to_string(), na::dvector![0.0, 0.0, 0.0, 1.0]),
+        ]);
+        match problem.validate(&initial_values) {
+            Err(TinySolverError::InvalidProblem(errors)) => {
+                assert_eq!(errors.len(), 3, "{:?}", errors);
+                assert!(matches!(
+                    errors[0],
+                    TinySolverError::VariableSizeMismatch {
+                        expected: 7,
+                        actual: 4,
+                        ..
+                    }
+                ));
+                assert!(matches!(
+                    errors[1],
+                    TinySolverError::DimensionMismatch {
+                        residual_block_id: 1,
+                        expected: 1,
+                        actual: 2
+                    }
+                ));
+                assert!(matches!(
+                    errors[2],
+                    TinySolverError::MissingVariable {
+                        residual_block_id: 2,
+                        ..
+                    }
+                ));
+            }
+            _ => panic!("expected an invalid problem"),
+        }
+
+        let mut problem = tiny_solver::Problem::new();
+        problem
+            .add_residual_block(1, &["x"], prior(na::dvector![1.0]), None)
+            .unwrap();
+        problem
+            .add_residual_block(
+                7,
+                &["pose"],
+                prior(na::dvector![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]),
+                None,
+            )
+            .unwrap();
+        problem.set_variable_manifold("pose", Arc::new(SE3Manifold));
+        initial_values.insert(
+            "pose".to_string(),
+            na::dvector![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        );
+        assert!(problem.validate(&initial_values).is_ok());
+    }
+
+    #[test]
+    fn fix_manifold_variable() {
+        // fix the z translation of an SE3 pose, a tangent index
+        let mut problem = tiny_solver::Problem::new();
+        let target = na::dvector![0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 3.0];
+        problem
+            .add_residual_block(
+                7,
+                &["pose"],
+                Box::new(tiny_solver::factors::PriorFactor { v: target }),
+                None,
+            )
+            .unwrap();
+        problem.set_variable_manifold("pose", Arc::new(SE3Manifold));
+        problem.fix_variable("pose", 5);
+        let initial_values = HashMap::<String, na::DVector<f64>>::from([(
+            "pose".to_string(),
+            na::dvector![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, -1.0],
+        )]);
+
+        let parameter_blocks = problem.initialize_parameter_blocks(&initial_values);
+        let pose = parameter_blocks[0].as_ref().unwrap();
+        assert_eq!(pose.tangent_size(), 6);
+        assert_eq!(pose.free_tangent_size(), 5);
+
+        let result = tiny_solver::LevenbergMarquardtOptimizer::default()
+            .optimize(&problem, &initial_values, None)
+            .unwrap();
+        let expected = na::dvector![0.0, 0.0, 0.0, 1.0, 1.0, 2.0, -1.0];
+        assert!((&result["pose"] - expected).norm() < 1e-4);
+
+        // 6 is the ambient index of tz, there is no such tangent index
+        problem.fix_variable("pose", 6);
+        let parameter_blocks = problem.initialize_parameter_blocks(&initial_values);
+        assert_eq!(parameter_blocks[0].as_ref().unwrap().free_tangent_size(), 5);
+        match tiny_solver::LevenbergMarquardtOptimizer::default().optimize(
+            &problem,
+            &initial_values,
+            None,
+        ) {
+            Err(TinySolverError::InvalidProblem(errors)) => {
+                assert!(matches!(
+                    &errors[..],
+                    [TinySolverError::FixedIndexOutOfRange {
+                        key,
+                        index: 6,
+                        tangent_size: 6,
+                    }] if key == "pose"
+                ));
+                assert_eq!(
+                    errors[0].to_string(),
+                    "variable pose has fixed index 6 but its tangent size is 6"
+                );
+            }
+            _ => panic!("expected an invalid problem"),
+        }
+    }
+
+    #[test]
+    fn evaluate_cost() {
+        let mut problem = tiny_solver::Problem::new();
+        problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
+        problem
+            .add_residual_block(
+                1,
+                &["y"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![0.0],
+                }),
+                Some(Box::new(
+                    tiny_solver::loss_functions::HuberLoss::new(1.0).unwrap(),
+                )),
+            )
+            .unwrap();
+        let initial_values = HashMap::<String, na::DVector<f64>>::from([
+            ("x".to_string(), na::dvector![0.7]),
+            ("y".to_string(), na::dvector![4.0]),
+        ]);
+
+        // 0.5 * 2.3^2 + 0.5 * (2 * 4 - 1) with the huber loss
+        let cost = problem.evaluate_cost(&initial_values).unwrap();
+        assert!((cost - 6.145).abs() < 1e-12);
+
+        // the corrected residuals only match the robust cost to first order
+        let parameter_blocks = problem.initialize_parameter_blocks(&initial_values);
+        assert_eq!(problem.compute_cost(&parameter_blocks).unwrap(), cost);
+        let corrected = problem.compute_residuals(&parameter_blocks, true).unwrap();
+        assert!((0.5 * corrected.as_ref().squared_norm_l2() - cost).abs() > 0.1);
+
+        let missing = HashMap::from([("x".to_string(), na::dvector![0.7])]);
+        assert!(matches!(
+            problem.evaluate_cost(&missing),
+            Err(TinySolverError::MissingVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn evaluate() {
+        struct CustomFactor;
+        impl<T: na::RealField> tiny_solver::factors::Factor<T> for CustomFactor {
+            fn residual_func(&self, params: &[nalgebra::DVector<T>]) -> nalgebra::DVector<T> {
+                let x = &params[0][0];
+                let y = &params[1][0];
+                let z = &params[1][1];
+                na::dvector![
+                    x.clone() + y.clone() * T::from_f64(2.0).unwrap(),
+                    y.clone() * z.clone()
+                ]
+            }
+        }
+        let mut problem = tiny_solver::Problem::new();
+        let prior_id = problem
+            .add_residual_block(
+                1,
+                &["x"],
+                Box::new(tiny_solver::factors::PriorFactor {
+                    v: na::dvector![3.0],
+                }),
+                None,
+            )
+            .unwrap();
+        let custom_id = problem
+            .add_residual_block(
+                2,
+                &["x", "yz"],
+                Box::new(CustomFactor),
+                Some(Box::new(
+                    tiny_solver::loss_functions::CauchyLoss::new(1.0).unwrap(),
+                )),
+            )
+            .unwrap();
+        let values = HashMap::<String, na::DVector<f64>>::from([
+            ("x".to_string(), na::dvector![0.7]),
+            ("yz".to_string(), na::dvector![-0.2, 1.4]),
+        ]);
+
+        let evaluation = problem
+            .evaluate(&values, tiny_solver::EvaluateOptions::default())
+            .unwrap();
+        assert_eq!(evaluation.cost, problem.evaluate_cost(&values).unwrap());
+        assert_eq!(evaluation.variable_columns["x"], (0, 1));
+        assert_eq!(evaluation.variable_columns["yz"], (1, 2));
+        assert_eq!(evaluation.residual_rows[&custom_id], (1, 2));
+        let residuals = evaluation.residuals.unwrap();
+        assert_eq!(residuals[&prior_id], na::dvector![-2.3]);
+
+        // the gradient of the robust cost matches finite differences
+        let gradient = evaluation.gradient.unwrap();
+        let jacobian = evaluation.jacobian.unwrap();
+        assert_eq!((jacobian.nrows(), jacobian.ncols()), (3, 3));
+        let eps = 1e-6;
+        for (key, idx, col) in [("x", 0, 0), ("yz", 0, 1), ("yz", 1, 2)] {
+            let mut plus = values.clone();
+            plus.get_mut(key).unwrap()[idx] += eps;
+            let mut minus = values.clone();
+            minus.get_mut(key).unwrap()[idx] -= eps;
+            let numeric = (problem.evaluate_cost(&plus).unwrap()
+                - problem.evaluate_cost(&minus).unwrap())
+                / (2.0 * eps);
+            assert!((gradient[col] - numeric).abs() < 1e-6);
+        }
+
+        // without the loss function everything is the plain least squares
+        let options = tiny_solver::EvaluateOptions {
+            apply_loss_function: false,
+            jacobian: false,
+            ..Default::default()
+        };
+        let evaluation = problem.evaluate(&values, options).unwrap();
+        let raw = &evaluation.residuals.unwrap()[&custom_id];
+        assert!((raw - na::dvector![0.3, -0.28]).norm() < 1e-12);
+        assert!((evaluation.cost - 0.5 * (2.3 * 2.3 + 0.3 * 0.3 + 0.28 * 0.28)).abs() < 1e-12);
+        assert!(evaluation.jacobian.is_none());
+        let gradient = evaluation.gradient.unwrap();
+        assert!((gradient[0] - (-2.3 + 0.3)).abs() < 1e-12);
+
+        // fixed tangent coordinates have no column
+        problem.fix_variable("yz", 0);
+        let evaluation = problem
+            .evaluate(&values, tiny_solver::EvaluateOptions::default())
+            .unwrap();
+        assert_eq!(evaluation.variable_columns["yz"], (1, 1));
+        assert_eq!(evaluation.jacobian.unwrap().ncols(), 2);
+    }
 }